@@ -1,15 +1,18 @@
-use crate::arp_supplier::ARPSampleSupplier;
+use crate::cli::Args;
 use crate::ieee80211::IEEE80211Monitor;
-use crate::keycracker::KeystreamSample;
-use crate::ui::keycracker::KeyCrackerSettings;
+use crate::keycracker::{KeyCrackerSampleProvider, KeyCrackerSettings, KeystreamSample};
+use crate::pcap_supplier::PcapSampleSupplier;
+use crate::plaintext;
+use crate::replay_rate::ReplayRateController;
+use crate::session::{SessionAttack, SessionTarget, SessionWriter};
+use crate::ui::attack_prep::AttackSampleSupplier;
 use crate::ui::UIScene;
-use crate::wep::{WepIV, WepKey};
+use crate::wep::{WepFrame, WepIV, WepKey};
 use crate::TERMINAL_LOCK;
 use crate::{nl80211::NL80211Connection, ui};
 
 use anyhow::Context;
 use crossterm::event::{self, Event, KeyCode};
-use hex::FromHex;
 use rand::RngCore;
 use ratatui::{
     prelude::{Alignment, Constraint, CrosstermBackend, Direction, Layout},
@@ -22,7 +25,8 @@ use std::rc::{Rc, Weak};
 use std::{
     cell::RefCell,
     error::Error,
-    sync::{atomic, Arc},
+    path::PathBuf,
+    sync::{atomic, Arc, Mutex},
     time::Duration,
 };
 
@@ -33,16 +37,30 @@ struct AppState {
 
     nl80211_con: Rc<NL80211Connection>,
     ieee80211_mon: Option<Rc<IEEE80211Monitor>>,
+    capture_path: Option<PathBuf>,
+    session_path: Option<PathBuf>,
+    keycracker_settings: KeyCrackerSettings,
+    passive: bool,
 }
 
 impl AppState {
-    fn new(nl80211_con: NL80211Connection) -> Rc<RefCell<AppState>> {
+    fn new(
+        nl80211_con: NL80211Connection,
+        capture_path: Option<PathBuf>,
+        session_path: Option<PathBuf>,
+        keycracker_settings: KeyCrackerSettings,
+        passive: bool,
+    ) -> Rc<RefCell<AppState>> {
         Rc::new_cyclic(|state_ref| {
             RefCell::new(AppState {
                 state_ref: state_ref.clone(),
                 new_scene: None,
                 nl80211_con: Rc::new(nl80211_con),
                 ieee80211_mon: None,
+                capture_path,
+                session_path,
+                keycracker_settings,
+                passive,
             })
         })
     }
@@ -60,10 +78,18 @@ impl AppState {
                 let mut state = state.borrow_mut();
 
                 //Create the 802.11 monitor
-                state.ieee80211_mon = Some(Rc::new(
+                let mut ieee80211_mon =
                     IEEE80211Monitor::enter_monitor_mode(state.nl80211_con.clone(), wiphy)
-                        .expect("failed to create 802.11 monitor"),
-                ));
+                        .expect("failed to create 802.11 monitor");
+
+                //Record all sniffed traffic if requested
+                if let Some(capture_path) = &state.capture_path {
+                    ieee80211_mon
+                        .start_capture(capture_path)
+                        .expect("failed to start capturing sniffed traffic");
+                }
+
+                state.ieee80211_mon = Some(Rc::new(ieee80211_mon));
 
                 //Switch the scene to the target selector
                 state.select_target();
@@ -112,56 +138,88 @@ impl AppState {
             ieee80211_mon,
             ap_mac,
             dev_mac,
-            move |prov| {
+            self.passive,
+            move |mut prov| {
                 //Deref the state reference
                 let Some(state) = state_ref.upgrade() else {
                     return;
                 };
                 let mut state = state.borrow_mut();
 
+                //Record the attack target, so that the session can be resumed later
+                let channel = state
+                    .ieee80211_mon
+                    .as_ref()
+                    .expect("no 802.11 monitor has been created")
+                    .channel()
+                    .expect("failed to query 802.11 monitor channel")
+                    .expect("802.11 monitor is not on any channel");
+
+                let target = SessionTarget {
+                    bssid: ap_mac,
+                    channel: channel.main_channel(),
+                    attack: match &prov {
+                        AttackSampleSupplier::ARPReplay(arp_prov) => SessionAttack::ARPReplay {
+                            client: dev_mac,
+                            arp_request: arp_prov.arp_request().to_vec(),
+                            fake_auth: None,
+                        },
+                        AttackSampleSupplier::Passive(_) => SessionAttack::Passive,
+                    },
+                };
+
                 //Switch the scene to key cracking
-                state.keycrack(prov);
+                let replay_rate = prov.replay_rate();
+                state.keycrack(
+                    Box::new(move |should_exit| prov.provide_sample(should_exit)),
+                    &[],
+                    Some(target),
+                    replay_rate,
+                );
             },
         )));
     }
 
-    fn keycrack(&mut self, mut sample_prov: ARPSampleSupplier) {
+    fn keycrack(
+        &mut self,
+        sample_prov: Box<KeyCrackerSampleProvider>,
+        test_frames: &[WepFrame],
+        target: Option<SessionTarget>,
+        replay_rate: Option<Arc<Mutex<ReplayRateController>>>,
+    ) {
         //Switch the scene to the key cracking scene
-        const KEYCRACK_SETTINGS: KeyCrackerSettings = KeyCrackerSettings {
-            key_predictor_normal_threshold: 0.075,
-            key_predictor_strong_threshold: 0.025,
-            num_test_samples: 1024,
-            test_sample_period: 128,
-            test_sample_threshold: 0.25,
-        };
-
         self.new_scene = Some(Box::new(ui::keycracker::UIKeyCracker::new(
-            KEYCRACK_SETTINGS,
-            Box::new(move |should_exit| sample_prov.provide_sample(should_exit)),
+            self.keycracker_settings,
+            sample_prov,
+            test_frames,
+            self.session_path
+                .clone()
+                .map(|path| SessionWriter::new(path, target)),
+            replay_rate,
         )));
     }
 
     fn keycrack_simulate(&mut self, key: WepKey) {
         //Switch the scene to the key cracking scene
-        const KEYCRACK_SETTINGS: KeyCrackerSettings = KeyCrackerSettings {
-            key_predictor_normal_threshold: 0.50,
-            key_predictor_strong_threshold: 0.35,
-            num_test_samples: 1024,
-            test_sample_period: 128,
-            test_sample_threshold: 1.,
-        };
-
         self.new_scene = Some(Box::new(ui::keycracker::UIKeyCracker::new(
-            KEYCRACK_SETTINGS,
+            self.keycracker_settings,
             Box::new(move |_should_exit| {
                 let mut iv = WepIV::default();
                 rand::thread_rng().fill_bytes(&mut iv);
 
-                let mut keystream = [0u8; 16];
+                let mut keystream = [0u8; KeystreamSample::KEYSTREAM_LEN];
                 key.create_rc4(&iv).gen_keystream(&mut keystream);
 
-                Some(KeystreamSample { iv, keystream })
+                Some(KeystreamSample {
+                    iv,
+                    keystream,
+                    keystream_len: KeystreamSample::KEYSTREAM_LEN,
+                    confidence: 1.,
+                })
             }),
+            &[],
+            None,
+            None,
         )));
     }
 }
@@ -172,27 +230,78 @@ pub struct App {
 }
 
 impl App {
-    pub fn create() -> Result<App, Box<dyn Error>> {
+    pub fn create(args: &Args) -> Result<App, Box<dyn Error>> {
         //Create a new nl80211 connection
         let nl80211_con =
             NL80211Connection::new().context("failed to create a nl80211 connection")?;
 
+        //Determine the key cracker settings
+        //Simulated samples are free of noise, so we can use much stricter defaults for them
+        const SIMULATION_KEYCRACK_SETTINGS: KeyCrackerSettings = KeyCrackerSettings {
+            key_len: WepKey::LEN_104,
+            key_predictor_normal_threshold: 0.50,
+            key_predictor_strong_threshold: 0.35,
+            min_sample_confidence: 1.,
+            num_test_samples: 1024,
+            test_sample_period: 128,
+            test_sample_threshold: 1.,
+            num_test_frames: 0,
+
+            num_sigma_candidates: 4,
+            max_candidate_keys: 1 << 16,
+        };
+
+        let keycracker_settings = args.keycracker_settings(match args.simulate_key {
+            Some(key) => KeyCrackerSettings {
+                key_len: key.key().len(),
+                ..SIMULATION_KEYCRACK_SETTINGS
+            },
+            None => KeyCrackerSettings::default(),
+        })?;
+
         //Allocate the app state
-        let state_rc = AppState::new(nl80211_con);
+        let state_rc = AppState::new(
+            nl80211_con,
+            args.capture.clone(),
+            args.session.clone(),
+            keycracker_settings,
+            args.passive,
+        );
 
-        if let Ok(simulate_key) = std::env::var("WEPCRACK_SIMULATE_KEY") {
-            let simulate_key = match simulate_key.len() {
-                10 => WepKey::Wep40Key(
-                    <[u8; WepKey::LEN_40]>::from_hex(simulate_key)
-                        .expect("failed to parse WEP key"),
-                ),
-                26 => WepKey::Wep104Key(
-                    <[u8; WepKey::LEN_104]>::from_hex(simulate_key)
-                        .expect("failed to parse WEP key"),
-                ),
-                _ => panic!("invalid WEP key length"),
-            };
+        //Only simulated samples contain enough keystream bytes for every key length
+        if args.simulate_key.is_none() {
+            keycracker_settings.check_keystream_len(plaintext::MAX_PREDICTED_PLAINTEXT_LEN)?;
+        }
+
+        //Full frames for ICV verification can only be read from capture files
+        if keycracker_settings.num_test_frames > 0 && args.pcap.is_none() {
+            return Err(
+                "verifying keys using ICVs is only supported when cracking capture files".into(),
+            );
+        }
+
+        if let Some(simulate_key) = args.simulate_key {
             state_rc.borrow_mut().keycrack_simulate(simulate_key);
+        } else if let Some(pcap_path) = &args.pcap {
+            let test_frames = PcapSampleSupplier::read_test_frames(
+                pcap_path,
+                None,
+                keycracker_settings.num_test_frames,
+            )?;
+            if keycracker_settings.num_test_frames > 0 && test_frames.is_empty() {
+                return Err(
+                    "the capture file contains no protected frames to verify keys with".into(),
+                );
+            }
+
+            let mut sample_prov = PcapSampleSupplier::open(pcap_path, None)
+                .context("failed to open capture file for offline cracking")?;
+            state_rc.borrow_mut().keycrack(
+                Box::new(move |should_exit| sample_prov.provide_sample(should_exit)),
+                &test_frames,
+                None,
+                None,
+            );
         } else {
             state_rc.borrow_mut().select_device();
         }
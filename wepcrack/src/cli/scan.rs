@@ -0,0 +1,123 @@
+use std::{
+    sync::{
+        atomic::{self, AtomicBool},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use ieee80211::MacAddress;
+use serde_json::json;
+
+use crate::{nl80211::NL80211Channel, target_monitor::TargetMonitor};
+
+use super::{emit_event, enter_monitor_mode, lookup_channel, parse_mac_address, Args};
+
+#[derive(clap::Args)]
+pub struct ScanArgs {
+    #[arg(long, help = "The wireless device to scan with")]
+    device: String,
+
+    #[arg(
+        long = "channel",
+        help = "The channel(s) to scan on [default: all permitted 20MHz channels]"
+    )]
+    channels: Vec<u32>,
+
+    #[arg(
+        long,
+        default_value_t = 1000,
+        help = "How long to listen on each channel in milliseconds"
+    )]
+    dwell_ms: u64,
+
+    #[arg(
+        long,
+        value_parser = parse_mac_address,
+        help = "Scan for client devices of this access point instead of for access points"
+    )]
+    bssid: Option<MacAddress>,
+}
+
+pub(super) fn run(
+    scan_args: &ScanArgs,
+    args: &Args,
+    should_quit: &Arc<AtomicBool>,
+) -> anyhow::Result<()> {
+    if scan_args.bssid.map_or(false, |bssid| !bssid.is_unicast()) {
+        anyhow::bail!("the BSSID must be a unicast address");
+    }
+
+    let monitor = enter_monitor_mode(&scan_args.device, args)?;
+
+    //Determine the channels to scan
+    let channels = if scan_args.channels.is_empty() {
+        monitor
+            .channels()
+            .iter()
+            .filter(|channel| matches!(channel, NL80211Channel::Channel20NoHT { .. }))
+            .copied()
+            .collect::<Vec<_>>()
+    } else {
+        scan_args
+            .channels
+            .iter()
+            .map(|&channel| lookup_channel(&monitor, channel))
+            .collect::<anyhow::Result<Vec<_>>>()?
+    };
+
+    //Scan each channel
+    let mut target_monitor = TargetMonitor::new(monitor);
+    for channel in channels {
+        let NL80211Channel::Channel20NoHT {
+            channel: channel_idx,
+        } = channel
+        else {
+            unreachable!();
+        };
+
+        target_monitor.set_channel(channel)?;
+        match scan_args.bssid {
+            Some(bssid) => target_monitor.sniff_devices(bssid),
+            None => target_monitor.sniff_aps(),
+        }
+
+        //Wait for the dwell time to elapse
+        let start_time = Instant::now();
+        while start_time.elapsed() < Duration::from_millis(scan_args.dwell_ms) {
+            if should_quit.load(atomic::Ordering::SeqCst) {
+                anyhow::bail!("interrupted");
+            }
+            if target_monitor.did_crash() {
+                anyhow::bail!("target sniffer thread crashed");
+            }
+
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        //Report the results
+        if let Some(bssid) = scan_args.bssid {
+            for dev in target_monitor.get_sniffed_devices() {
+                emit_event(json!({
+                    "event": "client",
+                    "channel": channel_idx,
+                    "bssid": bssid.to_hex_string(),
+                    "mac_address": dev.mac_address().to_hex_string(),
+                    "strength_dbm": dev.strength_dbm(),
+                }));
+            }
+        } else {
+            for ap in target_monitor.get_sniffed_aps() {
+                emit_event(json!({
+                    "event": "access_point",
+                    "channel": channel_idx,
+                    "bssid": ap.mac_address().to_hex_string(),
+                    "ssid": ap.ssid(),
+                    "strength_dbm": ap.strength_dbm(),
+                }));
+            }
+        }
+    }
+
+    Ok(())
+}
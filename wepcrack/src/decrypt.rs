@@ -0,0 +1,207 @@
+//Decryption of captured traffic once the key is known
+
+use std::{
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use anyhow::Context;
+use ieee80211::{DataFrameTrait, MacAddress};
+
+use crate::{
+    keystream_store::KeystreamStore,
+    pcap::{PcapReader, PcapRecord, PcapWriter, LINKTYPE_ETHERNET},
+    pcap_supplier::parse_protected_frame,
+    plaintext::{LLC_SNAP_HEADER_SIZE, LLC_SNAP_PREFIX},
+    wep::{WepFrame, WepKey},
+};
+
+//802.1H encapsulation header, which is replaced by an Ethernet II header just like the RFC 1042 one
+const LLC_SNAP_BRIDGE_TUNNEL: [u8; 6] = [0xaa, 0xaa, 0x03, 0x00, 0x00, 0xf8];
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DecryptionStats {
+    pub num_records: usize,
+    pub num_frames: usize,
+    pub num_decrypted: usize,
+    pub num_icv_errors: usize,
+    pub num_unknown_ivs: usize,
+    pub num_short_keystreams: usize,
+}
+
+//Frames can either be decrypted using the key, or using the keystreams recovered for their IVs
+pub enum DecryptionSource {
+    Key(WepKey),
+    Keystreams(KeystreamStore),
+}
+
+pub struct CaptureDecrypter {
+    source: DecryptionSource,
+    bssid: Option<MacAddress>,
+    stats: DecryptionStats,
+}
+
+impl CaptureDecrypter {
+    pub fn new(source: DecryptionSource, bssid: Option<MacAddress>) -> CaptureDecrypter {
+        CaptureDecrypter {
+            source,
+            bssid,
+            stats: DecryptionStats::default(),
+        }
+    }
+
+    pub const fn stats(&self) -> &DecryptionStats {
+        &self.stats
+    }
+
+    pub fn decrypt_capture(
+        &mut self,
+        input_path: &Path,
+        output_path: &Path,
+        should_quit: &AtomicBool,
+    ) -> anyhow::Result<()> {
+        let mut reader = PcapReader::open(input_path).context("failed to open capture file")?;
+        let mut writer = PcapWriter::create(output_path, LINKTYPE_ETHERNET)?;
+
+        while let Some(record) = reader
+            .next_record()
+            .context("failed to read record from capture file")?
+        {
+            if should_quit.load(Ordering::SeqCst) {
+                anyhow::bail!("interrupted");
+            }
+
+            //Keep the original timestamps, so that the decrypted traffic lines up with the capture
+            if let Some(eth_frame) = self.decrypt_record(&record) {
+                writer.write_packet(record.timestamp, &eth_frame)?;
+            }
+        }
+
+        writer.flush()
+    }
+
+    pub fn decrypt_record(&mut self, record: &PcapRecord) -> Option<Vec<u8>> {
+        self.stats.num_records += 1;
+
+        let (frame, dst, src) = parse_protected_frame(record, self.bssid, |data, has_fcs| {
+            Some((
+                WepFrame::from_data_frame(data, has_fcs)?,
+                data.destination_address()?,
+                data.source_address()?,
+            ))
+        })?;
+        self.stats.num_frames += 1;
+
+        //Frames with a mismatching ICV were either corrupted, or encrypted using a different key
+        let payload = match &self.source {
+            DecryptionSource::Key(key) => frame.decrypt(key),
+            DecryptionSource::Keystreams(store) => {
+                let Some(keystream) = store.get(&frame.iv) else {
+                    self.stats.num_unknown_ivs += 1;
+                    return None;
+                };
+                if keystream.keystream.len() < frame.body.len() {
+                    self.stats.num_short_keystreams += 1;
+                    return None;
+                }
+                frame.decrypt_with_keystream(&keystream)
+            }
+        };
+        let Some(payload) = payload else {
+            self.stats.num_icv_errors += 1;
+            return None;
+        };
+        self.stats.num_decrypted += 1;
+
+        Some(ethernet_frame(&dst, &src, &payload))
+    }
+}
+
+pub fn ethernet_frame(dst: &MacAddress, src: &MacAddress, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(14 + payload.len());
+    frame.extend_from_slice(dst.as_bytes());
+    frame.extend_from_slice(src.as_bytes());
+
+    if payload.len() >= LLC_SNAP_HEADER_SIZE
+        && (payload[..6] == LLC_SNAP_PREFIX || payload[..6] == LLC_SNAP_BRIDGE_TUNNEL)
+    {
+        //Convert to an Ethernet II frame using the SNAP EtherType
+        frame.extend_from_slice(&payload[6..LLC_SNAP_HEADER_SIZE]);
+        frame.extend_from_slice(&payload[LLC_SNAP_HEADER_SIZE..]);
+    } else {
+        //Keep the LLC header, and emit an 802.3 frame with a length field instead
+        frame.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        frame.extend_from_slice(payload);
+    }
+
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use ieee80211::DSStatus;
+
+    use super::*;
+    use crate::{
+        pcap::LINKTYPE_IEEE802_11,
+        wep::{WepIV, WepKeystream},
+    };
+
+    #[test]
+    fn test_ethernet_frame() {
+        let dst = MacAddress::new([0xff; 6]);
+        let src = MacAddress::new([0x02, 0, 0, 0, 0, 1]);
+
+        //SNAP encapsulated ARP packet
+        let frame = ethernet_frame(
+            &dst,
+            &src,
+            &[0xaa, 0xaa, 0x03, 0x00, 0x00, 0x00, 0x08, 0x06, 0x00, 0x01],
+        );
+        assert_eq!(
+            frame,
+            [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x02, 0, 0, 0, 0, 1, 0x08, 0x06, 0x00, 0x01]
+        );
+
+        //Plain LLC payload (e.g. STP)
+        let frame = ethernet_frame(&dst, &src, &[0x42, 0x42, 0x03]);
+        assert_eq!(&frame[12..], [0x00, 0x03, 0x42, 0x42, 0x03]);
+    }
+
+    #[test]
+    fn test_keystream_decryption_stats() {
+        let key: WepKey = "0102030405".parse().unwrap();
+        let bssid = MacAddress::new([0x02, 0, 0, 0, 0, 1]);
+        let client = MacAddress::new([0x02, 0, 0, 0, 0, 2]);
+
+        let record = |iv: WepIV| {
+            let frame = WepFrame::encrypt(&key, &iv, 0, b"\xaa\xaa\x03\x00\x00\x00\x08\x00payload");
+            PcapRecord {
+                link_type: LINKTYPE_IEEE802_11,
+                timestamp: Duration::ZERO,
+                data: frame
+                    .build_data_frame(DSStatus::FromSTAToDS, &bssid, &client, &bssid)
+                    .unwrap(),
+            }
+        };
+
+        let mut store = KeystreamStore::new();
+        store.insert(WepKeystream::from_key(&key, &[1, 1, 1], 64));
+        store.insert(WepKeystream::from_key(&key, &[2, 2, 2], 8));
+
+        //Keystreams which don't cover the whole frame aren't counted as ICV errors
+        let mut decrypter = CaptureDecrypter::new(DecryptionSource::Keystreams(store), None);
+        assert!(decrypter.decrypt_record(&record([1, 1, 1])).is_some());
+        assert!(decrypter.decrypt_record(&record([2, 2, 2])).is_none());
+        assert!(decrypter.decrypt_record(&record([3, 3, 3])).is_none());
+
+        let stats = decrypter.stats();
+        assert_eq!(stats.num_frames, 3);
+        assert_eq!(stats.num_decrypted, 1);
+        assert_eq!(stats.num_short_keystreams, 1);
+        assert_eq!(stats.num_unknown_ivs, 1);
+        assert_eq!(stats.num_icv_errors, 0);
+    }
+}
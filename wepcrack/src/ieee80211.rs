@@ -1,6 +1,18 @@
-use std::{io::Read, rc::Rc, time::Duration};
+use std::{
+    fs::File,
+    io::{BufWriter, Read},
+    path::Path,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant, SystemTime},
+};
 
 use anyhow::Context;
+use ieee80211::{DataFrameTrait, FrameLayer, FrameTrait, MacAddress};
 use libc::{sockaddr_ll, sockaddr_storage, AF_PACKET, ETH_P_ALL, SOCK_RAW};
 use netlink_packet_route::{
     link::{LinkFlag, LinkLayerType, LinkMessage},
@@ -14,10 +26,17 @@ use crate::{
         NL80211Channel, NL80211Connection, NL80211Interface, NL80211InterfaceType,
         NL80211RegulatoryDomain, NL80211Wiphy,
     },
+    pcap::{PcapngWriter, LINKTYPE_IEEE802_11_RADIOTAP},
     rtnetlink::RTNetlinkConnection,
     util::DropGuard,
+    wep::WepFrame,
 };
 
+struct CaptureThread {
+    should_exit: Arc<AtomicBool>,
+    thread: JoinHandle<anyhow::Result<()>>,
+}
+
 pub struct IEEE80211Monitor {
     nl802111_con: Rc<NL80211Connection>,
 
@@ -26,6 +45,8 @@ pub struct IEEE80211Monitor {
 
     orig_interfaces: Vec<NL80211Interface>,
     mon_interface: NL80211Interface,
+
+    capture: Option<CaptureThread>,
 }
 
 impl IEEE80211Monitor {
@@ -107,13 +128,91 @@ impl IEEE80211Monitor {
 
             orig_interfaces,
             mon_interface,
+
+            capture: None,
         })
     }
 
+    pub fn start_capture(&mut self, path: &Path) -> anyhow::Result<()> {
+        //Every sniffer gets its own copy of each frame, so record them using a dedicated one
+        //The kernel also hands the frames we inject to it, so those are recorded as well
+        let mut writer = PcapngWriter::create(path).context("failed to create capture writer")?;
+        let if_id = writer
+            .add_interface(LINKTYPE_IEEE802_11_RADIOTAP, self.mon_interface.name())
+            .context("failed to add capture interface")?;
+
+        let mut sniffer = self
+            .create_sniffer()
+            .context("failed to create capture sniffer")?;
+        sniffer.set_timeout(Some(Duration::from_millis(250)))?;
+
+        self.stop_capture();
+
+        let should_exit = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let should_exit = should_exit.clone();
+            std::thread::Builder::new()
+                .name("capture thread".into())
+                .spawn(move || Self::capture_thread_fnc(sniffer, writer, if_id, &should_exit))
+                .context("failed to spawn capture thread")?
+        };
+
+        self.capture = Some(CaptureThread {
+            should_exit,
+            thread,
+        });
+        Ok(())
+    }
+
+    fn stop_capture(&mut self) {
+        let Some(capture) = self.capture.take() else {
+            return;
+        };
+
+        capture.should_exit.store(true, Ordering::SeqCst);
+        match capture.thread.join() {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => eprintln!("failed to record capture file: {err:?}"),
+            Err(err) => std::panic::resume_unwind(err),
+        }
+    }
+
+    fn capture_thread_fnc(
+        mut sniffer: IEEE80211PacketSniffer,
+        mut writer: PcapngWriter<BufWriter<File>>,
+        if_id: u32,
+        should_exit: &AtomicBool,
+    ) -> anyhow::Result<()> {
+        let mut rx_buf = [0u8; IEEE80211Packet::MAX_SIZE];
+        while !should_exit.load(Ordering::SeqCst) {
+            let Some(rx_size) = sniffer.receive(&mut rx_buf)? else {
+                continue;
+            };
+
+            //Record the raw packet (radiotap header included)
+            let timestamp = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default();
+            writer
+                .write_packet(if_id, timestamp, &rx_buf[..rx_size])
+                .context("failed to record sniffed packet")?;
+        }
+
+        writer.flush()
+    }
+
+    pub fn mac_address(&self) -> MacAddress {
+        MacAddress::new(*self.mon_interface.mac_address())
+    }
+
     pub fn channels(&self) -> &[NL80211Channel] {
         &self.channels
     }
 
+    pub fn channel(&self) -> anyhow::Result<Option<NL80211Channel>> {
+        self.mon_interface.get_channel(&self.nl802111_con)
+    }
+
     pub fn set_channel(&self, channel: NL80211Channel) -> anyhow::Result<()> {
         self.mon_interface.set_channel(&channel, &self.nl802111_con)
     }
@@ -142,12 +241,17 @@ impl IEEE80211Monitor {
             .bind(&unsafe { SockAddr::new(sockaddr, std::mem::size_of::<sockaddr_ll>() as u32) })
             .context("failed to bind the PF_PACKET socket to the monitor interface")?;
 
-        Ok(IEEE80211PacketSniffer(packet_socket))
+        Ok(IEEE80211PacketSniffer {
+            socket: packet_socket,
+        })
     }
 }
 
 impl Drop for IEEE80211Monitor {
     fn drop(&mut self) {
+        //Finish the capture file
+        self.stop_capture();
+
         //Try to revert back the wiphy
         if let Err(err) = (|| -> anyhow::Result<()> {
             //Delete the monitor interface
@@ -171,14 +275,16 @@ impl Drop for IEEE80211Monitor {
     }
 }
 
-pub struct IEEE80211PacketSniffer(Socket);
+pub struct IEEE80211PacketSniffer {
+    socket: Socket,
+}
 
 impl IEEE80211PacketSniffer {
     pub fn set_timeout(&mut self, timeout: Option<Duration>) -> anyhow::Result<()> {
-        self.0
+        self.socket
             .set_read_timeout(timeout)
             .context("failed to set 802.11 sniffer socket read timeout")?;
-        self.0
+        self.socket
             .set_write_timeout(timeout)
             .context("failed to set 802.11 sniffer socket write timeout")?;
         Ok(())
@@ -187,30 +293,123 @@ impl IEEE80211PacketSniffer {
     pub fn sniff_packet(&mut self) -> anyhow::Result<Option<IEEE80211Packet>> {
         //Receive a packet from the socket
         let mut rx_buf = [0u8; IEEE80211Packet::MAX_SIZE];
+        let Some(rx_size) = self.receive(&mut rx_buf)? else {
+            return Ok(None);
+        };
 
-        let rx_size = 'rx_loop: loop {
-            match self.0.read(&mut rx_buf) {
-                Ok(rx_size) => break 'rx_loop rx_size,
-                Err(err) if err.raw_os_error() == Some(11) => {
-                    //Resource temporarily unavailable
-                    std::thread::sleep(std::time::Duration::from_millis(10));
-                }
-                Err(err) if err.kind() == std::io::ErrorKind::TimedOut => return Ok(None),
+        Ok(Some(
+            IEEE80211Packet::try_from(&rx_buf[..rx_size])
+                .context("failed to parse 802.11 packet")?,
+        ))
+    }
+
+    fn receive(&mut self, rx_buf: &mut [u8]) -> anyhow::Result<Option<usize>> {
+        match self.socket.read(rx_buf) {
+            Ok(rx_size) => Ok(Some(rx_size)),
+            //The read timeout expiring shows up as "resource temporarily unavailable"
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                ) =>
+            {
+                Ok(None)
+            }
+            Err(err) => {
+                Err(anyhow::anyhow!(err).context("failed to read packet from packet socket"))
+            }
+        }
+    }
+
+    //Discards all packets which were received but haven't been read yet
+    pub fn drain(&mut self) -> anyhow::Result<()> {
+        let mut rx_buf = [0u8; IEEE80211Packet::MAX_SIZE];
+
+        self.socket
+            .set_nonblocking(true)
+            .context("failed to make 802.11 sniffer socket non-blocking")?;
+        let res = loop {
+            match self.socket.read(&mut rx_buf) {
+                Ok(_) => {}
+                Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => break Ok(()),
                 Err(err) => {
-                    return Err(
+                    break Err(
                         anyhow::anyhow!(err).context("failed to read packet from packet socket")
                     )
                 }
             }
         };
+        self.socket
+            .set_nonblocking(false)
+            .context("failed to make 802.11 sniffer socket blocking")?;
 
-        Ok(Some(
-            IEEE80211Packet::try_from(&rx_buf[..rx_size])
-                .context("failed to parse 802.11 packet")?,
-        ))
+        res
+    }
+
+    pub fn sniff_wep_frame(
+        &mut self,
+        bssid: &MacAddress,
+        min_payload_len: usize,
+        timeout: Duration,
+    ) -> anyhow::Result<Option<WepFrame>> {
+        //Chopping / fragmenting a frame takes longer the bigger it is, so collect frames for a short window and pick the shortest one
+        const CANDIDATE_WINDOW: Duration = Duration::from_millis(500);
+
+        let mut deadline = Instant::now() + timeout;
+        let mut best_frame: Option<WepFrame> = None;
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+
+            self.set_timeout(Some(deadline - now))?;
+            let Some(packet) = self.sniff_packet()? else {
+                break;
+            };
+            let frame = packet.ieee80211_frame();
+
+            //Check if this is a protected data frame of the network
+            let Some(FrameLayer::Data(data)) = frame.next_layer() else {
+                continue;
+            };
+            if !data.protected() || data.bssid_address() != Some(*bssid) {
+                continue;
+            }
+
+            let Some(wep_frame) = WepFrame::from_data_frame(&data, true) else {
+                continue;
+            };
+            let payload_len = wep_frame.encrypted_payload().len();
+            if payload_len < min_payload_len {
+                continue;
+            }
+
+            match &best_frame {
+                Some(best_frame) if best_frame.encrypted_payload().len() <= payload_len => {}
+                Some(_) => best_frame = Some(wep_frame),
+                None => {
+                    //Don't wait for other candidates for longer than the window
+                    deadline = deadline.min(now + CANDIDATE_WINDOW);
+                    best_frame = Some(wep_frame);
+                }
+            }
+        }
+
+        Ok(best_frame)
     }
 
     pub fn inject_frame(&mut self, frame: &impl ieee80211::FrameTrait) -> anyhow::Result<()> {
+        while !self.try_inject_frame(frame)? {
+            //Resource temporarily unavailable
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+
+        Ok(())
+    }
+
+    //Returns false instead of blocking if the device's transmit queue is full
+    pub fn try_inject_frame(&mut self, frame: &impl ieee80211::FrameTrait) -> anyhow::Result<bool> {
         const IEEE80211_RADIOTAP_TX_FLAGS: u32 = 15;
         const IEEE80211_RADIOTAP_F_TX_NOACK: u16 = 0x8;
 
@@ -223,23 +422,16 @@ impl IEEE80211PacketSniffer {
 
         tx_buf[10..tx_len].copy_from_slice(frame.bytes());
 
-        let tx_size = 'tx_loop: loop {
-            match self.0.send(&tx_buf[..tx_len]) {
-                Ok(tx_size) => break 'tx_loop tx_size,
-                Err(err) if err.raw_os_error() == Some(11) => {
-                    //Resource temporarily unavailable
-                    std::thread::sleep(std::time::Duration::from_millis(100));
-                }
-                Err(err) => {
-                    return Err(
-                        anyhow::anyhow!(err).context("failed to send packet through packet socket")
-                    );
-                }
-            };
-        };
-        assert_eq!(tx_size, tx_len);
-
-        Ok(())
+        match self.socket.send(&tx_buf[..tx_len]) {
+            Ok(tx_size) => {
+                assert_eq!(tx_size, tx_len);
+                Ok(true)
+            }
+            Err(err) if err.raw_os_error() == Some(11) => Ok(false),
+            Err(err) => {
+                Err(anyhow::anyhow!(err).context("failed to send packet through packet socket"))
+            }
+        }
     }
 }
 
@@ -0,0 +1,332 @@
+//Support for the IVS capture format of aircrack-ng, which only stores the IVs and keystreams of WEP frames
+
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use anyhow::Context;
+
+use crate::{util::read_exact_or_truncated, wep::WepIV};
+
+const IVS1_MAGIC: [u8; 4] = [0xbf, 0xca, 0x84, 0xd4];
+const IVS2_MAGIC: [u8; 4] = [0xae, 0x78, 0xd1, 0xff];
+const IVS2_VERSION: u16 = 1;
+
+const IVS2_FLAG_BSSID: u16 = 0x0001;
+const IVS2_FLAG_XOR: u16 = 0x0008;
+const IVS2_FLAG_PTW: u16 = 0x0010;
+
+#[derive(Debug, Clone)]
+pub struct IvsRecord {
+    pub bssid: Option<[u8; 6]>,
+    pub iv: WepIV,
+    pub keystream: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IvsVersion {
+    //Version 1 files only store the first two keystream bytes of each frame
+    V1,
+    V2,
+}
+
+pub struct IvsReader<R: Read> {
+    reader: R,
+    version: IvsVersion,
+    bssid: Option<[u8; 6]>,
+}
+
+impl IvsReader<BufReader<File>> {
+    pub fn open(path: &Path) -> anyhow::Result<IvsReader<BufReader<File>>> {
+        let file = File::open(path)
+            .with_context(|| format!("failed to open IVS file {}", path.display()))?;
+        IvsReader::new(BufReader::new(file))
+    }
+}
+
+impl<R: Read> IvsReader<R> {
+    pub fn new(mut reader: R) -> anyhow::Result<IvsReader<R>> {
+        //Read the magic number to determine the file version
+        let mut magic = [0u8; 4];
+        reader
+            .read_exact(&mut magic)
+            .context("failed to read IVS file magic")?;
+
+        let version = match magic {
+            IVS1_MAGIC => IvsVersion::V1,
+            IVS2_MAGIC => {
+                let mut version = [0u8; 2];
+                reader
+                    .read_exact(&mut version)
+                    .context("failed to read IVS file header")?;
+
+                let version = u16::from_le_bytes(version);
+                if version > IVS2_VERSION {
+                    anyhow::bail!("unsupported IVS file version: {version}");
+                }
+
+                IvsVersion::V2
+            }
+            _ => anyhow::bail!("unknown IVS file magic: {}", hex::encode(magic)),
+        };
+
+        Ok(IvsReader {
+            reader,
+            version,
+            bssid: None,
+        })
+    }
+
+    pub const fn version(&self) -> IvsVersion {
+        self.version
+    }
+
+    pub fn next_record(&mut self) -> anyhow::Result<Option<IvsRecord>> {
+        match self.version {
+            IvsVersion::V1 => self.next_v1_record(),
+            IvsVersion::V2 => self.next_v2_record(),
+        }
+    }
+
+    fn next_v1_record(&mut self) -> anyhow::Result<Option<IvsRecord>> {
+        //Records start with either a new BSSID, or 0xff if the BSSID didn't change
+        let mut first = [0u8; 1];
+        if !read_exact_or_truncated(&mut self.reader, &mut first)
+            .context("failed to read IVS record header")?
+        {
+            return Ok(None);
+        }
+
+        if first[0] != 0xff {
+            let mut bssid = [first[0], 0, 0, 0, 0, 0];
+            if !read_exact_or_truncated(&mut self.reader, &mut bssid[1..])
+                .context("failed to read IVS record BSSID")?
+            {
+                return Ok(None);
+            }
+            self.bssid = Some(bssid);
+        }
+
+        //Read the IV and the first two keystream bytes
+        let mut data = [0u8; 5];
+        if !read_exact_or_truncated(&mut self.reader, &mut data)
+            .context("failed to read IVS record data")?
+        {
+            return Ok(None);
+        }
+
+        Ok(Some(IvsRecord {
+            bssid: self.bssid,
+            iv: [data[0], data[1], data[2]],
+            keystream: Vec::from(&data[3..]),
+        }))
+    }
+
+    fn next_v2_record(&mut self) -> anyhow::Result<Option<IvsRecord>> {
+        loop {
+            //Read the record header
+            let mut header = [0u8; 4];
+            if !read_exact_or_truncated(&mut self.reader, &mut header)
+                .context("failed to read IVS record header")?
+            {
+                return Ok(None);
+            }
+
+            let flags = u16::from_le_bytes([header[0], header[1]]);
+            let mut len = u16::from_le_bytes([header[2], header[3]]) as usize;
+
+            //The BSSID is only stored when it changes, and is included in the record length
+            if flags & IVS2_FLAG_BSSID != 0 {
+                if len < 6 {
+                    anyhow::bail!("invalid IVS record length: {len}");
+                }
+
+                let mut bssid = [0u8; 6];
+                if !read_exact_or_truncated(&mut self.reader, &mut bssid)
+                    .context("failed to read IVS record BSSID")?
+                {
+                    return Ok(None);
+                }
+                self.bssid = Some(bssid);
+                len -= 6;
+            }
+
+            let mut data = vec![0u8; len];
+            if !read_exact_or_truncated(&mut self.reader, &mut data)
+                .context("failed to read IVS record data")?
+            {
+                return Ok(None);
+            }
+
+            //Records consist of the IV + key index, followed by the keystream
+            let keystream = if flags & IVS2_FLAG_XOR != 0 && data.len() >= 4 {
+                &data[4..]
+            } else if flags & IVS2_FLAG_PTW != 0 && data.len() >= 6 {
+                //PTW records hold multiple possible keystreams - only use the first (most likely) one
+                let (num_keystreams, keystream_len) = (data[4] as usize, data[5] as usize);
+                if num_keystreams == 0
+                    || keystream_len == 0
+                    || data.len() < 6 + num_keystreams * keystream_len
+                {
+                    continue;
+                }
+                &data[6..6 + keystream_len]
+            } else {
+                //Skip all other records (ESSIDs, WPA handshakes, unencrypted frames, ...)
+                continue;
+            };
+
+            return Ok(Some(IvsRecord {
+                bssid: self.bssid,
+                iv: [data[0], data[1], data[2]],
+                keystream: Vec::from(keystream),
+            }));
+        }
+    }
+}
+
+pub struct IvsWriter<W: Write> {
+    writer: W,
+    bssid: Option<[u8; 6]>,
+}
+
+impl IvsWriter<BufWriter<File>> {
+    pub fn create(path: &Path) -> anyhow::Result<IvsWriter<BufWriter<File>>> {
+        let file = File::create(path)
+            .with_context(|| format!("failed to create IVS file {}", path.display()))?;
+        IvsWriter::new(BufWriter::new(file))
+    }
+}
+
+impl<W: Write> IvsWriter<W> {
+    pub fn new(mut writer: W) -> anyhow::Result<IvsWriter<W>> {
+        //We always write version 2 files, as version 1 can't hold enough keystream bytes
+        writer
+            .write_all(&IVS2_MAGIC)
+            .and_then(|_| writer.write_all(&IVS2_VERSION.to_le_bytes()))
+            .context("failed to write IVS file header")?;
+
+        Ok(IvsWriter {
+            writer,
+            bssid: None,
+        })
+    }
+
+    pub fn write_record(
+        &mut self,
+        bssid: &[u8; 6],
+        iv: &WepIV,
+        keystream: &[u8],
+    ) -> anyhow::Result<()> {
+        //Only include the BSSID if it changed
+        let new_bssid = self.bssid != Some(*bssid);
+        let mut flags = IVS2_FLAG_XOR;
+        let mut record = Vec::with_capacity(6 + 4 + keystream.len());
+        if new_bssid {
+            flags |= IVS2_FLAG_BSSID;
+            record.extend_from_slice(bssid);
+        }
+        record.extend_from_slice(iv);
+        record.push(0); //Key index
+        record.extend_from_slice(keystream);
+
+        let len = u16::try_from(record.len()).context("IVS record keystream is too long")?;
+
+        self.writer
+            .write_all(&flags.to_le_bytes())
+            .and_then(|_| self.writer.write_all(&len.to_le_bytes()))
+            .and_then(|_| self.writer.write_all(&record))
+            .context("failed to write IVS record")?;
+
+        self.bssid = Some(*bssid);
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> anyhow::Result<()> {
+        self.writer.flush().context("failed to flush IVS file")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ivs2_roundtrip() {
+        const BSSID_A: [u8; 6] = [0x00, 0x11, 0x22, 0x33, 0x44, 0x55];
+        const BSSID_B: [u8; 6] = [0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb];
+
+        //Write a small IVS file with two networks
+        let mut buf = Vec::new();
+        {
+            let mut writer = IvsWriter::new(&mut buf).unwrap();
+            writer.write_record(&BSSID_A, &[1, 2, 3], &[4; 16]).unwrap();
+            writer.write_record(&BSSID_A, &[5, 6, 7], &[8; 16]).unwrap();
+            writer
+                .write_record(&BSSID_B, &[9, 10, 11], &[12; 20])
+                .unwrap();
+        }
+
+        //Read it back again
+        let mut reader = IvsReader::new(&buf[..]).unwrap();
+        assert_eq!(reader.version(), IvsVersion::V2);
+
+        let rec = reader.next_record().unwrap().unwrap();
+        assert_eq!(rec.bssid, Some(BSSID_A));
+        assert_eq!(rec.iv, [1, 2, 3]);
+        assert_eq!(rec.keystream, [4; 16]);
+
+        let rec = reader.next_record().unwrap().unwrap();
+        assert_eq!(rec.bssid, Some(BSSID_A));
+        assert_eq!(rec.iv, [5, 6, 7]);
+        assert_eq!(rec.keystream, [8; 16]);
+
+        let rec = reader.next_record().unwrap().unwrap();
+        assert_eq!(rec.bssid, Some(BSSID_B));
+        assert_eq!(rec.iv, [9, 10, 11]);
+        assert_eq!(rec.keystream, [12; 20]);
+
+        assert!(reader.next_record().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_ivs1_read() {
+        let mut buf = Vec::from(IVS1_MAGIC);
+        buf.extend_from_slice(&[0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 1, 2, 3, 4, 5]);
+        buf.extend_from_slice(&[0xff, 6, 7, 8, 9, 10]);
+
+        let mut reader = IvsReader::new(&buf[..]).unwrap();
+        assert_eq!(reader.version(), IvsVersion::V1);
+
+        let rec = reader.next_record().unwrap().unwrap();
+        assert_eq!(rec.bssid, Some([0x00, 0x11, 0x22, 0x33, 0x44, 0x55]));
+        assert_eq!(rec.iv, [1, 2, 3]);
+        assert_eq!(rec.keystream, [4, 5]);
+
+        let rec = reader.next_record().unwrap().unwrap();
+        assert_eq!(rec.bssid, Some([0x00, 0x11, 0x22, 0x33, 0x44, 0x55]));
+        assert_eq!(rec.iv, [6, 7, 8]);
+        assert_eq!(rec.keystream, [9, 10]);
+
+        assert!(reader.next_record().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_truncated_ivs() {
+        let mut buf = Vec::new();
+        {
+            let mut writer = IvsWriter::new(&mut buf).unwrap();
+            writer.write_record(&[0; 6], &[1, 2, 3], &[4; 16]).unwrap();
+            writer.write_record(&[0; 6], &[5, 6, 7], &[8; 16]).unwrap();
+        }
+
+        //A file cut off in the middle of the last record should end after the last complete one
+        for cut in [1, 22] {
+            let mut reader = IvsReader::new(&buf[..buf.len() - cut]).unwrap();
+            assert_eq!(reader.next_record().unwrap().unwrap().iv, [1, 2, 3]);
+            assert!(reader.next_record().unwrap().is_none());
+        }
+    }
+}
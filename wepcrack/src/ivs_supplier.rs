@@ -0,0 +1,116 @@
+use std::{
+    fs::File,
+    io::BufReader,
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use anyhow::Context;
+use ieee80211::MacAddress;
+
+use crate::{
+    ivs::{IvsReader, IvsRecord, IvsVersion},
+    keycracker::KeystreamSample,
+};
+
+pub struct IvsSampleSupplier {
+    reader: IvsReader<BufReader<File>>,
+    bssid: Option<MacAddress>,
+
+    num_records: usize,
+    num_samples: usize,
+    is_exhausted: bool,
+    error: Option<anyhow::Error>,
+}
+
+impl IvsSampleSupplier {
+    pub fn open(path: &Path, bssid: Option<MacAddress>) -> anyhow::Result<IvsSampleSupplier> {
+        Ok(IvsSampleSupplier {
+            reader: IvsReader::open(path).context("failed to open IVS file")?,
+            bssid,
+
+            num_records: 0,
+            num_samples: 0,
+            is_exhausted: false,
+            error: None,
+        })
+    }
+
+    pub const fn version(&self) -> IvsVersion {
+        self.reader.version()
+    }
+
+    pub const fn num_records(&self) -> usize {
+        self.num_records
+    }
+
+    pub const fn num_samples(&self) -> usize {
+        self.num_samples
+    }
+
+    pub const fn is_exhausted(&self) -> bool {
+        self.is_exhausted
+    }
+
+    pub fn take_error(&mut self) -> Option<anyhow::Error> {
+        self.error.take()
+    }
+
+    pub fn provide_sample(&mut self, should_exit: &AtomicBool) -> Option<KeystreamSample> {
+        while !self.is_exhausted && !should_exit.load(Ordering::SeqCst) {
+            //Read the next record from the IVS file
+            //Samples can't carry errors, so stop reading and keep the error around for the caller
+            let record = match self
+                .reader
+                .next_record()
+                .context("failed to read record from IVS file")
+            {
+                Ok(Some(record)) => record,
+                Ok(None) => {
+                    self.is_exhausted = true;
+                    break;
+                }
+                Err(err) => {
+                    self.is_exhausted = true;
+                    self.error = Some(err);
+                    break;
+                }
+            };
+            self.num_records += 1;
+
+            //Try to turn it into a sample
+            if let Some(sample) = self.recover_sample(&record) {
+                self.num_samples += 1;
+                return Some(sample);
+            }
+        }
+
+        None
+    }
+
+    fn recover_sample(&self, record: &IvsRecord) -> Option<KeystreamSample> {
+        //Check if the record belongs to the target network
+        if let Some(bssid) = self.bssid {
+            if record.bssid.as_ref().map(|bssid| &bssid[..]) != Some(bssid.as_bytes()) {
+                return None;
+            }
+        }
+
+        //Records from version 1 files (or short frames) don't have enough keystream bytes
+        if record.keystream.len() < KeystreamSample::MIN_KEYSTREAM_LEN {
+            return None;
+        }
+        let keystream =
+            &record.keystream[..record.keystream.len().min(KeystreamSample::KEYSTREAM_LEN)];
+
+        //IVS files only contain keystreams which the capturing tool was sure about
+        let mut sample = KeystreamSample {
+            iv: record.iv,
+            keystream_len: keystream.len(),
+            confidence: 1.,
+            ..Default::default()
+        };
+        sample.keystream[..keystream.len()].copy_from_slice(keystream);
+        Some(sample)
+    }
+}
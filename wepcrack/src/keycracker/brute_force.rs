@@ -0,0 +1,296 @@
+use std::{
+    collections::BTreeSet,
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::wep::WepKey;
+
+use super::TestSampleBuffer;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct BruteForceCheckpoint {
+    //All keys before this one have been tested
+    pub next_key: u64,
+}
+
+impl BruteForceCheckpoint {
+    pub fn load(path: &Path) -> anyhow::Result<BruteForceCheckpoint> {
+        let checkpoint: BruteForceCheckpoint = serde_json::from_reader(BufReader::new(
+            File::open(path).context("failed to open brute force checkpoint file")?,
+        ))
+        .context("failed to parse brute force checkpoint file")?;
+
+        if checkpoint.next_key > BruteForceAttack::NUM_KEYS {
+            anyhow::bail!("brute force checkpoint is outside of the key space");
+        }
+        Ok(checkpoint)
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        //Write to a temporary file first, so that we never leave a half-written checkpoint behind
+        let tmp_path = path.with_extension("tmp");
+        {
+            let mut writer = BufWriter::new(
+                File::create(&tmp_path).context("failed to create temporary checkpoint file")?,
+            );
+            serde_json::to_writer(&mut writer, self).context("failed to write checkpoint file")?;
+            writer.flush().context("failed to write checkpoint file")?;
+        }
+
+        std::fs::rename(&tmp_path, path).context("failed to replace checkpoint file")
+    }
+}
+
+#[derive(Default)]
+struct CompletedChunks {
+    //All chunks before the frontier have been tested
+    frontier: u64,
+    chunks: BTreeSet<u64>,
+}
+
+struct BruteForceState {
+    test_sample_buf: TestSampleBuffer,
+    start_key: u64,
+    next_chunk: AtomicU64,
+
+    num_tested_keys: AtomicU64,
+    completed_chunks: Mutex<CompletedChunks>,
+    cracked_key: Mutex<Option<WepKey>>,
+    should_stop: AtomicBool,
+}
+
+pub struct BruteForceAttack {
+    workers: Vec<JoinHandle<()>>,
+    state: Arc<BruteForceState>,
+}
+
+impl BruteForceAttack {
+    pub const NUM_KEYS: u64 = 1 << (8 * WepKey::LEN_40);
+
+    const CHUNK_SIZE: u64 = 1 << 20;
+    const BLOCK_SIZE: u64 = 1 << 12;
+
+    //Number of samples whose first keystream byte has to match before running the full test
+    //An incorrect key only passes each of them with a chance of 1/256
+    const NUM_EARLY_REJECT_SAMPLES: usize = 2;
+
+    pub fn launch(
+        test_sample_buf: TestSampleBuffer,
+        checkpoint: Option<&BruteForceCheckpoint>,
+    ) -> BruteForceAttack {
+        let start_key = checkpoint.map_or(0, |checkpoint| checkpoint.next_key);
+        let state = Arc::new(BruteForceState {
+            test_sample_buf,
+            start_key,
+            next_chunk: AtomicU64::new(0),
+
+            num_tested_keys: AtomicU64::new(0),
+            completed_chunks: Mutex::new(CompletedChunks::default()),
+            cracked_key: Mutex::new(None),
+            should_stop: AtomicBool::new(false),
+        });
+
+        //Launch one worker per CPU
+        let num_workers = std::thread::available_parallelism().map_or(1, |num| num.get());
+        let workers = (0..num_workers)
+            .map(|idx| {
+                let state = state.clone();
+                std::thread::Builder::new()
+                    .name(format!("brute force thread {idx}"))
+                    .spawn(move || Self::worker_thread_func(&state))
+                    .expect("failed to spawn brute force thread")
+            })
+            .collect();
+
+        BruteForceAttack { workers, state }
+    }
+
+    fn worker_thread_func(state: &BruteForceState) {
+        loop {
+            //Claim the next chunk of the key space
+            let chunk = state.next_chunk.fetch_add(1, Ordering::SeqCst);
+            let chunk_start = state.start_key + chunk * Self::CHUNK_SIZE;
+            if chunk_start >= Self::NUM_KEYS {
+                return;
+            }
+            let chunk_end = (chunk_start + Self::CHUNK_SIZE).min(Self::NUM_KEYS);
+
+            //Test all keys in the chunk, block by block
+            for block_start in (chunk_start..chunk_end).step_by(Self::BLOCK_SIZE as usize) {
+                if state.should_stop.load(Ordering::SeqCst) {
+                    return;
+                }
+
+                let block_end = (block_start + Self::BLOCK_SIZE).min(chunk_end);
+                let keys = (block_start..block_end)
+                    .map(Self::key_from_index)
+                    .collect::<Vec<_>>();
+
+                //Only run the full test for keys which produce the correct first keystream bytes
+                let keys = keys
+                    .iter()
+                    .zip(
+                        state
+                            .test_sample_buf
+                            .test_first_keystream_bytes(&keys, Self::NUM_EARLY_REJECT_SAMPLES),
+                    )
+                    .filter_map(|(key, is_candidate)| is_candidate.then_some(*key))
+                    .collect::<Vec<_>>();
+
+                let cracked_key = keys
+                    .iter()
+                    .zip(state.test_sample_buf.test_wep_keys(&keys))
+                    .find_map(|(key, is_correct)| is_correct.then_some(*key));
+
+                if let Some(key) = cracked_key {
+                    //We found the key! Cancel all other workers
+                    *state
+                        .cracked_key
+                        .lock()
+                        .expect("failed to lock cracked key") = Some(key);
+                    state.should_stop.store(true, Ordering::SeqCst);
+                }
+
+                state
+                    .num_tested_keys
+                    .fetch_add(block_end - block_start, Ordering::SeqCst);
+            }
+
+            //Mark the chunk as completed
+            let mut completed = state
+                .completed_chunks
+                .lock()
+                .expect("failed to lock completed chunks");
+            let completed = &mut *completed;
+
+            completed.chunks.insert(chunk);
+            while completed.chunks.remove(&completed.frontier) {
+                completed.frontier += 1;
+            }
+        }
+    }
+
+    fn key_from_index(idx: u64) -> WepKey {
+        let mut key = [0u8; WepKey::LEN_40];
+        key.copy_from_slice(&idx.to_be_bytes()[8 - WepKey::LEN_40..]);
+        WepKey::Wep40Key(key)
+    }
+
+    pub fn is_running(&self) -> bool {
+        !self.state.should_stop.load(Ordering::SeqCst)
+            && !self.workers.iter().all(JoinHandle::is_finished)
+    }
+
+    pub fn cracked_key(&self) -> Option<WepKey> {
+        *self
+            .state
+            .cracked_key
+            .lock()
+            .expect("failed to lock cracked key")
+    }
+
+    pub fn num_tested_keys(&self) -> u64 {
+        //Include the keys which have already been tested before the checkpoint
+        self.state.start_key + self.state.num_tested_keys.load(Ordering::SeqCst)
+    }
+
+    pub fn progress(&self) -> f64 {
+        self.num_tested_keys() as f64 / Self::NUM_KEYS as f64
+    }
+
+    pub fn checkpoint(&self) -> BruteForceCheckpoint {
+        //Workers might finish chunks out of order, so only include chunks up to the first untested one
+        let frontier = self
+            .state
+            .completed_chunks
+            .lock()
+            .expect("failed to lock completed chunks")
+            .frontier;
+
+        BruteForceCheckpoint {
+            next_key: (self.state.start_key + frontier * Self::CHUNK_SIZE).min(Self::NUM_KEYS),
+        }
+    }
+
+    pub fn shutdown(&mut self) {
+        //Cancel all workers, and wait for them to exit
+        self.state.should_stop.store(true, Ordering::SeqCst);
+
+        for worker in self.workers.drain(..) {
+            if let Err(err) = worker.join() {
+                std::panic::resume_unwind(err);
+            }
+        }
+    }
+}
+
+impl Drop for BruteForceAttack {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keycracker::KeystreamSample;
+
+    fn run_attack(key: &WepKey, checkpoint: &BruteForceCheckpoint) -> BruteForceAttack {
+        let mut test_sample_buf = TestSampleBuffer::new(8, 1, 1., 0);
+        for i in 0..8 {
+            let iv = [i, 0x42, 0x13];
+            let mut keystream = [0u8; KeystreamSample::KEYSTREAM_LEN];
+            key.create_rc4(&iv).gen_keystream(&mut keystream);
+            test_sample_buf.accept_sample(&KeystreamSample {
+                keystream,
+                keystream_len: KeystreamSample::KEYSTREAM_LEN,
+                iv,
+                confidence: 1.,
+            });
+        }
+
+        let mut attack = BruteForceAttack::launch(test_sample_buf, Some(checkpoint));
+        while attack.is_running() {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        attack.shutdown();
+        attack
+    }
+
+    #[test]
+    fn test_brute_force_checkpoint() {
+        assert_eq!(
+            BruteForceAttack::key_from_index(0x01_2345_6789),
+            WepKey::Wep40Key([0x01, 0x23, 0x45, 0x67, 0x89])
+        );
+        assert_eq!(
+            BruteForceAttack::key_from_index(BruteForceAttack::NUM_KEYS - 1),
+            WepKey::Wep40Key([0xff; 5])
+        );
+
+        //Resuming from a checkpoint only searches the rest of the key space
+        let checkpoint = BruteForceCheckpoint {
+            next_key: BruteForceAttack::NUM_KEYS - BruteForceAttack::CHUNK_SIZE,
+        };
+
+        let key = BruteForceAttack::key_from_index(BruteForceAttack::NUM_KEYS - 42);
+        let attack = run_attack(&key, &checkpoint);
+        assert_eq!(attack.cracked_key(), Some(key));
+
+        let key = BruteForceAttack::key_from_index(checkpoint.next_key - 1);
+        let attack = run_attack(&key, &checkpoint);
+        assert_eq!(attack.cracked_key(), None);
+        assert_eq!(attack.num_tested_keys(), BruteForceAttack::NUM_KEYS);
+        assert_eq!(attack.checkpoint().next_key, BruteForceAttack::NUM_KEYS);
+    }
+}
@@ -0,0 +1,505 @@
+use std::{
+    str::FromStr,
+    sync::{atomic::AtomicBool, Arc},
+    time::Duration,
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::wep::{WepFrame, WepKey};
+
+use super::{
+    CandidateKeyBatch, KeyBytePrediction, KeyPredictor, KeyTestPool, KeyTester, KeystreamSample,
+    SigmaVoteTable, TestSampleBuffer,
+};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyCrackerSettings {
+    //Length of the key to crack in bytes
+    //Shorter keys are still found as long as their length is also supported
+    pub key_len: usize,
+
+    //Sample collection settings
+    pub key_predictor_normal_threshold: f64,
+    pub key_predictor_strong_threshold: f64,
+    pub min_sample_confidence: f64,
+
+    //Test buffer settings
+    pub num_test_samples: usize,
+    pub test_sample_period: usize,
+    pub test_sample_threshold: f64,
+
+    //Number of full frames to verify candidate keys with using their ICV (0 to use keystream samples instead)
+    pub num_test_frames: usize,
+
+    //Candidate key testing settings
+    pub num_sigma_candidates: usize,
+    pub max_candidate_keys: usize,
+}
+
+impl Default for KeyCrackerSettings {
+    fn default() -> Self {
+        KeyCrackerSettings {
+            key_len: WepKey::LEN_104,
+            key_predictor_normal_threshold: 0.075,
+            key_predictor_strong_threshold: 0.025,
+            min_sample_confidence: 0.5,
+            num_test_samples: 1024,
+            test_sample_period: 128,
+            test_sample_threshold: 0.25,
+            num_test_frames: 0,
+
+            num_sigma_candidates: 4,
+            max_candidate_keys: 1 << 16,
+        }
+    }
+}
+
+impl KeyCrackerSettings {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        fn check_fraction(name: &str, val: f64) -> anyhow::Result<()> {
+            if !(val > 0. && val <= 1.) {
+                anyhow::bail!("{name} must be in the range (0, 1], but is {val}");
+            }
+            Ok(())
+        }
+
+        if !WepKey::KEY_LENGTHS.contains(&self.key_len) {
+            anyhow::bail!(
+                "key_len must be one of {:?}, but is {}",
+                WepKey::KEY_LENGTHS,
+                self.key_len
+            );
+        }
+
+        check_fraction(
+            "key_predictor_normal_threshold",
+            self.key_predictor_normal_threshold,
+        )?;
+        check_fraction(
+            "key_predictor_strong_threshold",
+            self.key_predictor_strong_threshold,
+        )?;
+        check_fraction("test_sample_threshold", self.test_sample_threshold)?;
+
+        if !(0. ..=1.).contains(&self.min_sample_confidence) {
+            anyhow::bail!(
+                "min_sample_confidence must be in the range [0, 1], but is {}",
+                self.min_sample_confidence
+            );
+        }
+
+        if self.num_test_samples == 0 {
+            anyhow::bail!("num_test_samples must be at least 1");
+        }
+        if self.test_sample_period == 0 {
+            anyhow::bail!("test_sample_period must be at least 1");
+        }
+        if self.num_sigma_candidates == 0 {
+            anyhow::bail!("num_sigma_candidates must be at least 1");
+        }
+        if self.max_candidate_keys == 0 {
+            anyhow::bail!("max_candidate_keys must be at least 1");
+        }
+
+        Ok(())
+    }
+
+    //Ensures that samples of a source which recovers at most the given number of keystream bytes can vote for every key byte
+    pub fn check_keystream_len(&self, max_keystream_len: usize) -> anyhow::Result<()> {
+        let required_len = KeystreamSample::required_keystream_len(self.key_len - 1);
+        if max_keystream_len < required_len {
+            anyhow::bail!(
+                "cracking {}-byte keys requires {required_len} keystream bytes per sample, but the sample source only recovers up to {max_keystream_len}",
+                self.key_len
+            );
+        }
+        Ok(())
+    }
+}
+
+pub type KeyCrackerSampleProvider = dyn FnMut(&AtomicBool) -> Option<KeystreamSample> + Send + Sync;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum KeyCrackerPhase {
+    SampleCollection,
+    CandidateKeyTesting,
+    FinishedSuccess,
+    FinishedFailure,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct KeyCrackerSnapshot {
+    pub phase: KeyCrackerPhase,
+    pub settings: KeyCrackerSettings,
+
+    pub votes: SigmaVoteTable,
+    pub test_samples: Vec<KeystreamSample>,
+    #[serde(default)]
+    pub test_frames: Vec<WepFrame>,
+
+    pub num_tested_keys: usize,
+    pub cracked_key: Option<String>,
+}
+
+pub struct KeyCracker {
+    phase: KeyCrackerPhase,
+    delay_timer: usize,
+
+    settings: KeyCrackerSettings,
+    sample_provider: Box<KeyCrackerSampleProvider>,
+    should_exit: Arc<AtomicBool>,
+
+    key_predictor: KeyPredictor,
+    test_sample_buf: TestSampleBuffer,
+    key_tester: Option<KeyTester>,
+    key_test_pool: Option<KeyTestPool>,
+    num_skipped_keys: usize,
+    dispatched_all_keys: bool,
+    num_dropped_samples: usize,
+
+    cracked_key: Option<WepKey>,
+}
+
+impl KeyCracker {
+    pub fn new(
+        settings: KeyCrackerSettings,
+        sample_provider: Box<KeyCrackerSampleProvider>,
+        should_exit: Arc<AtomicBool>,
+    ) -> KeyCracker {
+        KeyCracker {
+            phase: KeyCrackerPhase::SampleCollection,
+            delay_timer: 0,
+
+            settings,
+            sample_provider,
+            should_exit,
+
+            key_predictor: KeyPredictor::new(settings.key_len),
+            test_sample_buf: TestSampleBuffer::new(
+                settings.num_test_samples,
+                settings.test_sample_period,
+                settings.test_sample_threshold,
+                settings.num_test_frames,
+            ),
+            key_tester: None,
+            key_test_pool: None,
+            num_skipped_keys: 0,
+            dispatched_all_keys: false,
+            num_dropped_samples: 0,
+
+            cracked_key: None,
+        }
+    }
+
+    pub fn restore(
+        settings: KeyCrackerSettings,
+        sample_provider: Box<KeyCrackerSampleProvider>,
+        should_exit: Arc<AtomicBool>,
+        snapshot: &KeyCrackerSnapshot,
+    ) -> anyhow::Result<KeyCracker> {
+        let mut cracker = KeyCracker::new(settings, sample_provider, should_exit);
+
+        //Restore the key predictor
+        cracker.key_predictor = KeyPredictor::from_vote_table(&snapshot.votes)?;
+        if cracker.key_predictor.key_len() != settings.key_len {
+            anyhow::bail!(
+                "snapshot has votes for {}-byte keys, but {}-byte keys are being cracked",
+                cracker.key_predictor.key_len(),
+                settings.key_len
+            );
+        }
+
+        //Restore the test sample buffer
+        cracker
+            .test_sample_buf
+            .restore_samples(&snapshot.test_samples);
+        for frame in &snapshot.test_frames {
+            cracker.test_sample_buf.accept_frame(frame);
+        }
+
+        //Restore the phase
+        match snapshot.phase {
+            KeyCrackerPhase::SampleCollection => {}
+            KeyCrackerPhase::CandidateKeyTesting => {
+                //Candidate keys are enumerated in a different order if the candidate settings changed
+                let num_tested_keys =
+                    if settings.num_sigma_candidates == snapshot.settings.num_sigma_candidates {
+                        snapshot.num_tested_keys
+                    } else {
+                        0
+                    };
+                cracker.start_key_testing(num_tested_keys);
+            }
+            KeyCrackerPhase::FinishedSuccess => {
+                let key = snapshot
+                    .cracked_key
+                    .as_deref()
+                    .context("snapshot of a successful cracker has no cracked key")?;
+
+                cracker.phase = KeyCrackerPhase::FinishedSuccess;
+                cracker.cracked_key = Some(WepKey::from_str(key)?);
+            }
+            KeyCrackerPhase::FinishedFailure => cracker.phase = KeyCrackerPhase::FinishedFailure,
+        }
+
+        Ok(cracker)
+    }
+
+    pub fn snapshot(&self) -> KeyCrackerSnapshot {
+        KeyCrackerSnapshot {
+            phase: self.phase,
+            settings: self.settings,
+
+            votes: self.key_predictor.export_votes(),
+            test_samples: self.test_sample_buf.samples().copied().collect(),
+            test_frames: self.test_sample_buf.frames().cloned().collect(),
+
+            num_tested_keys: self.num_skipped_keys
+                + self
+                    .key_test_pool
+                    .as_ref()
+                    .map_or(0, |pool| pool.num_contiguous_tested_keys()),
+            cracked_key: self.cracked_key.map(|key| hex::encode(key.key())),
+        }
+    }
+
+    pub const fn settings(&self) -> &KeyCrackerSettings {
+        &self.settings
+    }
+
+    pub const fn phase(&self) -> KeyCrackerPhase {
+        self.phase
+    }
+
+    pub const fn is_running(&self) -> bool {
+        !matches!(
+            self.phase,
+            KeyCrackerPhase::FinishedSuccess | KeyCrackerPhase::FinishedFailure
+        )
+    }
+
+    pub const fn key_predictor(&self) -> &KeyPredictor {
+        &self.key_predictor
+    }
+
+    pub const fn test_sample_buf(&self) -> &TestSampleBuffer {
+        &self.test_sample_buf
+    }
+
+    pub const fn key_tester(&self) -> Option<&KeyTester> {
+        self.key_tester.as_ref()
+    }
+
+    pub const fn cracked_key(&self) -> Option<&WepKey> {
+        self.cracked_key.as_ref()
+    }
+
+    pub const fn num_dropped_samples(&self) -> usize {
+        self.num_dropped_samples
+    }
+
+    pub fn num_tested_keys(&self) -> usize {
+        self.num_skipped_keys
+            + self
+                .key_test_pool
+                .as_ref()
+                .map_or(0, |pool| pool.num_tested_keys())
+    }
+
+    pub fn progress(&self) -> f64 {
+        match self.phase {
+            KeyCrackerPhase::SampleCollection => {
+                //Aggregate progress of all key bytes towards the threshold
+                self.key_predictor
+                    .key_byte_infos()
+                    .iter()
+                    .map(|info| {
+                        (info.prediction_score()
+                            / (if matches!(
+                                info.prediction(),
+                                KeyBytePrediction::Normal { sigma: _ }
+                            ) {
+                                self.settings.key_predictor_normal_threshold
+                            } else {
+                                self.settings.key_predictor_strong_threshold
+                            }))
+                        .min(1.)
+                    })
+                    .sum::<f64>()
+                    / self.key_predictor.key_byte_infos().len() as f64
+            }
+            KeyCrackerPhase::CandidateKeyTesting => {
+                let tester = self.key_tester.as_ref().unwrap();
+                self.num_tested_keys() as f64 / tester.num_keys() as f64
+            }
+            KeyCrackerPhase::FinishedSuccess => 1.,
+            KeyCrackerPhase::FinishedFailure => 1.,
+        }
+    }
+
+    fn check_if_ready(&mut self) {
+        if self.test_sample_buf.is_full()
+            && self.key_predictor.key_byte_infos().iter().all(|info| {
+                info.prediction_score()
+                    >= if matches!(info.prediction(), KeyBytePrediction::Normal { sigma: _ }) {
+                        self.settings.key_predictor_normal_threshold
+                    } else {
+                        self.settings.key_predictor_strong_threshold
+                    }
+            })
+        {
+            //Move onto testing candidate keys
+            self.start_key_testing(0);
+        }
+    }
+
+    pub fn merge_votes(&mut self, table: &SigmaVoteTable) -> anyhow::Result<()> {
+        if self.phase != KeyCrackerPhase::SampleCollection {
+            anyhow::bail!("can only merge votes while collecting samples");
+        }
+
+        self.key_predictor.merge_votes(table)
+    }
+
+    pub fn skip_to_key_testing(&mut self) -> anyhow::Result<()> {
+        if self.phase != KeyCrackerPhase::SampleCollection {
+            return Ok(());
+        }
+        if self.key_predictor.num_samples() == 0 || self.test_sample_buf.is_empty() {
+            anyhow::bail!("can't test candidate keys without any samples");
+        }
+
+        self.start_key_testing(0);
+        Ok(())
+    }
+
+    pub fn accept_test_frame(&mut self, frame: &WepFrame) {
+        //The test buffer is handed to the key testers once key testing starts
+        if self.phase == KeyCrackerPhase::SampleCollection {
+            self.test_sample_buf.accept_frame(frame);
+        }
+    }
+
+    fn start_key_testing(&mut self, num_skipped_keys: usize) {
+        let mut tester = KeyTester::new(
+            &self.key_predictor,
+            self.settings.num_sigma_candidates,
+            self.settings.max_candidate_keys,
+        );
+
+        //Skip keys which have already been tested
+        let num_skipped_keys = num_skipped_keys.min(tester.num_keys());
+        for _ in 0..num_skipped_keys {
+            if !tester.advance_to_next_key() {
+                self.dispatched_all_keys = true;
+                break;
+            }
+        }
+
+        self.phase = KeyCrackerPhase::CandidateKeyTesting;
+        self.key_tester = Some(tester);
+        self.key_test_pool = Some(KeyTestPool::launch(self.test_sample_buf.clone()));
+        self.num_skipped_keys = num_skipped_keys;
+    }
+
+    pub fn do_work(&mut self) {
+        match self.phase {
+            KeyCrackerPhase::SampleCollection => {
+                //Collect a sample and feed it to the predictor and test sample buffer
+                let Some(sample) = (self.sample_provider)(self.should_exit.as_ref()) else {
+                    //Also check if we're ready once the provider runs dry
+                    //Otherwise finite sample sources (e.g. capture files) might never be checked
+                    if self.delay_timer > 0 {
+                        self.delay_timer = 0;
+                        self.check_if_ready();
+                    }
+                    return;
+                };
+
+                //Drop samples whose plaintext we weren't sure enough about
+                //Wrong keystreams only add random votes, which slow down (or even prevent) convergence
+                if sample.confidence < self.settings.min_sample_confidence {
+                    self.num_dropped_samples += 1;
+                    return;
+                }
+
+                self.key_predictor.accept_sample(&sample);
+                self.test_sample_buf.accept_sample(&sample);
+
+                //Occasionally check if we collected enough samples
+                const READY_CHECK_PERIOD: usize = 2048;
+
+                self.delay_timer += 1;
+                if self.delay_timer >= READY_CHECK_PERIOD {
+                    self.delay_timer = 0;
+                    self.check_if_ready();
+                }
+            }
+            KeyCrackerPhase::CandidateKeyTesting => {
+                let tester = self.key_tester.as_mut().unwrap();
+                let pool = self.key_test_pool.as_mut().unwrap();
+
+                //Check if one of the workers found the key
+                if let Some(key) = pool.cracked_key() {
+                    //We found the key!
+                    self.phase = KeyCrackerPhase::FinishedSuccess;
+                    self.cracked_key = Some(key);
+                    pool.shutdown();
+                    return;
+                }
+
+                if self.dispatched_all_keys {
+                    if pool.num_pending_keys() == 0 {
+                        //We went through all keys and didn't find one which matches :/
+                        self.phase = KeyCrackerPhase::FinishedFailure;
+                        pool.shutdown();
+                    } else {
+                        std::thread::sleep(Duration::from_millis(1));
+                    }
+                    return;
+                }
+
+                //Don't get too far ahead of the workers
+                const BATCH_SIZE: usize = 64;
+
+                if pool.num_pending_keys() >= 4 * BATCH_SIZE * pool.num_workers() {
+                    std::thread::sleep(Duration::from_millis(1));
+                    return;
+                }
+
+                //Hand out the next batch of keys to the workers
+                let mut batch = CandidateKeyBatch::with_capacity(BATCH_SIZE);
+                while batch.len() < BATCH_SIZE && !self.dispatched_all_keys {
+                    batch.push(tester.current_key());
+                    self.dispatched_all_keys = !tester.advance_to_next_key();
+                }
+                pool.dispatch(batch);
+            }
+            KeyCrackerPhase::FinishedSuccess | KeyCrackerPhase::FinishedFailure => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plaintext::MAX_PREDICTED_PLAINTEXT_LEN;
+
+    #[test]
+    fn test_check_keystream_len() {
+        //Samples recovered using known plaintext are too short for 232-bit keys, so such sources are refused
+        let settings = KeyCrackerSettings {
+            key_len: WepKey::LEN_232,
+            ..KeyCrackerSettings::default()
+        };
+        assert!(settings
+            .check_keystream_len(MAX_PREDICTED_PLAINTEXT_LEN)
+            .is_err());
+        assert!(settings
+            .check_keystream_len(KeystreamSample::KEYSTREAM_LEN)
+            .is_ok());
+    }
+}
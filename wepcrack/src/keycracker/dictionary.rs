@@ -0,0 +1,106 @@
+use std::time::Duration;
+
+use crate::{keygen, wep::WepKey};
+
+use super::{CandidateKey, CandidateKeyBatch, KeyTestPool, TestSampleBuffer};
+
+pub type PassphraseProvider = dyn Iterator<Item = anyhow::Result<Vec<u8>>> + Send;
+
+pub struct DictionaryAttack {
+    passphrases: Box<PassphraseProvider>,
+    key_test_pool: Option<KeyTestPool>,
+
+    num_passphrases: usize,
+    dispatched_all_keys: bool,
+    is_running: bool,
+    cracked_key: Option<WepKey>,
+}
+
+impl DictionaryAttack {
+    pub fn new(
+        test_sample_buf: TestSampleBuffer,
+        passphrases: Box<PassphraseProvider>,
+    ) -> DictionaryAttack {
+        DictionaryAttack {
+            passphrases,
+            key_test_pool: Some(KeyTestPool::launch(test_sample_buf)),
+
+            num_passphrases: 0,
+            dispatched_all_keys: false,
+            is_running: true,
+            cracked_key: None,
+        }
+    }
+
+    pub const fn is_running(&self) -> bool {
+        self.is_running
+    }
+
+    pub const fn cracked_key(&self) -> Option<&WepKey> {
+        self.cracked_key.as_ref()
+    }
+
+    pub const fn num_passphrases(&self) -> usize {
+        self.num_passphrases
+    }
+
+    pub fn num_tested_keys(&self) -> usize {
+        self.key_test_pool
+            .as_ref()
+            .map_or(0, |pool| pool.num_tested_keys())
+    }
+
+    pub fn do_work(&mut self) -> anyhow::Result<()> {
+        let Some(pool) = &mut self.key_test_pool else {
+            return Ok(());
+        };
+
+        //Check if a worker found the key
+        if let Some(key) = pool.cracked_key() {
+            self.cracked_key = Some(key);
+            self.is_running = false;
+            pool.shutdown();
+            return Ok(());
+        }
+
+        if self.dispatched_all_keys {
+            if pool.num_pending_keys() == 0 {
+                //None of the passphrases matched :/
+                self.is_running = false;
+                pool.shutdown();
+            } else {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            return Ok(());
+        }
+
+        //Don't get too far ahead of the workers
+        const BATCH_SIZE: usize = 64;
+
+        if pool.num_pending_keys() >= 4 * BATCH_SIZE * pool.num_workers() {
+            std::thread::sleep(Duration::from_millis(1));
+            return Ok(());
+        }
+
+        //Derive the keys of the next few passphrases and hand them out to the workers
+        let mut batch = CandidateKeyBatch::with_capacity(BATCH_SIZE);
+        while batch.len() < BATCH_SIZE {
+            let Some(passphrase) = self.passphrases.next().transpose()? else {
+                self.dispatched_all_keys = true;
+                break;
+            };
+            self.num_passphrases += 1;
+
+            batch.extend(
+                keygen::passphrase_keys(&passphrase)
+                    .iter()
+                    .map(CandidateKey::from_wep_key),
+            );
+        }
+
+        if !batch.is_empty() {
+            pool.dispatch(batch);
+        }
+        Ok(())
+    }
+}
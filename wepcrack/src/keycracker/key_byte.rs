@@ -21,9 +21,9 @@ pub enum KeyBytePrediction {
 }
 
 impl KeyBytePredictionInfo {
-    fn calc_p_correct() -> [f64; WepKey::LEN_104] {
+    fn calc_p_correct() -> [f64; WepKey::LEN_MAX] {
         //Calculate p_correct for all key bytes
-        let mut p_correct = [0f64; WepKey::LEN_104];
+        let mut p_correct = [0f64; WepKey::LEN_MAX];
 
         fn p_nopick_i(opts: i32) -> f64 {
             1. - opts as f64 / 256.
@@ -49,9 +49,18 @@ impl KeyBytePredictionInfo {
         votes: &[usize; 256],
         total_votes: usize,
     ) -> KeyBytePredictionInfo {
-        static P_CORRECT: OnceLock<[f64; WepKey::LEN_104]> = OnceLock::new();
+        static P_CORRECT: OnceLock<[f64; WepKey::LEN_MAX]> = OnceLock::new();
         let p_correct = P_CORRECT.get_or_init(KeyBytePredictionInfo::calc_p_correct);
 
+        //We can't predict anything without any votes
+        if total_votes == 0 {
+            return KeyBytePredictionInfo {
+                p_correct: p_correct[key_idx],
+                p_equal: 1f64 / 256f64,
+                ..Default::default()
+            };
+        }
+
         //Find the index of the candidate sigma (= the one with the most votes)
         let candidate_sigma = votes
             .iter()
@@ -102,7 +111,9 @@ impl KeyBytePredictionInfo {
     }
 
     pub fn prediction_score(&self) -> f64 {
-        if self.err_normal < self.err_strong {
+        if self.err_normal == self.err_strong {
+            0.
+        } else if self.err_normal < self.err_strong {
             (self.err_strong - self.err_normal) / self.err_normal
         } else {
             (self.err_normal - self.err_strong) / self.err_strong
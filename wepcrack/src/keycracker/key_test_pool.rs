@@ -0,0 +1,192 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+};
+
+use crate::wep::WepKey;
+
+use super::{CandidateKey, KeyTester, TestSampleBuffer};
+
+pub type CandidateKeyBatch = Vec<CandidateKey>;
+
+#[derive(Default)]
+struct CompletedBatches {
+    //All keys before the frontier have been tested
+    frontier: usize,
+    batches: BTreeMap<usize, usize>,
+}
+
+struct KeyTestPoolState {
+    test_sample_buf: TestSampleBuffer,
+    batch_recv: Mutex<Receiver<(usize, CandidateKeyBatch)>>,
+
+    num_tested_keys: AtomicUsize,
+    completed_batches: Mutex<CompletedBatches>,
+    cracked_key: Mutex<Option<WepKey>>,
+    should_stop: AtomicBool,
+}
+
+pub struct KeyTestPool {
+    workers: Vec<JoinHandle<()>>,
+    batch_sender: Option<Sender<(usize, CandidateKeyBatch)>>,
+    state: Arc<KeyTestPoolState>,
+
+    num_dispatched_keys: usize,
+}
+
+impl KeyTestPool {
+    pub fn launch(test_sample_buf: TestSampleBuffer) -> KeyTestPool {
+        let (batch_tx, batch_rx) = mpsc::channel();
+        let state = Arc::new(KeyTestPoolState {
+            test_sample_buf,
+            batch_recv: Mutex::new(batch_rx),
+
+            num_tested_keys: AtomicUsize::new(0),
+            completed_batches: Mutex::new(CompletedBatches::default()),
+            cracked_key: Mutex::new(None),
+            should_stop: AtomicBool::new(false),
+        });
+
+        //Launch one worker per CPU
+        let num_workers = std::thread::available_parallelism().map_or(1, |num| num.get());
+        let workers = (0..num_workers)
+            .map(|idx| {
+                let state = state.clone();
+                std::thread::Builder::new()
+                    .name(format!("key tester thread {idx}"))
+                    .spawn(move || Self::worker_thread_func(&state))
+                    .expect("failed to spawn key tester thread")
+            })
+            .collect();
+
+        KeyTestPool {
+            workers,
+            batch_sender: Some(batch_tx),
+            state,
+
+            num_dispatched_keys: 0,
+        }
+    }
+
+    fn worker_thread_func(state: &KeyTestPoolState) {
+        loop {
+            //Receive a batch of keys to test
+            let Ok((batch_start, batch)) = ({
+                let batch_recv = state
+                    .batch_recv
+                    .lock()
+                    .expect("failed to lock batch receiver");
+                batch_recv.recv()
+            }) else {
+                //The pool has been shut down
+                return;
+            };
+
+            //Test all keys in the batch, a few at a time so that they can be interleaved
+            for keys in batch.chunks(TestSampleBuffer::NUM_LANES) {
+                if state.should_stop.load(Ordering::SeqCst) {
+                    return;
+                }
+
+                if let Some(key) = KeyTester::test_keys(keys, &state.test_sample_buf) {
+                    //We found the key! Cancel all other workers
+                    *state
+                        .cracked_key
+                        .lock()
+                        .expect("failed to lock cracked key") = Some(key);
+                    state.should_stop.store(true, Ordering::SeqCst);
+                }
+
+                state
+                    .num_tested_keys
+                    .fetch_add(keys.len(), Ordering::SeqCst);
+            }
+
+            //Mark the batch as completed
+            let mut completed = state
+                .completed_batches
+                .lock()
+                .expect("failed to lock completed batches");
+            let completed = &mut *completed;
+
+            completed.batches.insert(batch_start, batch.len());
+            while let Some(batch_len) = completed.batches.remove(&completed.frontier) {
+                completed.frontier += batch_len;
+            }
+        }
+    }
+
+    pub fn num_workers(&self) -> usize {
+        self.workers.len()
+    }
+
+    pub const fn num_dispatched_keys(&self) -> usize {
+        self.num_dispatched_keys
+    }
+
+    pub fn num_tested_keys(&self) -> usize {
+        self.state.num_tested_keys.load(Ordering::SeqCst)
+    }
+
+    pub fn num_contiguous_tested_keys(&self) -> usize {
+        //Workers might finish batches out of order, so only count keys up to the first untested one
+        self.state
+            .completed_batches
+            .lock()
+            .expect("failed to lock completed batches")
+            .frontier
+    }
+
+    pub fn num_pending_keys(&self) -> usize {
+        self.num_dispatched_keys - self.num_tested_keys()
+    }
+
+    pub fn cracked_key(&self) -> Option<WepKey> {
+        *self
+            .state
+            .cracked_key
+            .lock()
+            .expect("failed to lock cracked key")
+    }
+
+    pub fn dispatch(&mut self, batch: CandidateKeyBatch) {
+        //Ensure no worker has crashed
+        if !self.state.should_stop.load(Ordering::SeqCst)
+            && self.workers.iter().any(JoinHandle::is_finished)
+        {
+            self.shutdown();
+            panic!("key tester thread exited prematurely");
+        }
+
+        let batch_start = self.num_dispatched_keys;
+        self.num_dispatched_keys += batch.len();
+        self.batch_sender
+            .as_ref()
+            .expect("key test pool has already been shut down")
+            .send((batch_start, batch))
+            .expect("failed to dispatch candidate key batch");
+    }
+
+    pub fn shutdown(&mut self) {
+        //Cancel all workers, and wait for them to exit
+        self.state.should_stop.store(true, Ordering::SeqCst);
+        self.batch_sender.take();
+
+        for worker in self.workers.drain(..) {
+            if let Err(err) = worker.join() {
+                std::panic::resume_unwind(err);
+            }
+        }
+    }
+}
+
+impl Drop for KeyTestPool {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
@@ -1,47 +1,196 @@
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::BinaryHeap,
+};
+
 use crate::wep::WepKey;
 
-use super::{KeyBytePrediction, TestSampleBuffer};
+use super::{KeyBytePrediction, KeyPredictor, TestSampleBuffer};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KeyByteCandidate {
+    Sigma(u8),
+    StrongLIndex(usize),
+}
+
+struct CandidateKeyState {
+    cost: f64,
+    key_len: usize,
+    cand_idxs: [usize; WepKey::LEN_MAX],
+    pivot: usize,
+}
+
+impl PartialEq for CandidateKeyState {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for CandidateKeyState {}
+
+impl PartialOrd for CandidateKeyState {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for CandidateKeyState {
+    fn cmp(&self, other: &Self) -> Ordering {
+        //Break ties by the candidate indices, so that the order of equally likely keys is deterministic
+        self.cost
+            .total_cmp(&other.cost)
+            .then_with(|| self.key_len.cmp(&other.key_len))
+            .then_with(|| self.cand_idxs.cmp(&other.cand_idxs))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CandidateKey {
+    pub key: [u8; WepKey::LEN_MAX],
+    pub key_len: usize,
+}
+
+impl CandidateKey {
+    pub fn from_wep_key(key: &WepKey) -> CandidateKey {
+        let mut key_bytes = [0u8; WepKey::LEN_MAX];
+        key_bytes[..key.key().len()].copy_from_slice(key.key());
+
+        CandidateKey {
+            key: key_bytes,
+            key_len: key.key().len(),
+        }
+    }
+
+    pub fn key(&self) -> &[u8] {
+        &self.key[..self.key_len]
+    }
+}
 
 pub struct KeyTester {
+    key_len: usize,
     num_keys: usize,
     cur_key_idx: usize,
-    cur_l_idxs: [usize; WepKey::LEN_104],
+    cur_state: CandidateKeyState,
 
-    key_predictions: [KeyBytePrediction; WepKey::LEN_104],
-    maybe_wep40: bool,
+    key_predictions: Vec<KeyBytePrediction>,
+    candidates: Vec<Vec<(KeyByteCandidate, f64)>>,
+    candidate_queue: BinaryHeap<Reverse<CandidateKeyState>>,
 }
 
 impl KeyTester {
-    pub fn new(key_predictions: [KeyBytePrediction; WepKey::LEN_104]) -> KeyTester {
+    pub fn new(
+        key_predictor: &KeyPredictor,
+        num_sigma_candidates: usize,
+        max_keys: usize,
+    ) -> KeyTester {
+        let key_len = key_predictor.key_len();
+        let key_predictions = key_predictor
+            .key_byte_infos()
+            .iter()
+            .map(|info| info.prediction())
+            .collect::<Vec<_>>();
+
+        //Determine the candidates for each key byte, sorted by their cost
+        //The cost of a candidate is how much less likely it is than the best candidate (as a negative log-likelihood)
+        let candidates: Vec<Vec<(KeyByteCandidate, f64)>> = (0..key_len)
+            .map(|idx| {
+                match key_predictions[idx] {
+                    //Strong bytes are resolved using their l-index, which has no votes to rank them by
+                    //As such, each of them is one of idx equally likely options, which keeps keys of different lengths comparable
+                    KeyBytePrediction::Strong if idx > 0 => (1..=idx)
+                        .map(|l_idx| (KeyByteCandidate::StrongLIndex(l_idx), (idx as f64).ln()))
+                        .collect(),
+
+                    //Otherwise try the top voted sigmas
+                    _ => {
+                        let info = key_predictor.key_byte_info(idx);
+                        let votes = key_predictor.sigma_votes(idx);
+
+                        let p_wrong = (1. - info.p_correct) / 255.;
+                        let vote_weight = (info.p_correct / p_wrong).ln().max(0.);
+
+                        let mut sigmas = (0..=255u8).collect::<Vec<_>>();
+                        sigmas.sort_by_key(|&sigma| {
+                            (
+                                Reverse(votes[sigma as usize]),
+                                sigma != info.candidate_sigma,
+                            )
+                        });
+
+                        let top_votes = votes[info.candidate_sigma as usize];
+                        sigmas
+                            .into_iter()
+                            .take(num_sigma_candidates.max(1))
+                            .map(|sigma| {
+                                let vote_diff = (top_votes - votes[sigma as usize]) as f64
+                                    / KeyPredictor::FULL_VOTE as f64;
+                                (KeyByteCandidate::Sigma(sigma), vote_diff * vote_weight)
+                            })
+                            .collect()
+                    }
+                }
+            })
+            .collect();
+
+        //Check which shorter keys the key could be
+        //The RC4 key of shorter keys wraps around to the IV, which makes the votes of the trailing bytes look random
+        let key_lens = WepKey::KEY_LENGTHS
+            .into_iter()
+            .filter(|&len| {
+                len == key_len
+                    || (len < key_len
+                        && key_predictions[len..]
+                            .iter()
+                            .all(|&pred| pred == KeyBytePrediction::Strong))
+            })
+            .collect::<Vec<_>>();
+
         //Determine the total number of keys
-        let mut num_keys = 1;
-        for (idx, &pred) in key_predictions.iter().enumerate() {
-            if pred == KeyBytePrediction::Strong {
-                num_keys *= idx;
-            }
-        }
-        assert!(num_keys >= 1);
+        let num_keys = key_lens
+            .iter()
+            .fold(0usize, |num_keys, &len| {
+                num_keys.saturating_add(candidates[..len].iter().fold(1usize, |num_keys, cands| {
+                    num_keys.saturating_mul(cands.len())
+                }))
+            })
+            .min(max_keys)
+            .max(1);
 
-        //Check if the key could be a WEP-40 key
-        let maybe_wep40 = key_predictions[WepKey::LEN_40..]
+        //Search the keys of every length separately, so that the guesses for the trailing bytes of longer keys don't crowd out shorter ones
+        let mut candidate_queue = key_lens
             .iter()
-            .all(|&pred| pred == KeyBytePrediction::Strong);
+            .map(|&len| {
+                Reverse(CandidateKeyState {
+                    cost: candidates[..len].iter().map(|cands| cands[0].1).sum(),
+                    key_len: len,
+                    cand_idxs: [0; WepKey::LEN_MAX],
+                    pivot: 0,
+                })
+            })
+            .collect::<BinaryHeap<_>>();
 
-        KeyTester {
-            cur_key_idx: 0,
+        //Start with the most likely key
+        let Reverse(cur_state) = candidate_queue.pop().unwrap();
+        let mut tester = KeyTester {
+            key_len,
             num_keys,
-            cur_l_idxs: key_predictions.map(|pred| match pred {
-                KeyBytePrediction::Strong => 1,
-                _ => usize::MAX,
-            }),
+            cur_key_idx: 0,
+            cur_state,
 
             key_predictions,
-            maybe_wep40,
-        }
+            candidates,
+            candidate_queue,
+        };
+        tester.push_successors();
+        tester
+    }
+
+    pub const fn key_len(&self) -> usize {
+        self.key_len
     }
 
-    pub const fn key_predictions(&self) -> [KeyBytePrediction; WepKey::LEN_104] {
-        self.key_predictions
+    pub fn key_predictions(&self) -> &[KeyBytePrediction] {
+        &self.key_predictions
     }
 
     pub const fn num_keys(&self) -> usize {
@@ -53,30 +202,48 @@ impl KeyTester {
     }
 
     pub const fn is_at_end(&self) -> bool {
-        self.cur_key_idx >= self.num_keys
+        self.cur_key_idx + 1 >= self.num_keys
     }
 
-    pub const fn is_maybe_wep40(&self) -> bool {
-        self.maybe_wep40
+    pub const fn current_key_len(&self) -> usize {
+        self.cur_state.key_len
     }
 
-    pub const fn current_l_indices(&self) -> [usize; WepKey::LEN_104] {
-        self.cur_l_idxs
+    pub fn current_l_indices(&self) -> Vec<usize> {
+        (0..self.cur_state.key_len)
+            .map(|i| match self.current_candidate(i) {
+                KeyByteCandidate::StrongLIndex(l_idx) => l_idx,
+                KeyByteCandidate::Sigma(_) => usize::MAX,
+            })
+            .collect()
     }
 
-    pub fn current_key(&self) -> [u8; WepKey::LEN_104] {
-        if self.is_at_end() {
-            panic!("tried to get current key of an end-state KeyTester");
-        }
+    pub fn current_sigma_ranks(&self) -> Vec<usize> {
+        (0..self.cur_state.key_len)
+            .map(|i| match self.current_candidate(i) {
+                KeyByteCandidate::Sigma(_) => self.cur_state.cand_idxs[i],
+                KeyByteCandidate::StrongLIndex(_) => usize::MAX,
+            })
+            .collect()
+    }
 
-        let mut key: [u8; WepKey::LEN_104] = [0; WepKey::LEN_104];
+    pub const fn current_cost(&self) -> f64 {
+        self.cur_state.cost
+    }
+
+    fn current_candidate(&self, idx: usize) -> KeyByteCandidate {
+        self.candidates[idx][self.cur_state.cand_idxs[idx]].0
+    }
+
+    pub fn current_key(&self) -> CandidateKey {
+        let mut key: [u8; WepKey::LEN_MAX] = [0; WepKey::LEN_MAX];
         let mut prev_sigma = 0u8;
-        for i in 0..WepKey::LEN_104 {
+        for i in 0..self.cur_state.key_len {
             //Get the sigma sum of the byte
-            let sigma = match self.key_predictions[i] {
-                KeyBytePrediction::Normal { sigma } => sigma,
-                KeyBytePrediction::Strong => {
-                    let inv_rk = (self.cur_l_idxs[i]..i)
+            let sigma = match self.current_candidate(i) {
+                KeyByteCandidate::Sigma(sigma) => sigma,
+                KeyByteCandidate::StrongLIndex(l_idx) => {
+                    let inv_rk = (l_idx..i)
                         .map(|k| key[k] as isize + 3 + k as isize)
                         .sum::<isize>()
                         + 3
@@ -91,7 +258,32 @@ impl KeyTester {
             prev_sigma = sigma;
         }
 
-        key
+        CandidateKey {
+            key,
+            key_len: self.cur_state.key_len,
+        }
+    }
+
+    fn push_successors(&mut self) {
+        //Every key has exactly one predecessor (the key with the candidate at its pivot decremented)
+        //As such, only advancing bytes at or after the pivot ensures every key is enqueued exactly once
+        for i in self.cur_state.pivot..self.cur_state.key_len {
+            let cand_idx = self.cur_state.cand_idxs[i];
+            if cand_idx + 1 >= self.candidates[i].len() {
+                continue;
+            }
+
+            let mut cand_idxs = self.cur_state.cand_idxs;
+            cand_idxs[i] += 1;
+
+            self.candidate_queue.push(Reverse(CandidateKeyState {
+                cost: self.cur_state.cost - self.candidates[i][cand_idx].1
+                    + self.candidates[i][cand_idx + 1].1,
+                key_len: self.cur_state.key_len,
+                cand_idxs,
+                pivot: i,
+            }));
+        }
     }
 
     pub fn advance_to_next_key(&mut self) -> bool {
@@ -99,49 +291,104 @@ impl KeyTester {
             return false;
         }
 
-        for i in 0..WepKey::LEN_104 {
-            if self.key_predictions[i] != KeyBytePrediction::Strong {
-                continue;
-            }
+        //Move onto the next most likely key
+        let Some(Reverse(next_state)) = self.candidate_queue.pop() else {
+            return false;
+        };
 
-            //If we ever advance to a non-WEP40 byte then our key isn't one
-            if i >= WepKey::LEN_40 {
-                self.maybe_wep40 = false;
-            }
+        self.cur_state = next_state;
+        self.cur_key_idx += 1;
+        self.push_successors();
+        true
+    }
 
-            //Increment the k value for this strong byte
-            self.cur_l_idxs[i] += 1;
-            if self.cur_l_idxs[i] > i {
-                //Reset k and move onto the next strong key byte
-                self.cur_l_idxs[i] = 1;
-            } else {
-                self.cur_key_idx += 1;
-                return true;
+    pub fn test_current_key(&self, test_sample_buf: &TestSampleBuffer) -> Option<WepKey> {
+        Self::test_key(&self.current_key(), test_sample_buf)
+    }
+
+    pub fn test_key(key: &CandidateKey, test_sample_buf: &TestSampleBuffer) -> Option<WepKey> {
+        Self::test_keys(std::slice::from_ref(key), test_sample_buf)
+    }
+
+    pub fn test_keys(keys: &[CandidateKey], test_sample_buf: &TestSampleBuffer) -> Option<WepKey> {
+        let wep_keys = keys
+            .iter()
+            .filter_map(|key| WepKey::from_slice(key.key()))
+            .collect::<Vec<_>>();
+
+        //Test all of them at once, so that they can be interleaved
+        wep_keys
+            .iter()
+            .zip(test_sample_buf.test_wep_keys(&wep_keys))
+            .find_map(|(key, is_correct)| is_correct.then_some(*key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::RngCore;
+
+    use super::*;
+    use crate::keycracker::{KeyCrackerSettings, KeystreamSample};
+
+    #[test]
+    fn test_short_key_alternative_sigma() {
+        const NOISE_VOTES: usize = 10000;
+        const CORRECT_VOTES: usize = 13700;
+
+        let key = WepKey::from_slice(&[0x13, 0x37, 0x42, 0xab, 0xcd]).unwrap();
+
+        //Votes of a 40-bit key analysed as a 104-bit one, whose trailing bytes look random
+        let mut table = KeyPredictor::new(WepKey::LEN_104).export_votes();
+        let mut sigma = 0u8;
+        for (idx, votes) in table.sigma_votes.iter_mut().enumerate() {
+            votes.fill(NOISE_VOTES);
+            if let Some(&key_byte) = key.key().get(idx) {
+                sigma = sigma.wrapping_add(key_byte);
+                votes[sigma as usize] = CORRECT_VOTES;
             }
         }
-        panic!("unable to advance to next key");
-    }
 
-    pub fn test_current_key(&self, test_sample_buf: &TestSampleBuffer) -> Option<WepKey> {
-        let key = self.current_key();
+        //Make a wrong sigma of the third byte get slightly more votes than the correct one
+        let wrong_sigma = key.key()[..3]
+            .iter()
+            .fold(0u8, |sum, &b| sum.wrapping_add(b))
+            ^ 0x80;
+        table.sigma_votes[2][wrong_sigma as usize] = CORRECT_VOTES + 300;
+        let predictor = KeyPredictor::from_vote_table(&table).unwrap();
+
+        let settings = KeyCrackerSettings::default();
+        let mut test_sample_buf = TestSampleBuffer::new(64, 1, settings.test_sample_threshold, 0);
+        for _ in 0..64 {
+            let mut iv = [0u8; 3];
+            rand::thread_rng().fill_bytes(&mut iv);
 
-        //Test as a WEP-104 key
-        let key104 = WepKey::Wep104Key(key);
-        if test_sample_buf.test_wep_key(&key104) {
-            return Some(key104);
+            let mut keystream = [0u8; KeystreamSample::KEYSTREAM_LEN];
+            key.create_rc4(&iv).gen_keystream(&mut keystream);
+
+            test_sample_buf.accept_sample(&KeystreamSample {
+                iv,
+                keystream,
+                keystream_len: KeystreamSample::MIN_KEYSTREAM_LEN,
+                confidence: 1.,
+            });
         }
 
-        //Test as a WEP-40 key if it might be one
-        if self.maybe_wep40 {
-            let mut key40 = [0u8; WepKey::LEN_40];
-            key40.copy_from_slice(&key[..WepKey::LEN_40]);
+        //The alternative sigma has to be tried as a 40-bit key before all guesses for the trailing bytes
+        let mut tester = KeyTester::new(
+            &predictor,
+            settings.num_sigma_candidates,
+            settings.max_candidate_keys,
+        );
+        assert_eq!(tester.current_key_len(), WepKey::LEN_40);
 
-            let key40 = WepKey::Wep40Key(key40);
-            if test_sample_buf.test_wep_key(&key40) {
-                return Some(key40);
+        let mut found_key = None;
+        while found_key.is_none() && tester.current_key_index() < 16 {
+            found_key = tester.test_current_key(&test_sample_buf);
+            if !tester.advance_to_next_key() {
+                break;
             }
         }
-
-        None
+        assert_eq!(found_key, Some(key));
     }
 }
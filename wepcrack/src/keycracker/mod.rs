@@ -1,12 +1,20 @@
 //Implementation of "Breaking 104 bit WEP in less than 60 seconds" (https://eprint.iacr.org/2007/120.pdf)
 
+mod brute_force;
+mod cracker;
+mod dictionary;
 mod key_byte;
+mod key_test_pool;
 mod key_tester;
 mod predictor;
 mod sample;
 mod test_sample_buf;
 
+pub use brute_force::*;
+pub use cracker::*;
+pub use dictionary::*;
 pub use key_byte::*;
+pub use key_test_pool::*;
 pub use key_tester::*;
 pub use predictor::*;
 pub use sample::*;
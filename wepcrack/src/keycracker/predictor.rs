@@ -1,25 +1,153 @@
-use std::cell::OnceCell;
+use std::{
+    cell::OnceCell,
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    path::Path,
+};
 
-use crate::{rc4::RC4Cipher, wep::WepKey};
+use anyhow::Context;
+use ieee80211::MacAddress;
+use serde::{Deserialize, Serialize};
+
+use crate::{rc4::RC4Cipher, session::mac_address_serde, wep::WepKey};
 
 use super::{KeyBytePredictionInfo, KeystreamSample};
 
+//A serializable export of the votes of a key predictor, which can be merged with the votes of other runs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SigmaVoteTable {
+    //Tables written before the format was versioned don't weight their votes
+    #[serde(default)]
+    pub version: u32,
+    //The network the votes were collected for, if known
+    #[serde(default, with = "mac_address_serde::option")]
+    pub bssid: Option<MacAddress>,
+    pub key_len: usize,
+
+    pub num_samples: usize,
+    pub sigma_votes: Vec<Vec<usize>>,
+}
+
+impl SigmaVoteTable {
+    //Version 1 weights the votes by the confidence of their sample
+    pub const VERSION: u32 = 1;
+
+    pub fn load(path: &Path) -> anyhow::Result<SigmaVoteTable> {
+        let table: SigmaVoteTable = serde_json::from_reader(BufReader::new(
+            File::open(path)
+                .with_context(|| format!("failed to open vote table {}", path.display()))?,
+        ))
+        .with_context(|| format!("failed to parse vote table {}", path.display()))?;
+
+        if table.version != Self::VERSION {
+            anyhow::bail!(
+                "vote table {} has unsupported version {} (expected {})",
+                path.display(),
+                table.version,
+                Self::VERSION
+            );
+        }
+        Ok(table)
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let mut writer = BufWriter::new(
+            File::create(path)
+                .with_context(|| format!("failed to create vote table {}", path.display()))?,
+        );
+        serde_json::to_writer(&mut writer, self)
+            .with_context(|| format!("failed to write vote table {}", path.display()))?;
+        writer
+            .flush()
+            .with_context(|| format!("failed to write vote table {}", path.display()))
+    }
+
+    pub fn check_bssid(&self, bssid: Option<MacAddress>) -> anyhow::Result<()> {
+        //Votes for different networks are votes for different keys, so they can't be merged
+        if let (Some(table_bssid), Some(bssid)) = (self.bssid, bssid) {
+            if table_bssid != bssid {
+                anyhow::bail!(
+                    "vote table was collected for network {}, not {}",
+                    table_bssid.to_hex_string(),
+                    bssid.to_hex_string()
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
 pub struct KeyPredictor {
+    key_len: usize,
     num_samples: usize,
-    sigma_votes: [[usize; 256]; WepKey::LEN_104],
-    key_byte_infos: OnceCell<[KeyBytePredictionInfo; WepKey::LEN_104]>,
+    sigma_votes: Vec<[usize; 256]>,
+    key_byte_infos: OnceCell<Vec<KeyBytePredictionInfo>>,
 }
 
 impl KeyPredictor {
-    #[allow(clippy::new_without_default)]
-    pub fn new() -> KeyPredictor {
+    //Votes are weighted by the confidence of their sample, so a fully confident sample casts this many votes
+    pub const FULL_VOTE: usize = 16;
+
+    pub fn new(key_len: usize) -> KeyPredictor {
+        assert!(
+            WepKey::KEY_LENGTHS.contains(&key_len),
+            "invalid WEP key length {key_len}"
+        );
+
         KeyPredictor {
+            key_len,
             num_samples: 0,
-            sigma_votes: [[0; 256]; WepKey::LEN_104],
+            sigma_votes: vec![[0; 256]; key_len],
             key_byte_infos: OnceCell::new(),
         }
     }
 
+    pub fn from_vote_table(table: &SigmaVoteTable) -> anyhow::Result<KeyPredictor> {
+        if !WepKey::KEY_LENGTHS.contains(&table.key_len) {
+            anyhow::bail!("vote table is for invalid WEP key length {}", table.key_len);
+        }
+        if table.sigma_votes.len() != table.key_len {
+            anyhow::bail!(
+                "vote table has votes for {} key bytes, but is for {}-byte keys",
+                table.sigma_votes.len(),
+                table.key_len
+            );
+        }
+
+        let sigma_votes = table
+            .sigma_votes
+            .iter()
+            .map(|votes| votes.as_slice().try_into())
+            .collect::<Result<Vec<[usize; 256]>, _>>()
+            .context("vote table has an unexpected number of sigma votes")?;
+
+        Ok(KeyPredictor {
+            key_len: sigma_votes.len(),
+            num_samples: table.num_samples,
+            sigma_votes,
+            key_byte_infos: OnceCell::new(),
+        })
+    }
+
+    pub fn export_votes(&self) -> SigmaVoteTable {
+        SigmaVoteTable {
+            version: SigmaVoteTable::VERSION,
+            bssid: None,
+            key_len: self.key_len,
+
+            num_samples: self.num_samples,
+            sigma_votes: self
+                .sigma_votes
+                .iter()
+                .map(|votes| votes.to_vec())
+                .collect(),
+        }
+    }
+
+    pub const fn key_len(&self) -> usize {
+        self.key_len
+    }
+
     pub const fn num_samples(&self) -> usize {
         self.num_samples
     }
@@ -38,9 +166,17 @@ impl KeyPredictor {
             sinv_3[s_3[i] as usize] = i as u8;
         }
 
+        //Samples with a wrong plaintext only cast random votes, so give less confident ones less weight
+        let vote = ((sample.confidence * Self::FULL_VOTE as f64).round() as usize).max(1);
+
         //Calculate approximate sigma sums for all key bytes
+        //Samples with short keystreams only vote for the first few key bytes
         let mut s3_sum: usize = 0;
-        for i in 0..WepKey::LEN_104 {
+        for i in 0..self.key_len {
+            if sample.keystream_len < KeystreamSample::required_keystream_len(i) {
+                break;
+            }
+
             //Update the sum of S3 in the range of 3 to 3+i
             s3_sum += s_3[3 + i] as usize;
 
@@ -51,7 +187,7 @@ impl KeyPredictor {
                 - (j_3 + s3_sum) as isize;
 
             //Add a vote for this sigma
-            self.sigma_votes[i][sigma.rem_euclid(256) as usize] += 1;
+            self.sigma_votes[i][sigma.rem_euclid(256) as usize] += vote;
         }
 
         //Increment the sample counter
@@ -61,17 +197,46 @@ impl KeyPredictor {
         self.key_byte_infos.take();
     }
 
-    pub fn key_byte_infos(&self) -> &[KeyBytePredictionInfo; WepKey::LEN_104] {
-        self.key_byte_infos.get_or_init(|| {
-            let mut infos = [KeyBytePredictionInfo::default(); WepKey::LEN_104];
-            for (idx, info) in infos.iter_mut().enumerate() {
-                *info = KeyBytePredictionInfo::from_sigma_votes(
-                    idx,
-                    &self.sigma_votes[idx],
-                    self.num_samples,
-                );
+    pub fn merge(&mut self, other: &KeyPredictor) -> anyhow::Result<()> {
+        if self.key_len != other.key_len {
+            anyhow::bail!(
+                "can't merge votes for {}-byte keys with votes for {}-byte keys",
+                other.key_len,
+                self.key_len
+            );
+        }
+
+        //Votes are simple counters, so samples from different runs can just be added together
+        for (votes, other_votes) in self.sigma_votes.iter_mut().zip(&other.sigma_votes) {
+            for (vote, other_vote) in votes.iter_mut().zip(other_votes) {
+                *vote += other_vote;
             }
-            infos
+        }
+        self.num_samples += other.num_samples;
+
+        //Reset key byte info
+        self.key_byte_infos.take();
+        Ok(())
+    }
+
+    pub fn merge_votes(&mut self, table: &SigmaVoteTable) -> anyhow::Result<()> {
+        self.merge(&KeyPredictor::from_vote_table(table)?)
+    }
+
+    pub fn sigma_votes(&self, idx: usize) -> &[usize; 256] {
+        &self.sigma_votes[idx]
+    }
+
+    pub fn key_byte_infos(&self) -> &[KeyBytePredictionInfo] {
+        self.key_byte_infos.get_or_init(|| {
+            //Not every sample votes for every key byte, so count the votes of each byte individually
+            self.sigma_votes
+                .iter()
+                .enumerate()
+                .map(|(idx, votes)| {
+                    KeyBytePredictionInfo::from_sigma_votes(idx, votes, votes.iter().sum())
+                })
+                .collect()
         })
     }
 
@@ -79,3 +244,92 @@ impl KeyPredictor {
         &self.key_byte_infos()[idx]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_232_bit_votes() {
+        const NUM_SAMPLES: usize = 256;
+
+        let key = WepKey::from_slice(&(1..=WepKey::LEN_232 as u8).collect::<Vec<_>>()).unwrap();
+        let mut predictor = KeyPredictor::new(WepKey::LEN_232);
+
+        //Samples with a full keystream vote for every key byte
+        for i in 0..NUM_SAMPLES {
+            let iv = [i as u8, 0xff, 0x42];
+            let mut keystream = [0u8; KeystreamSample::KEYSTREAM_LEN];
+            key.create_rc4(&iv).gen_keystream(&mut keystream);
+
+            predictor.accept_sample(&KeystreamSample {
+                keystream,
+                keystream_len: KeystreamSample::KEYSTREAM_LEN,
+                iv,
+                confidence: 1.,
+            });
+        }
+
+        for idx in 0..WepKey::LEN_232 {
+            assert_eq!(
+                predictor.sigma_votes(idx).iter().sum::<usize>(),
+                NUM_SAMPLES * KeyPredictor::FULL_VOTE
+            );
+        }
+    }
+
+    #[test]
+    fn test_merge_votes() {
+        let key = WepKey::from_slice(&[0x13, 0x37, 0x42, 0xab, 0xcd]).unwrap();
+        let bssid = MacAddress::new([0x02, 0, 0, 0, 0, 1]);
+
+        //Collect votes in two separate runs
+        let mut predictors = [
+            KeyPredictor::new(WepKey::LEN_40),
+            KeyPredictor::new(WepKey::LEN_40),
+        ];
+        for (i, predictor) in predictors.iter_mut().enumerate() {
+            for j in 0..64 {
+                let iv = [i as u8, j, 0x42];
+                let mut keystream = [0u8; KeystreamSample::KEYSTREAM_LEN];
+                key.create_rc4(&iv).gen_keystream(&mut keystream);
+
+                predictor.accept_sample(&KeystreamSample {
+                    keystream,
+                    keystream_len: KeystreamSample::KEYSTREAM_LEN,
+                    iv,
+                    confidence: 1.,
+                });
+            }
+        }
+
+        //Merging adds up the votes of both runs
+        let mut table = predictors[1].export_votes();
+        table.bssid = Some(bssid);
+        let mut merged = KeyPredictor::from_vote_table(&predictors[0].export_votes()).unwrap();
+        merged.merge_votes(&table).unwrap();
+        assert_eq!(merged.num_samples(), 128);
+        for idx in 0..WepKey::LEN_40 {
+            for sigma in 0..256 {
+                assert_eq!(
+                    merged.sigma_votes(idx)[sigma],
+                    predictors[0].sigma_votes(idx)[sigma] + predictors[1].sigma_votes(idx)[sigma]
+                );
+            }
+        }
+
+        //Votes for other networks and other key lengths are refused
+        assert!(table.check_bssid(Some(bssid)).is_ok());
+        assert!(table.check_bssid(None).is_ok());
+        assert!(table
+            .check_bssid(Some(MacAddress::new([0x02, 0, 0, 0, 0, 2])))
+            .is_err());
+        assert!(merged
+            .merge_votes(&KeyPredictor::new(WepKey::LEN_104).export_votes())
+            .is_err());
+
+        //So are tables whose votes don't match their key length
+        table.key_len = WepKey::LEN_104;
+        assert!(KeyPredictor::from_vote_table(&table).is_err());
+    }
+}
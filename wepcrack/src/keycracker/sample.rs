@@ -1,11 +1,30 @@
+use serde::{Deserialize, Serialize};
+
 use crate::wep::WepIV;
 
-#[derive(Clone, Copy, Default)]
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
 pub struct KeystreamSample {
     pub keystream: [u8; KeystreamSample::KEYSTREAM_LEN],
+    pub keystream_len: usize,
     pub iv: WepIV,
+
+    //How confident the sample source is that the keystream is correct, in the range [0, 1]
+    pub confidence: f64,
 }
 
 impl KeystreamSample {
-    pub const KEYSTREAM_LEN: usize = 16;
+    //Enough keystream bytes to crack even WEP-232 keys
+    pub const KEYSTREAM_LEN: usize = 32;
+
+    //Samples with less keystream bytes than this aren't useful for anything
+    pub const MIN_KEYSTREAM_LEN: usize = 16;
+
+    //The number of keystream bytes required to vote for the given key byte
+    pub const fn required_keystream_len(key_idx: usize) -> usize {
+        key_idx + 3
+    }
+
+    pub fn valid_keystream(&self) -> &[u8] {
+        &self.keystream[..self.keystream_len]
+    }
 }
@@ -1,13 +1,69 @@
 use std::collections::VecDeque;
 
-use crate::wep::WepKey;
+use crate::{
+    crc32::Crc32,
+    rc4::{RC4Cipher, RC4MultiCipher},
+    wep::{WepFrame, WepIV, WepKey},
+};
 
 use super::KeystreamSample;
 
+//The RC4 state after scheduling an IV, which is the same for all keys
+#[derive(Clone)]
+struct IVSchedule {
+    iv: WepIV,
+    rc4: RC4Cipher,
+}
+
+impl IVSchedule {
+    fn new(iv: &WepIV) -> IVSchedule {
+        let mut rc4 = RC4Cipher::default();
+        rc4.do_partial_keyschedule(iv);
+
+        IVSchedule { iv: *iv, rc4 }
+    }
+
+    fn create_rc4(
+        &self,
+        keys: &[WepKey; TestSampleBuffer::NUM_LANES],
+    ) -> RC4MultiCipher<{ TestSampleBuffer::NUM_LANES }> {
+        let rc4_keys: [_; TestSampleBuffer::NUM_LANES] = std::array::from_fn(|lane| {
+            let key = keys[lane].key();
+
+            let mut rc4_key = [0u8; 3 + WepKey::LEN_MAX];
+            rc4_key[..3].copy_from_slice(&self.iv);
+            rc4_key[3..3 + key.len()].copy_from_slice(key);
+            rc4_key
+        });
+
+        RC4MultiCipher::from_prefix(
+            &self.rc4,
+            std::array::from_fn(|lane| &rc4_keys[lane][..3 + keys[lane].key().len()]),
+        )
+    }
+}
+
+#[derive(Clone)]
+struct TestSample {
+    sample: KeystreamSample,
+    iv_schedule: IVSchedule,
+}
+
+#[derive(Clone)]
+struct TestFrame {
+    frame: WepFrame,
+    iv_schedule: IVSchedule,
+}
+
+#[derive(Clone)]
 pub struct TestSampleBuffer {
-    samples: VecDeque<KeystreamSample>,
+    samples: VecDeque<TestSample>,
     buffer_size: usize,
 
+    //If enabled, keys are verified using the ICVs of full frames instead of keystream samples
+    frames: Vec<TestFrame>,
+    frame_buffer_size: usize,
+
     period_timer: usize,
     sample_period: usize,
 
@@ -15,15 +71,22 @@ pub struct TestSampleBuffer {
 }
 
 impl TestSampleBuffer {
+    //Number of keys which are tested in lockstep
+    pub const NUM_LANES: usize = 8;
+
     pub fn new(
         buffer_size: usize,
         sample_period: usize,
         test_threshold_fract: f64,
+        frame_buffer_size: usize,
     ) -> TestSampleBuffer {
         TestSampleBuffer {
             samples: VecDeque::with_capacity(buffer_size),
             buffer_size,
 
+            frames: Vec::with_capacity(frame_buffer_size),
+            frame_buffer_size,
+
             period_timer: 0,
             sample_period,
 
@@ -35,10 +98,64 @@ impl TestSampleBuffer {
         self.samples.len()
     }
 
+    pub fn num_frames(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub const fn uses_frames(&self) -> bool {
+        self.frame_buffer_size > 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        if self.uses_frames() {
+            self.frames.is_empty()
+        } else {
+            self.samples.is_empty()
+        }
+    }
+
     pub fn is_full(&self) -> bool {
+        //A single frame can already confirm a key on its own, so don't wait for more
+        if self.uses_frames() {
+            return !self.frames.is_empty();
+        }
+
         self.samples.len() >= self.buffer_size
     }
 
+    pub fn samples(&self) -> impl Iterator<Item = &KeystreamSample> {
+        self.samples.iter().map(|sample| &sample.sample)
+    }
+
+    pub fn frames(&self) -> impl Iterator<Item = &WepFrame> {
+        self.frames.iter().map(|frame| &frame.frame)
+    }
+
+    pub fn accept_frame(&mut self, frame: &WepFrame) {
+        //Keep the first frames, as there's no benefit in keeping newer ones around
+        if self.frames.len() >= self.frame_buffer_size || frame.body.len() < WepFrame::ICV_SIZE {
+            return;
+        }
+
+        self.frames.push(TestFrame {
+            frame: frame.clone(),
+            iv_schedule: IVSchedule::new(&frame.iv),
+        });
+    }
+
+    pub fn restore_samples(&mut self, samples: &[KeystreamSample]) {
+        //Restored samples bypass the sample period
+        for sample in samples {
+            while self.samples.len() >= self.buffer_size {
+                self.samples.pop_front();
+            }
+            self.samples.push_back(TestSample {
+                sample: *sample,
+                iv_schedule: IVSchedule::new(&sample.iv),
+            });
+        }
+    }
+
     pub fn accept_sample(&mut self, sample: &KeystreamSample) {
         //Only accept every n-th sample
         self.period_timer += 1;
@@ -54,35 +171,130 @@ impl TestSampleBuffer {
         }
 
         //Add the sample to the buffer
-        self.samples.push_back(*sample);
+        self.samples.push_back(TestSample {
+            sample: *sample,
+            iv_schedule: IVSchedule::new(&sample.iv),
+        });
     }
 
-    pub fn test_wep_key(&self, key: &WepKey) -> bool {
-        //Calculate the maximum number of incorrect samples (the negative threshold)
-        let threshold = (self.samples.len() as f64 * self.test_threshold_fract).ceil() as usize;
-        let neg_threshold = self.samples.len() - threshold;
+    pub fn test_first_keystream_bytes(&self, keys: &[WepKey], num_samples: usize) -> Vec<bool> {
+        //The first plaintext byte of every sample is the LLC DSAP (0xaa), so the first keystream byte is always correct
+        //This allows rejecting most incorrect keys after generating just a single keystream byte
+        Self::test_in_lanes(keys, |keys| {
+            let mut matches = [true; Self::NUM_LANES];
+            for test_sample in self.samples.iter().take(num_samples) {
+                let keystream_bytes = test_sample
+                    .iv_schedule
+                    .create_rc4(keys)
+                    .gen_keystream_bytes();
+                for (matches, keystream_byte) in matches.iter_mut().zip(keystream_bytes) {
+                    *matches &= keystream_byte == test_sample.sample.keystream[0];
+                }
+            }
+            matches
+        })
+    }
+
+    pub fn test_wep_keys(&self, keys: &[WepKey]) -> Vec<bool> {
+        if self.uses_frames() {
+            return self.test_wep_keys_icv(keys);
+        }
+
+        let neg_threshold = self.neg_threshold(self.samples.len());
 
-        //Check samples
-        let mut neg_samples = 0;
-        for sample in &self.samples {
-            //Compute the keystream based on the sample IV
-            let mut rc4 = key.create_rc4(&sample.iv);
+        Self::test_in_lanes(keys, |keys| {
+            let mut neg_samples = [0usize; Self::NUM_LANES];
+            for test_sample in &self.samples {
+                //Compute the keystreams based on the sample IV
+                let mut rc4 = test_sample.iv_schedule.create_rc4(keys);
 
-            let mut keystream = [0u8; KeystreamSample::KEYSTREAM_LEN];
-            rc4.gen_keystream(&mut keystream);
+                //Compare with the correct sample keystream
+                //Most incorrect keys already differ in the first byte, so stop once all keys do
+                let mut matches = [true; Self::NUM_LANES];
+                for &sample_byte in test_sample.sample.valid_keystream() {
+                    for (matches, keystream_byte) in
+                        matches.iter_mut().zip(rc4.gen_keystream_bytes())
+                    {
+                        *matches &= keystream_byte == sample_byte;
+                    }
 
-            //Compare with the correct sample keystream
-            if keystream.eq(&sample.keystream) {
-                continue;
+                    if !matches.contains(&true) {
+                        break;
+                    }
+                }
+
+                for (neg_samples, matches) in neg_samples.iter_mut().zip(matches) {
+                    if !matches {
+                        *neg_samples += 1;
+                    }
+                }
+
+                //Check if the negative sample threshold has been crossed for all keys
+                if neg_samples.iter().all(|&num| num >= neg_threshold) {
+                    break;
+                }
             }
 
-            //Check if the negative sample threshold has been crossed
-            neg_samples += 1;
-            if neg_samples >= neg_threshold {
-                return false;
+            neg_samples.map(|num| num < neg_threshold)
+        })
+    }
+
+    fn test_wep_keys_icv(&self, keys: &[WepKey]) -> Vec<bool> {
+        let neg_threshold = self.neg_threshold(self.frames.len());
+
+        Self::test_in_lanes(keys, |keys| {
+            let mut neg_frames = [0usize; Self::NUM_LANES];
+            for test_frame in &self.frames {
+                let mut rc4 = test_frame.iv_schedule.create_rc4(keys);
+
+                //Decrypt the payload, and calculate its CRC
+                let mut crcs = [Crc32::default(); Self::NUM_LANES];
+                for &byte in test_frame.frame.encrypted_payload() {
+                    for (crc, keystream_byte) in crcs.iter_mut().zip(rc4.gen_keystream_bytes()) {
+                        crc.update_byte(byte ^ keystream_byte);
+                    }
+                }
+
+                //Decrypt the ICV
+                let mut icvs = [[0u8; WepFrame::ICV_SIZE]; Self::NUM_LANES];
+                for (idx, &byte) in test_frame.frame.encrypted_icv().iter().enumerate() {
+                    for (icv, keystream_byte) in icvs.iter_mut().zip(rc4.gen_keystream_bytes()) {
+                        icv[idx] = byte ^ keystream_byte;
+                    }
+                }
+
+                //Only the correct key produces a matching ICV (except for a 2^-32 chance)
+                for ((neg_frames, crc), icv) in neg_frames.iter_mut().zip(crcs).zip(icvs) {
+                    if crc.finish() != u32::from_le_bytes(icv) {
+                        *neg_frames += 1;
+                    }
+                }
+
+                if neg_frames.iter().all(|&num| num >= neg_threshold) {
+                    break;
+                }
             }
-        }
 
-        true
+            neg_frames.map(|num| num < neg_threshold)
+        })
+    }
+
+    fn neg_threshold(&self, num_tests: usize) -> usize {
+        //Calculate the maximum number of incorrect samples / frames (the negative threshold)
+        let threshold = (num_tests as f64 * self.test_threshold_fract).ceil() as usize;
+        (num_tests - threshold).max(1)
+    }
+
+    fn test_in_lanes(
+        keys: &[WepKey],
+        mut test_func: impl FnMut(&[WepKey; Self::NUM_LANES]) -> [bool; Self::NUM_LANES],
+    ) -> Vec<bool> {
+        keys.chunks(Self::NUM_LANES)
+            .flat_map(|keys| {
+                //Pad the last chunk with copies of its first key
+                let lane_keys = std::array::from_fn(|lane| *keys.get(lane).unwrap_or(&keys[0]));
+                test_func(&lane_keys).into_iter().take(keys.len())
+            })
+            .collect()
     }
 }
@@ -0,0 +1,92 @@
+//Derivation of WEP keys from passphrases, as done by many consumer access points
+
+use md5::{Digest, Md5};
+
+use crate::wep::WepKey;
+
+//The 40-bit generator by Neesus Datacom, which derives four keys from a passphrase
+pub fn neesus_datacom_keys(passphrase: &[u8]) -> [WepKey; 4] {
+    //Fold the passphrase into a 32-bit seed
+    let mut seed = 0u32;
+    for (i, &c) in passphrase.iter().enumerate() {
+        seed ^= (c as u32) << ((i & 3) * 8);
+    }
+
+    //Generate the keys using a linear congruential generator
+    std::array::from_fn(|_| {
+        let mut key = [0u8; WepKey::LEN_40];
+        for b in key.iter_mut() {
+            seed = seed.wrapping_mul(0x343fd).wrapping_add(0x269ec3);
+            *b = (seed >> 16) as u8;
+        }
+        WepKey::Wep40Key(key)
+    })
+}
+
+//The 104-bit generator, which takes the MD5 hash of the passphrase repeated to 64 bytes
+pub fn md5_key(passphrase: &[u8]) -> Option<WepKey> {
+    if passphrase.is_empty() {
+        return None;
+    }
+
+    let mut hasher = Md5::new();
+    for &c in passphrase.iter().cycle().take(64) {
+        hasher.update([c]);
+    }
+    let hash = hasher.finalize();
+
+    WepKey::from_slice(&hash[..WepKey::LEN_104])
+}
+
+//Some access points use the bytes of ASCII passphrases of the right length as the key directly
+pub fn ascii_key(passphrase: &[u8]) -> Option<WepKey> {
+    WepKey::from_slice(passphrase)
+}
+
+pub fn passphrase_keys(passphrase: &[u8]) -> Vec<WepKey> {
+    let mut keys = Vec::with_capacity(7);
+    keys.extend(neesus_datacom_keys(passphrase));
+    keys.extend(md5_key(passphrase));
+    keys.extend(ascii_key(passphrase));
+
+    //Wordlists of known keys might also contain them as hex strings
+    if let Some(key) = std::str::from_utf8(passphrase)
+        .ok()
+        .and_then(|passphrase| passphrase.parse::<WepKey>().ok())
+    {
+        keys.push(key);
+    }
+
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keygen_known_answers() {
+        //Known answers of the reference implementations of both generators
+        let keys = neesus_datacom_keys(b"password").map(|key| hex::encode(key.key()));
+        assert_eq!(
+            keys,
+            ["f2c7bb35b9", "858edab02e", "27914293e5", "ce63e8fb8b"]
+        );
+
+        let keys = neesus_datacom_keys(b"Hello World!").map(|key| hex::encode(key.key()));
+        assert_eq!(
+            keys,
+            ["02f66f36ba", "5504a5262a", "75bddd44ab", "d3b5b1c98e"]
+        );
+
+        assert_eq!(
+            md5_key(b"password").unwrap(),
+            "2b204a3f1042643e480fdd655e".parse().unwrap()
+        );
+        assert_eq!(
+            md5_key(b"Hello World!").unwrap(),
+            "2671d283386fa70535855f5940".parse().unwrap()
+        );
+        assert!(md5_key(b"").is_none());
+    }
+}
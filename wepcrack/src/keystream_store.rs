@@ -0,0 +1,121 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::wep::{WepIV, WepKeystream};
+
+//A dictionary of all keystreams recovered for a network, which can be used to decrypt / forge frames without the key
+#[derive(Debug, Clone, Default)]
+pub struct KeystreamStore {
+    keystreams: HashMap<WepIV, Vec<u8>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct KeystreamStoreFile {
+    keystreams: Vec<WepKeystream>,
+}
+
+impl KeystreamStore {
+    pub fn new() -> KeystreamStore {
+        KeystreamStore::default()
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<KeystreamStore> {
+        let file: KeystreamStoreFile =
+            serde_json::from_reader(BufReader::new(File::open(path).with_context(|| {
+                format!("failed to open keystream store {}", path.display())
+            })?))
+            .with_context(|| format!("failed to parse keystream store {}", path.display()))?;
+
+        let mut store = KeystreamStore::new();
+        for keystream in file.keystreams {
+            store.insert(keystream);
+        }
+        Ok(store)
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        //Sort the keystreams so that the file doesn't change needlessly between saves
+        let mut keystreams = self.iter().collect::<Vec<_>>();
+        keystreams.sort_unstable_by_key(|keystream| keystream.iv);
+
+        let mut writer = BufWriter::new(
+            File::create(path)
+                .with_context(|| format!("failed to create keystream store {}", path.display()))?,
+        );
+        serde_json::to_writer(&mut writer, &KeystreamStoreFile { keystreams })
+            .with_context(|| format!("failed to write keystream store {}", path.display()))?;
+        writer
+            .flush()
+            .with_context(|| format!("failed to write keystream store {}", path.display()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.keystreams.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keystreams.is_empty()
+    }
+
+    //Only keeps the new keystream if it's longer than the one we already have for its IV
+    pub fn insert(&mut self, keystream: WepKeystream) -> bool {
+        match self.keystreams.get(&keystream.iv) {
+            Some(cur_keystream) if cur_keystream.len() >= keystream.keystream.len() => false,
+            _ => {
+                self.keystreams.insert(keystream.iv, keystream.keystream);
+                true
+            }
+        }
+    }
+
+    pub fn get(&self, iv: &WepIV) -> Option<WepKeystream> {
+        self.keystreams.get(iv).map(|keystream| WepKeystream {
+            iv: *iv,
+            keystream: keystream.clone(),
+        })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = WepKeystream> + '_ {
+        self.keystreams.iter().map(|(iv, keystream)| WepKeystream {
+            iv: *iv,
+            keystream: keystream.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wep::{WepFrame, WepKey};
+
+    #[test]
+    fn test_keystream_store() {
+        let key: WepKey = "0102030405".parse().unwrap();
+        let frame = WepFrame::encrypt(&key, &[1, 2, 3], 0, b"keystream store");
+
+        //Only the longest keystream for each IV has to be kept
+        let mut store = KeystreamStore::new();
+        assert!(store.insert(WepKeystream::from_key(&key, &[1, 2, 3], 8)));
+        let keystream = store.get(&frame.iv).unwrap();
+        assert!(frame.decrypt_with_keystream(&keystream).is_none());
+
+        assert!(store.insert(WepKeystream::from_key(&key, &[1, 2, 3], 64)));
+        assert!(!store.insert(WepKeystream::from_key(&key, &[1, 2, 3], 16)));
+        assert!(store.insert(WepKeystream::from_key(&key, &[4, 5, 6], 32)));
+
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.get(&[1, 2, 3]).unwrap().keystream.len(), 64);
+        let keystream = store.get(&frame.iv).unwrap();
+        assert_eq!(
+            frame.decrypt_with_keystream(&keystream).unwrap(),
+            b"keystream store"
+        );
+    }
+}
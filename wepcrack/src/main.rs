@@ -1,3 +1,4 @@
+use clap::Parser;
 use crossterm::{
     terminal::{EnterAlternateScreen, LeaveAlternateScreen},
     ExecutableCommand,
@@ -10,12 +11,32 @@ use std::{
 
 pub mod app;
 pub mod arp_supplier;
+pub mod chopchop;
+pub mod cli;
+pub mod config;
+pub mod crc32;
+pub mod deauth;
+pub mod decrypt;
+pub mod fake_auth;
+pub mod fragmentation;
 pub mod ieee80211;
+pub mod ivs;
+pub mod ivs_supplier;
 pub mod keycracker;
+pub mod keygen;
+pub mod keystream_store;
 pub mod netlink;
 pub mod nl80211;
+pub mod passive_supplier;
+pub mod pcap;
+pub mod pcap_supplier;
+pub mod plaintext;
 pub mod rc4;
+pub mod replay_rate;
 pub mod rtnetlink;
+pub mod session;
+pub mod shared_key_auth;
+pub mod target_monitor;
 pub mod ui;
 pub mod util;
 pub mod wep;
@@ -23,8 +44,17 @@ pub mod wep;
 static TERMINAL_LOCK: AtomicBool = AtomicBool::new(true);
 
 fn main() -> Result<(), Box<dyn Error>> {
+    //Parse the command line arguments
+    let args = cli::Args::parse();
+
+    //Run headless if a command was given
+    if let Some(command) = &args.command {
+        cli::run_command(command, &args)?;
+        return Ok(());
+    }
+
     //Create the app
-    let mut app = app::App::create()?;
+    let mut app = app::App::create(&args)?;
 
     //Initialize the terminal
     crossterm::terminal::enable_raw_mode()?;
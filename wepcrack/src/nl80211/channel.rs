@@ -371,6 +371,17 @@ impl NL80211Channel {
         }
     }
 
+    pub fn main_channel(&self) -> u32 {
+        match self {
+            NL80211Channel::Channel20NoHT { channel } | NL80211Channel::ChannelHT20 { channel } => {
+                *channel
+            }
+            NL80211Channel::ChannelHT40 { main_channel, .. }
+            | NL80211Channel::ChannelVHT80 { main_channel, .. }
+            | NL80211Channel::ChannelVHT160 { main_channel, .. } => *main_channel,
+        }
+    }
+
     pub fn nla_frequency(&self) -> u32 {
         match self {
             NL80211Channel::Channel20NoHT { channel } | NL80211Channel::ChannelHT20 { channel } => {
@@ -0,0 +1,198 @@
+use std::{
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use ieee80211::{DSStatus, DataFrameTrait, FrameLayer, FrameTrait, MacAddress};
+
+use crate::{
+    ieee80211::{IEEE80211Monitor, IEEE80211PacketSniffer},
+    keycracker::KeystreamSample,
+    plaintext::{self, KnownPlaintextClassifier, PlaintextClass, PlaintextClassifier},
+};
+
+#[derive(Default)]
+struct PassiveSupplierStats {
+    num_frames: AtomicUsize,
+    num_arp_samples: AtomicUsize,
+    num_ipv4_samples: AtomicUsize,
+    num_ipv6_samples: AtomicUsize,
+}
+
+pub struct PassiveSampleSupplier {
+    sniffer_thread: Option<JoinHandle<()>>,
+
+    should_exit: Arc<AtomicBool>,
+    stats: Arc<PassiveSupplierStats>,
+    sample_recv: Mutex<Receiver<KeystreamSample>>,
+}
+
+impl PassiveSampleSupplier {
+    pub fn new(monitor: Rc<IEEE80211Monitor>, ap_mac: MacAddress) -> PassiveSampleSupplier {
+        Self::with_classifier(monitor, ap_mac, Box::new(KnownPlaintextClassifier))
+    }
+
+    pub fn with_classifier(
+        monitor: Rc<IEEE80211Monitor>,
+        ap_mac: MacAddress,
+        classifier: Box<dyn PlaintextClassifier>,
+    ) -> PassiveSampleSupplier {
+        let (sample_tx, sample_rx) = mpsc::channel();
+        let should_exit = Arc::new(AtomicBool::new(false));
+        let stats = Arc::new(PassiveSupplierStats::default());
+
+        //Launch the sniffer thread
+        let sniffer_thread = {
+            let sniffer = monitor
+                .create_sniffer()
+                .expect("failed to create sniffer for passive sniffer thread");
+
+            let should_exit = should_exit.clone();
+            let stats = stats.clone();
+            Some(
+                std::thread::Builder::new()
+                    .name("passive sniffer thread".into())
+                    .spawn(move || {
+                        Self::sniffer_thread_func(
+                            sniffer,
+                            sample_tx,
+                            ap_mac,
+                            classifier.as_ref(),
+                            &stats,
+                            should_exit.as_ref(),
+                        )
+                    })
+                    .expect("failed to spawn passive sniffer thread"),
+            )
+        };
+
+        PassiveSampleSupplier {
+            sniffer_thread,
+
+            should_exit,
+            stats,
+            sample_recv: Mutex::new(sample_rx),
+        }
+    }
+
+    pub fn num_frames(&self) -> usize {
+        self.stats.num_frames.load(Ordering::SeqCst)
+    }
+
+    pub fn num_arp_samples(&self) -> usize {
+        self.stats.num_arp_samples.load(Ordering::SeqCst)
+    }
+
+    pub fn num_ipv4_samples(&self) -> usize {
+        self.stats.num_ipv4_samples.load(Ordering::SeqCst)
+    }
+
+    pub fn num_ipv6_samples(&self) -> usize {
+        self.stats.num_ipv6_samples.load(Ordering::SeqCst)
+    }
+
+    fn sniffer_thread_func(
+        mut sniffer: IEEE80211PacketSniffer,
+        sample_sender: Sender<KeystreamSample>,
+        ap_mac: MacAddress,
+        classifier: &dyn PlaintextClassifier,
+        stats: &PassiveSupplierStats,
+        should_exit: &AtomicBool,
+    ) {
+        //Quiet networks might not send anything for a long time, so regularly check if we should exit
+        sniffer
+            .set_timeout(Some(Duration::from_millis(250)))
+            .expect("failed to set passive sniffer timeout");
+
+        while !should_exit.load(Ordering::SeqCst) {
+            //Sniff a packet
+            let packet = sniffer
+                .sniff_packet()
+                .expect("failed to sniff passive data packet");
+
+            let Some(packet) = packet else {
+                continue;
+            };
+            let frame = packet.ieee80211_frame();
+
+            //Check if this is an encrypted data frame of the target network
+            //Short / corrupted frames can't be trusted to have a full header
+            let frame_bssid = match frame.ds_status() {
+                DSStatus::FromDSToSTA => frame.bytes().get(10..16),
+                DSStatus::FromSTAToDS => frame.bytes().get(4..10),
+                _ => continue,
+            };
+            if frame_bssid != Some(ap_mac.as_bytes().as_slice()) {
+                continue;
+            }
+
+            let Some(FrameLayer::Data(data)) = frame.next_layer() else {
+                continue;
+            };
+            if !data.protected() {
+                continue;
+            }
+            stats.num_frames.fetch_add(1, Ordering::SeqCst);
+
+            //Guess the plaintext and recover the keystream
+            let Some((prediction, sample)) = plaintext::recover_sample(&data, true, classifier)
+            else {
+                continue;
+            };
+
+            match prediction.class {
+                PlaintextClass::ARPRequest | PlaintextClass::ARPReply => {
+                    stats.num_arp_samples.fetch_add(1, Ordering::SeqCst)
+                }
+                PlaintextClass::IPv4 => stats.num_ipv4_samples.fetch_add(1, Ordering::SeqCst),
+                PlaintextClass::IPv6 => stats.num_ipv6_samples.fetch_add(1, Ordering::SeqCst),
+            };
+
+            sample_sender
+                .send(sample)
+                .expect("failed to push sample to queue");
+        }
+    }
+
+    pub fn provide_sample(&mut self, _should_exit: &AtomicBool) -> Option<KeystreamSample> {
+        const TIMEOUT: Duration = Duration::from_millis(10);
+
+        //Pop a sample from the queue
+        if let Ok(sample) = {
+            let sample_recv = self
+                .sample_recv
+                .lock()
+                .expect("failed to lock sample receiver");
+            sample_recv.recv_timeout(TIMEOUT)
+        } {
+            return Some(sample);
+        };
+
+        //Ensure the sniffer thread hasn't finished
+        if self.sniffer_thread.as_ref().unwrap().is_finished() {
+            if let Some(Err(e)) = self.sniffer_thread.take().map(JoinHandle::join) {
+                std::panic::resume_unwind(e);
+            } else {
+                panic!("passive sniffer thread exited prematurely");
+            }
+        }
+
+        None
+    }
+}
+
+impl Drop for PassiveSampleSupplier {
+    fn drop(&mut self) {
+        self.should_exit.store(true, Ordering::SeqCst);
+
+        if let Some(Err(e)) = self.sniffer_thread.take().map(JoinHandle::join) {
+            std::panic::resume_unwind(e);
+        }
+    }
+}
@@ -0,0 +1,668 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+    time::Duration,
+};
+
+use anyhow::Context;
+
+use crate::util::read_exact_or_truncated;
+
+pub const LINKTYPE_ETHERNET: u32 = 1;
+pub const LINKTYPE_IEEE802_11: u32 = 105;
+pub const LINKTYPE_IEEE802_11_RADIOTAP: u32 = 127;
+
+const PCAP_MAGIC_US: u32 = 0xa1b2c3d4;
+const PCAP_MAGIC_NS: u32 = 0xa1b23c4d;
+
+const PCAPNG_SHB_TYPE: u32 = 0x0a0d0d0a;
+const PCAPNG_IDB_TYPE: u32 = 0x00000001;
+const PCAPNG_OPB_TYPE: u32 = 0x00000002;
+const PCAPNG_SPB_TYPE: u32 = 0x00000003;
+const PCAPNG_EPB_TYPE: u32 = 0x00000006;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1a2b3c4d;
+
+const PCAPNG_OPT_ENDOFOPT: u16 = 0;
+const PCAPNG_OPT_IF_NAME: u16 = 2;
+const PCAPNG_OPT_IF_TSRESOL: u16 = 9;
+
+//Upper bound for record / block lengths, so corrupt files can't trigger huge allocations
+const MAX_RECORD_LEN: usize = 256 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct PcapRecord {
+    pub link_type: u32,
+    pub timestamp: Duration,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PcapngInterface {
+    link_type: u32,
+    ts_units_per_sec: u128,
+}
+
+enum PcapFormat {
+    Pcap {
+        big_endian: bool,
+        nanosecond_ts: bool,
+        link_type: u32,
+    },
+    Pcapng {
+        big_endian: bool,
+        interfaces: Vec<PcapngInterface>,
+    },
+}
+
+pub struct PcapReader<R: Read> {
+    reader: R,
+    format: PcapFormat,
+}
+
+impl PcapReader<BufReader<File>> {
+    pub fn open(path: &Path) -> anyhow::Result<PcapReader<BufReader<File>>> {
+        let file = File::open(path)
+            .with_context(|| format!("failed to open capture file {}", path.display()))?;
+        PcapReader::new(BufReader::new(file))
+    }
+}
+
+impl<R: Read> PcapReader<R> {
+    pub fn new(mut reader: R) -> anyhow::Result<PcapReader<R>> {
+        //Read the magic number to determine the file format
+        let mut magic = [0u8; 4];
+        reader
+            .read_exact(&mut magic)
+            .context("failed to read capture file magic")?;
+
+        let format = if u32::from_le_bytes(magic) == PCAPNG_SHB_TYPE {
+            //Parse the initial section header block
+            let mut format = PcapFormat::Pcapng {
+                big_endian: false,
+                interfaces: Vec::new(),
+            };
+            Self::read_pcapng_section_header(&mut reader, &mut format)?;
+            format
+        } else {
+            //Determine the endianness / timestamp precision from the magic
+            let (big_endian, nanosecond_ts) =
+                match (u32::from_le_bytes(magic), u32::from_be_bytes(magic)) {
+                    (PCAP_MAGIC_US, _) => (false, false),
+                    (PCAP_MAGIC_NS, _) => (false, true),
+                    (_, PCAP_MAGIC_US) => (true, false),
+                    (_, PCAP_MAGIC_NS) => (true, true),
+                    _ => anyhow::bail!("unknown capture file magic: {}", hex::encode(magic)),
+                };
+
+            //Parse the rest of the global header
+            let mut header = [0u8; 20];
+            reader
+                .read_exact(&mut header)
+                .context("failed to read pcap global header")?;
+
+            PcapFormat::Pcap {
+                big_endian,
+                nanosecond_ts,
+                link_type: parse_u32(&header[16..20], big_endian),
+            }
+        };
+
+        Ok(PcapReader { reader, format })
+    }
+
+    pub fn next_record(&mut self) -> anyhow::Result<Option<PcapRecord>> {
+        match self.format {
+            PcapFormat::Pcap {
+                big_endian,
+                nanosecond_ts,
+                link_type,
+            } => {
+                //Read the record header
+                let mut header = [0u8; 16];
+                if !read_exact_or_truncated(&mut self.reader, &mut header)
+                    .context("failed to read pcap record header")?
+                {
+                    return Ok(None);
+                }
+
+                let ts_sec = parse_u32(&header[0..4], big_endian);
+                let ts_frac = parse_u32(&header[4..8], big_endian);
+                let incl_len = parse_u32(&header[8..12], big_endian) as usize;
+                if incl_len > MAX_RECORD_LEN {
+                    anyhow::bail!("invalid pcap record length: {incl_len}");
+                }
+
+                //Read the record data
+                let mut data = vec![0u8; incl_len];
+                if !read_exact_or_truncated(&mut self.reader, &mut data)
+                    .context("failed to read pcap record data")?
+                {
+                    return Ok(None);
+                }
+
+                Ok(Some(PcapRecord {
+                    link_type,
+                    timestamp: Duration::from_secs(ts_sec as u64)
+                        + if nanosecond_ts {
+                            Duration::from_nanos(ts_frac as u64)
+                        } else {
+                            Duration::from_micros(ts_frac as u64)
+                        },
+                    data,
+                }))
+            }
+            PcapFormat::Pcapng { .. } => loop {
+                //Read the block header
+                let mut header = [0u8; 8];
+                if !read_exact_or_truncated(&mut self.reader, &mut header)
+                    .context("failed to read pcapng block header")?
+                {
+                    return Ok(None);
+                }
+
+                //Handle new sections
+                if u32::from_le_bytes([header[0], header[1], header[2], header[3]])
+                    == PCAPNG_SHB_TYPE
+                {
+                    let mut len = [0u8; 4];
+                    len.copy_from_slice(&header[4..8]);
+                    Self::read_pcapng_section_body(&mut self.reader, &mut self.format, len)?;
+                    continue;
+                }
+
+                let PcapFormat::Pcapng {
+                    big_endian,
+                    interfaces,
+                } = &mut self.format
+                else {
+                    unreachable!();
+                };
+                let big_endian = *big_endian;
+
+                let block_type = parse_u32(&header[0..4], big_endian);
+                let block_len = parse_u32(&header[4..8], big_endian) as usize;
+                if block_len < 12 || block_len > MAX_RECORD_LEN || !block_len.is_multiple_of(4) {
+                    anyhow::bail!("invalid pcapng block length: {block_len}");
+                }
+
+                //Read the block body + trailing length
+                let mut body = vec![0u8; block_len - 8];
+                if !read_exact_or_truncated(&mut self.reader, &mut body)
+                    .context("failed to read pcapng block body")?
+                {
+                    return Ok(None);
+                }
+                body.truncate(block_len - 12);
+
+                match block_type {
+                    PCAPNG_IDB_TYPE => {
+                        if body.len() < 8 {
+                            anyhow::bail!("truncated pcapng interface description block");
+                        }
+
+                        //Parse the interface options for the timestamp resolution
+                        let mut ts_units_per_sec = 1_000_000u128;
+                        for (code, value) in PcapngOptionIter::new(&body[8..], big_endian) {
+                            if code == PCAPNG_OPT_IF_TSRESOL && !value.is_empty() {
+                                ts_units_per_sec = if value[0] & 0x80 != 0 {
+                                    1u128 << (value[0] & 0x7f).min(64)
+                                } else {
+                                    10u128.pow((value[0] as u32).min(19))
+                                };
+                            }
+                        }
+
+                        interfaces.push(PcapngInterface {
+                            link_type: parse_u16(&body[0..2], big_endian) as u32,
+                            ts_units_per_sec,
+                        });
+                    }
+                    PCAPNG_EPB_TYPE | PCAPNG_OPB_TYPE => {
+                        if body.len() < 20 {
+                            anyhow::bail!("truncated pcapng packet block");
+                        }
+
+                        //Obtain the interface the packet was captured on
+                        let if_id = if block_type == PCAPNG_EPB_TYPE {
+                            parse_u32(&body[0..4], big_endian) as usize
+                        } else {
+                            parse_u16(&body[0..2], big_endian) as usize
+                        };
+                        let interface = *interfaces.get(if_id).with_context(|| {
+                            format!("pcapng packet block references unknown interface {if_id}")
+                        })?;
+
+                        //Parse the timestamp
+                        let ts = (parse_u32(&body[4..8], big_endian) as u128) << 32
+                            | parse_u32(&body[8..12], big_endian) as u128;
+                        let ts_nanos = ts * 1_000_000_000 / interface.ts_units_per_sec;
+
+                        //Extract the packet data
+                        let cap_len = parse_u32(&body[12..16], big_endian) as usize;
+                        let data = body
+                            .get(20..20 + cap_len)
+                            .context("pcapng packet block data exceeds block length")?;
+
+                        return Ok(Some(PcapRecord {
+                            link_type: interface.link_type,
+                            timestamp: Duration::new(
+                                (ts_nanos / 1_000_000_000) as u64,
+                                (ts_nanos % 1_000_000_000) as u32,
+                            ),
+                            data: Vec::from(data),
+                        }));
+                    }
+                    PCAPNG_SPB_TYPE => {
+                        if body.len() < 4 {
+                            anyhow::bail!("truncated pcapng simple packet block");
+                        }
+
+                        //Simple packet blocks always belong to the first interface
+                        let interface = *interfaces
+                            .first()
+                            .context("pcapng simple packet block without an interface")?;
+
+                        let orig_len = parse_u32(&body[0..4], big_endian) as usize;
+                        let data = &body[4..(4 + orig_len).min(body.len())];
+
+                        return Ok(Some(PcapRecord {
+                            link_type: interface.link_type,
+                            timestamp: Duration::ZERO,
+                            data: Vec::from(data),
+                        }));
+                    }
+
+                    //Skip all other blocks
+                    _ => {}
+                }
+            },
+        }
+    }
+
+    fn read_pcapng_section_header(reader: &mut R, format: &mut PcapFormat) -> anyhow::Result<()> {
+        let mut len = [0u8; 4];
+        reader
+            .read_exact(&mut len)
+            .context("failed to read pcapng section header length")?;
+        Self::read_pcapng_section_body(reader, format, len)
+    }
+
+    fn read_pcapng_section_body(
+        reader: &mut R,
+        format: &mut PcapFormat,
+        raw_len: [u8; 4],
+    ) -> anyhow::Result<()> {
+        //Read the byte order magic to determine the section endianness
+        let mut magic = [0u8; 4];
+        reader
+            .read_exact(&mut magic)
+            .context("failed to read pcapng byte order magic")?;
+
+        let big_endian = if u32::from_le_bytes(magic) == PCAPNG_BYTE_ORDER_MAGIC {
+            false
+        } else if u32::from_be_bytes(magic) == PCAPNG_BYTE_ORDER_MAGIC {
+            true
+        } else {
+            anyhow::bail!("invalid pcapng byte order magic: {}", hex::encode(magic));
+        };
+
+        //Skip the rest of the block
+        let block_len = parse_u32(&raw_len, big_endian) as usize;
+        if block_len < 28 || block_len > MAX_RECORD_LEN || !block_len.is_multiple_of(4) {
+            anyhow::bail!("invalid pcapng section header block length: {block_len}");
+        }
+
+        let mut rest = vec![0u8; block_len - 12];
+        reader
+            .read_exact(&mut rest)
+            .context("failed to read pcapng section header block")?;
+
+        //Interfaces are scoped to their section
+        *format = PcapFormat::Pcapng {
+            big_endian,
+            interfaces: Vec::new(),
+        };
+
+        Ok(())
+    }
+}
+
+pub struct PcapWriter<W: Write> {
+    writer: W,
+}
+
+impl PcapWriter<BufWriter<File>> {
+    pub fn create(path: &Path, link_type: u32) -> anyhow::Result<PcapWriter<BufWriter<File>>> {
+        let file = File::create(path)
+            .with_context(|| format!("failed to create capture file {}", path.display()))?;
+        PcapWriter::new(BufWriter::new(file), link_type)
+    }
+}
+
+impl<W: Write> PcapWriter<W> {
+    const SNAP_LEN: u32 = 65535;
+
+    pub fn new(mut writer: W, link_type: u32) -> anyhow::Result<PcapWriter<W>> {
+        //Write the global header
+        let mut header = Vec::with_capacity(24);
+        header.extend_from_slice(&PCAP_MAGIC_US.to_le_bytes());
+        header.extend_from_slice(&2u16.to_le_bytes()); //Major version
+        header.extend_from_slice(&4u16.to_le_bytes()); //Minor version
+        header.extend_from_slice(&0i32.to_le_bytes()); //Timezone offset
+        header.extend_from_slice(&0u32.to_le_bytes()); //Timestamp accuracy
+        header.extend_from_slice(&Self::SNAP_LEN.to_le_bytes());
+        header.extend_from_slice(&link_type.to_le_bytes());
+
+        writer
+            .write_all(&header)
+            .context("failed to write pcap header")?;
+
+        Ok(PcapWriter { writer })
+    }
+
+    pub fn write_packet(&mut self, timestamp: Duration, data: &[u8]) -> anyhow::Result<()> {
+        let cap_len = data.len().min(Self::SNAP_LEN as usize);
+
+        let mut header = Vec::with_capacity(16);
+        header.extend_from_slice(&(timestamp.as_secs() as u32).to_le_bytes());
+        header.extend_from_slice(&timestamp.subsec_micros().to_le_bytes());
+        header.extend_from_slice(&(cap_len as u32).to_le_bytes());
+        header.extend_from_slice(&(data.len() as u32).to_le_bytes());
+
+        self.writer
+            .write_all(&header)
+            .and_then(|_| self.writer.write_all(&data[..cap_len]))
+            .context("failed to write pcap record")
+    }
+
+    pub fn flush(&mut self) -> anyhow::Result<()> {
+        self.writer
+            .flush()
+            .context("failed to flush pcap capture file")
+    }
+}
+
+pub struct PcapngWriter<W: Write> {
+    writer: W,
+    num_interfaces: u32,
+}
+
+impl PcapngWriter<BufWriter<File>> {
+    pub fn create(path: &Path) -> anyhow::Result<PcapngWriter<BufWriter<File>>> {
+        let file = File::create(path)
+            .with_context(|| format!("failed to create capture file {}", path.display()))?;
+        PcapngWriter::new(BufWriter::new(file))
+    }
+}
+
+impl<W: Write> PcapngWriter<W> {
+    const SNAP_LEN: u32 = 65535;
+
+    pub fn new(writer: W) -> anyhow::Result<PcapngWriter<W>> {
+        let mut writer = PcapngWriter {
+            writer,
+            num_interfaces: 0,
+        };
+
+        //Write the section header block
+        let mut body = Vec::new();
+        body.extend_from_slice(&PCAPNG_BYTE_ORDER_MAGIC.to_le_bytes());
+        body.extend_from_slice(&1u16.to_le_bytes()); //Major version
+        body.extend_from_slice(&0u16.to_le_bytes()); //Minor version
+        body.extend_from_slice(&(-1i64).to_le_bytes()); //Unspecified section length
+
+        writer
+            .write_block(PCAPNG_SHB_TYPE, &body)
+            .context("failed to write pcapng section header block")?;
+
+        Ok(writer)
+    }
+
+    pub fn add_interface(&mut self, link_type: u32, name: &str) -> anyhow::Result<u32> {
+        //Build the interface description block
+        let mut body = Vec::new();
+        body.extend_from_slice(&(link_type as u16).to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes()); //Reserved
+        body.extend_from_slice(&Self::SNAP_LEN.to_le_bytes());
+
+        // - options (all timestamps are written with the default microsecond resolution)
+        push_pcapng_option(&mut body, PCAPNG_OPT_IF_NAME, name.as_bytes());
+        push_pcapng_option(&mut body, PCAPNG_OPT_ENDOFOPT, &[]);
+
+        self.write_block(PCAPNG_IDB_TYPE, &body)
+            .context("failed to write pcapng interface description block")?;
+
+        self.num_interfaces += 1;
+        Ok(self.num_interfaces - 1)
+    }
+
+    pub fn write_packet(
+        &mut self,
+        interface_id: u32,
+        timestamp: Duration,
+        data: &[u8],
+    ) -> anyhow::Result<()> {
+        assert!(interface_id < self.num_interfaces);
+
+        //Build the enhanced packet block
+        let ts = timestamp.as_micros() as u64;
+        let cap_len = data.len().min(Self::SNAP_LEN as usize);
+
+        let mut body = Vec::with_capacity(20 + cap_len + 3);
+        body.extend_from_slice(&interface_id.to_le_bytes());
+        body.extend_from_slice(&((ts >> 32) as u32).to_le_bytes());
+        body.extend_from_slice(&(ts as u32).to_le_bytes());
+        body.extend_from_slice(&(cap_len as u32).to_le_bytes());
+        body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        body.extend_from_slice(&data[..cap_len]);
+        body.resize(body.len().next_multiple_of(4), 0);
+
+        self.write_block(PCAPNG_EPB_TYPE, &body)
+            .context("failed to write pcapng enhanced packet block")
+    }
+
+    pub fn flush(&mut self) -> anyhow::Result<()> {
+        self.writer
+            .flush()
+            .context("failed to flush pcapng capture file")
+    }
+
+    fn write_block(&mut self, block_type: u32, body: &[u8]) -> std::io::Result<()> {
+        assert!(body.len().is_multiple_of(4));
+
+        let block_len = (12 + body.len()) as u32;
+        self.writer.write_all(&block_type.to_le_bytes())?;
+        self.writer.write_all(&block_len.to_le_bytes())?;
+        self.writer.write_all(body)?;
+        self.writer.write_all(&block_len.to_le_bytes())
+    }
+}
+
+fn push_pcapng_option(body: &mut Vec<u8>, code: u16, value: &[u8]) {
+    body.extend_from_slice(&code.to_le_bytes());
+    body.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    body.extend_from_slice(value);
+    body.resize(body.len().next_multiple_of(4), 0);
+}
+
+struct PcapngOptionIter<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> PcapngOptionIter<'a> {
+    fn new(data: &'a [u8], big_endian: bool) -> PcapngOptionIter<'a> {
+        PcapngOptionIter { data, big_endian }
+    }
+}
+
+impl<'a> Iterator for PcapngOptionIter<'a> {
+    type Item = (u16, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.len() < 4 {
+            return None;
+        }
+
+        let code = parse_u16(&self.data[0..2], self.big_endian);
+        let len = parse_u16(&self.data[2..4], self.big_endian) as usize;
+        if code == PCAPNG_OPT_ENDOFOPT || self.data.len() < 4 + len {
+            return None;
+        }
+
+        let value = &self.data[4..4 + len];
+        self.data = &self.data[(4 + len.next_multiple_of(4)).min(self.data.len())..];
+        Some((code, value))
+    }
+}
+
+fn parse_u16(bytes: &[u8], big_endian: bool) -> u16 {
+    let bytes = [bytes[0], bytes[1]];
+    if big_endian {
+        u16::from_be_bytes(bytes)
+    } else {
+        u16::from_le_bytes(bytes)
+    }
+}
+
+fn parse_u32(bytes: &[u8], big_endian: bool) -> u32 {
+    let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+    if big_endian {
+        u32::from_be_bytes(bytes)
+    } else {
+        u32::from_le_bytes(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pcapng_roundtrip() {
+        //Write a small capture with two interfaces
+        let mut buf = Vec::new();
+        {
+            let mut writer = PcapngWriter::new(&mut buf).unwrap();
+            let if_a = writer.add_interface(LINKTYPE_IEEE802_11, "a").unwrap();
+            let if_b = writer
+                .add_interface(LINKTYPE_IEEE802_11_RADIOTAP, "interface b")
+                .unwrap();
+
+            writer
+                .write_packet(if_a, Duration::from_micros(1_500_000), &[1, 2, 3])
+                .unwrap();
+            writer
+                .write_packet(if_b, Duration::from_secs(2), &[4, 5, 6, 7, 8])
+                .unwrap();
+        }
+
+        //Read it back again
+        let mut reader = PcapReader::new(&buf[..]).unwrap();
+
+        let rec = reader.next_record().unwrap().unwrap();
+        assert_eq!(rec.link_type, LINKTYPE_IEEE802_11);
+        assert_eq!(rec.timestamp, Duration::from_micros(1_500_000));
+        assert_eq!(rec.data, [1, 2, 3]);
+
+        let rec = reader.next_record().unwrap().unwrap();
+        assert_eq!(rec.link_type, LINKTYPE_IEEE802_11_RADIOTAP);
+        assert_eq!(rec.timestamp, Duration::from_secs(2));
+        assert_eq!(rec.data, [4, 5, 6, 7, 8]);
+
+        assert!(reader.next_record().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_pcap_roundtrip() {
+        let mut buf = Vec::new();
+        {
+            let mut writer = PcapWriter::new(&mut buf, LINKTYPE_ETHERNET).unwrap();
+            writer
+                .write_packet(Duration::from_micros(3_000_042), &[1, 2, 3, 4])
+                .unwrap();
+        }
+
+        let mut reader = PcapReader::new(&buf[..]).unwrap();
+
+        let rec = reader.next_record().unwrap().unwrap();
+        assert_eq!(rec.link_type, LINKTYPE_ETHERNET);
+        assert_eq!(rec.timestamp, Duration::from_micros(3_000_042));
+        assert_eq!(rec.data, [1, 2, 3, 4]);
+
+        assert!(reader.next_record().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_oversized_record() {
+        let mut buf = Vec::new();
+        {
+            let mut writer = PcapWriter::new(&mut buf, LINKTYPE_ETHERNET).unwrap();
+            writer.write_packet(Duration::ZERO, &[1, 2, 3, 4]).unwrap();
+        }
+
+        //Corrupt the record's included length
+        buf[32..36].copy_from_slice(&u32::MAX.to_le_bytes());
+        let mut reader = PcapReader::new(&buf[..]).unwrap();
+        assert!(reader.next_record().is_err());
+
+        let mut buf = Vec::new();
+        {
+            let mut writer = PcapngWriter::new(&mut buf).unwrap();
+            let if_id = writer.add_interface(LINKTYPE_IEEE802_11, "a").unwrap();
+            writer
+                .write_packet(if_id, Duration::ZERO, &[1, 2, 3])
+                .unwrap();
+        }
+
+        //Corrupt the section header block length
+        let mut corrupt = buf.clone();
+        corrupt[4..8].copy_from_slice(&0xfffffff0u32.to_le_bytes());
+        assert!(PcapReader::new(&corrupt[..]).is_err());
+
+        //Corrupt the packet block length
+        let shb_len = u32::from_le_bytes(buf[4..8].try_into().unwrap()) as usize;
+        let idb_len =
+            u32::from_le_bytes(buf[shb_len + 4..shb_len + 8].try_into().unwrap()) as usize;
+        let epb = shb_len + idb_len;
+        buf[epb + 4..epb + 8].copy_from_slice(&0xfffffff0u32.to_le_bytes());
+        let mut reader = PcapReader::new(&buf[..]).unwrap();
+        assert!(reader.next_record().is_err());
+    }
+
+    #[test]
+    fn test_truncated_capture() {
+        let mut buf = Vec::new();
+        {
+            let mut writer = PcapngWriter::new(&mut buf).unwrap();
+            let if_id = writer.add_interface(LINKTYPE_IEEE802_11, "a").unwrap();
+            writer
+                .write_packet(if_id, Duration::ZERO, &[1, 2, 3])
+                .unwrap();
+            writer
+                .write_packet(if_id, Duration::ZERO, &[4, 5, 6])
+                .unwrap();
+        }
+
+        //A capture cut off in the middle of the last record should end after the last complete one
+        for cut in [4, 12] {
+            let mut reader = PcapReader::new(&buf[..buf.len() - cut]).unwrap();
+            assert_eq!(reader.next_record().unwrap().unwrap().data, [1, 2, 3]);
+            assert!(reader.next_record().unwrap().is_none());
+        }
+
+        let mut buf = Vec::new();
+        {
+            let mut writer = PcapWriter::new(&mut buf, LINKTYPE_ETHERNET).unwrap();
+            writer.write_packet(Duration::ZERO, &[1, 2, 3, 4]).unwrap();
+            writer.write_packet(Duration::ZERO, &[5, 6, 7, 8]).unwrap();
+        }
+
+        for cut in [2, 6] {
+            let mut reader = PcapReader::new(&buf[..buf.len() - cut]).unwrap();
+            assert_eq!(reader.next_record().unwrap().unwrap().data, [1, 2, 3, 4]);
+            assert!(reader.next_record().unwrap().is_none());
+        }
+    }
+}
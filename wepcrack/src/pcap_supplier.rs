@@ -0,0 +1,169 @@
+use std::{
+    fs::File,
+    io::BufReader,
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use anyhow::Context;
+use ieee80211::{DSStatus, DataFrame, Frame, FrameLayer, FrameTrait, MacAddress};
+use radiotap::Radiotap;
+
+use crate::{
+    arp_supplier::ARPSampleSupplier,
+    keycracker::KeystreamSample,
+    pcap::{PcapReader, PcapRecord, LINKTYPE_IEEE802_11, LINKTYPE_IEEE802_11_RADIOTAP},
+    wep::WepFrame,
+};
+
+pub struct PcapSampleSupplier {
+    reader: PcapReader<BufReader<File>>,
+    bssid: Option<MacAddress>,
+
+    num_records: usize,
+    num_samples: usize,
+    is_exhausted: bool,
+    error: Option<anyhow::Error>,
+}
+
+impl PcapSampleSupplier {
+    pub fn open(path: &Path, bssid: Option<MacAddress>) -> anyhow::Result<PcapSampleSupplier> {
+        Ok(PcapSampleSupplier {
+            reader: PcapReader::open(path).context("failed to open capture file")?,
+            bssid,
+
+            num_records: 0,
+            num_samples: 0,
+            is_exhausted: false,
+            error: None,
+        })
+    }
+
+    pub fn read_test_frames(
+        path: &Path,
+        bssid: Option<MacAddress>,
+        max_frames: usize,
+    ) -> anyhow::Result<Vec<WepFrame>> {
+        let mut reader = PcapReader::open(path).context("failed to open capture file")?;
+
+        //Any protected frame can be used, no matter what its plaintext is
+        let mut frames = Vec::with_capacity(max_frames);
+        while frames.len() < max_frames {
+            let Some(record) = reader
+                .next_record()
+                .context("failed to read record from capture file")?
+            else {
+                break;
+            };
+
+            frames.extend(parse_protected_frame(
+                &record,
+                bssid,
+                WepFrame::from_data_frame,
+            ));
+        }
+
+        Ok(frames)
+    }
+
+    pub const fn num_records(&self) -> usize {
+        self.num_records
+    }
+
+    pub const fn num_samples(&self) -> usize {
+        self.num_samples
+    }
+
+    pub const fn is_exhausted(&self) -> bool {
+        self.is_exhausted
+    }
+
+    pub fn take_error(&mut self) -> Option<anyhow::Error> {
+        self.error.take()
+    }
+
+    pub fn provide_sample(&mut self, should_exit: &AtomicBool) -> Option<KeystreamSample> {
+        while !self.is_exhausted && !should_exit.load(Ordering::SeqCst) {
+            //Read the next record from the capture
+            //Samples can't carry errors, so stop reading and keep the error around for the caller
+            let record = match self
+                .reader
+                .next_record()
+                .context("failed to read record from capture file")
+            {
+                Ok(Some(record)) => record,
+                Ok(None) => {
+                    self.is_exhausted = true;
+                    break;
+                }
+                Err(err) => {
+                    self.is_exhausted = true;
+                    self.error = Some(err);
+                    break;
+                }
+            };
+            self.num_records += 1;
+
+            //Try to recover a sample from it
+            if let Some(sample) = self.recover_sample(&record) {
+                self.num_samples += 1;
+                return Some(sample);
+            }
+        }
+
+        None
+    }
+
+    fn recover_sample(&self, record: &PcapRecord) -> Option<KeystreamSample> {
+        //Recover the keystream the same way we would for a live capture
+        parse_protected_frame(record, self.bssid, ARPSampleSupplier::recover_sample)
+    }
+}
+
+pub fn parse_protected_frame<T>(
+    record: &PcapRecord,
+    bssid: Option<MacAddress>,
+    parse_func: impl FnOnce(&DataFrame, bool) -> Option<T>,
+) -> Option<T> {
+    //Extract the 802.11 frame from the record
+    let (frame, has_fcs) = match record.link_type {
+        LINKTYPE_IEEE802_11 => (&record.data[..], false),
+        LINKTYPE_IEEE802_11_RADIOTAP => {
+            let (radiotap, frame) = Radiotap::parse(&record.data).ok()?;
+
+            //Skip frames we injected ourselves, as replays would just repeat the same IV over and over
+            if radiotap.tx_flags.is_some() {
+                return None;
+            }
+            (frame, radiotap.flags.map_or(false, |flags| flags.fcs))
+        }
+        _ => return None,
+    };
+    if frame.len() < 24 {
+        return None;
+    }
+    let frame = Frame::new(frame);
+
+    //Check if the frame belongs to the target network
+    if let Some(bssid) = bssid {
+        let frame_bssid = match frame.ds_status() {
+            DSStatus::NotLeavingDSOrADHOC => &frame.bytes()[16..22],
+            DSStatus::FromDSToSTA => &frame.bytes()[10..16],
+            DSStatus::FromSTAToDS => &frame.bytes()[4..10],
+            DSStatus::WDSOrMesh => return None,
+        };
+        if frame_bssid != bssid.as_bytes() {
+            return None;
+        }
+    }
+
+    //Check if this is an encrypted data frame
+    let Some(FrameLayer::Data(data)) = frame.next_layer() else {
+        return None;
+    };
+    if !data.protected() {
+        return None;
+    }
+
+    parse_func(&data, has_fcs)
+}
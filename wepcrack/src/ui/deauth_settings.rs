@@ -0,0 +1,158 @@
+use std::{marker::PhantomData, time::Duration};
+
+use crossterm::event::{Event, KeyCode, KeyEventKind};
+use ratatui::{
+    prelude::{Margin, Rect},
+    style::Stylize,
+    text::{Line, Span},
+    widgets::Paragraph,
+    Frame,
+};
+
+use crate::{
+    deauth::{DeauthDirection, DeauthFrameKind, DeauthSettings, DeauthTarget},
+    ui::draw_ui_widget_border,
+};
+
+use super::UIWidget;
+
+const NUM_OPTIONS: usize = 6;
+
+const MAX_BURST_COUNT: usize = 64;
+const BURST_INTERVAL_STEP: Duration = Duration::from_millis(10);
+const MAX_BURST_INTERVAL: Duration = Duration::from_secs(1);
+
+pub struct DeauthSettingsWidget<S> {
+    settings: DeauthSettings,
+    selected_opt: usize,
+
+    _s: PhantomData<S>,
+}
+
+impl<S> DeauthSettingsWidget<S> {
+    pub fn new(settings: DeauthSettings) -> DeauthSettingsWidget<S> {
+        DeauthSettingsWidget {
+            settings,
+            selected_opt: 0,
+            _s: PhantomData,
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &Event) -> Option<DeauthSettings> {
+        let Event::Key(key) = event else {
+            return None;
+        };
+        if key.kind == KeyEventKind::Release {
+            return None;
+        }
+
+        match key.code {
+            KeyCode::Up => self.selected_opt = (self.selected_opt + NUM_OPTIONS - 1) % NUM_OPTIONS,
+            KeyCode::Down => self.selected_opt = (self.selected_opt + 1) % NUM_OPTIONS,
+            KeyCode::Left => self.change_option(false),
+            KeyCode::Right => self.change_option(true),
+            KeyCode::Enter => return Some(self.settings),
+            _ => {}
+        }
+        None
+    }
+
+    fn change_option(&mut self, increase: bool) {
+        let settings = &mut self.settings;
+        match self.selected_opt {
+            0 => {
+                settings.target = match settings.target {
+                    DeauthTarget::Client => DeauthTarget::Broadcast,
+                    DeauthTarget::Broadcast => DeauthTarget::Client,
+                }
+            }
+            1 => {
+                settings.direction = match (settings.direction, increase) {
+                    (DeauthDirection::APToClient, true) | (DeauthDirection::Both, false) => {
+                        DeauthDirection::ClientToAP
+                    }
+                    (DeauthDirection::ClientToAP, true) | (DeauthDirection::APToClient, false) => {
+                        DeauthDirection::Both
+                    }
+                    (DeauthDirection::Both, true) | (DeauthDirection::ClientToAP, false) => {
+                        DeauthDirection::APToClient
+                    }
+                }
+            }
+            2 => {
+                settings.frame_kind = match settings.frame_kind {
+                    DeauthFrameKind::Deauthentication => DeauthFrameKind::Disassociation,
+                    DeauthFrameKind::Disassociation => DeauthFrameKind::Deauthentication,
+                }
+            }
+            3 => {
+                settings.reason_code = if increase {
+                    settings.reason_code.saturating_add(1)
+                } else {
+                    settings.reason_code.saturating_sub(1).max(1)
+                }
+            }
+            4 => {
+                settings.burst_count = if increase {
+                    (settings.burst_count + 1).min(MAX_BURST_COUNT)
+                } else {
+                    (settings.burst_count - 1).max(1)
+                }
+            }
+            5 => {
+                settings.burst_interval = if increase {
+                    (settings.burst_interval + BURST_INTERVAL_STEP).min(MAX_BURST_INTERVAL)
+                } else {
+                    settings.burst_interval.saturating_sub(BURST_INTERVAL_STEP)
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<S> UIWidget<'_> for DeauthSettingsWidget<S> {
+    type SharedState = S;
+
+    fn size(&self, _: &S) -> u16 {
+        4 + NUM_OPTIONS as u16
+    }
+
+    fn draw(&mut self, _: &S, frame: &mut Frame, area: Rect) {
+        draw_ui_widget_border("Deauthentication Settings", frame, area);
+
+        let options = [
+            ("Target", self.settings.target.to_string()),
+            ("Direction", self.settings.direction.to_string()),
+            ("Frame kind", self.settings.frame_kind.to_string()),
+            ("Reason code", self.settings.reason_code.to_string()),
+            ("Burst count", self.settings.burst_count.to_string()),
+            (
+                "Burst interval",
+                format!("{}ms", self.settings.burst_interval.as_millis()),
+            ),
+        ];
+
+        //Draw the options, followed by the controls
+        let mut lines = options
+            .into_iter()
+            .enumerate()
+            .map(|(idx, (name, val))| {
+                let opt = Span::from(format!("{name}: < {val} >"));
+                Line::from(if idx == self.selected_opt {
+                    vec!["> ".into(), opt.bold()]
+                } else {
+                    vec!["  ".into(), opt]
+                })
+            })
+            .collect::<Vec<_>>();
+        lines.push(Line::from(""));
+        lines.push(
+            "Up/Down: select option, Left/Right: change value, Enter: launch attack"
+                .italic()
+                .into(),
+        );
+
+        frame.render_widget(Paragraph::new(lines), area.inner(&Margin::new(1, 1)));
+    }
+}
@@ -7,13 +7,10 @@ use ratatui::{
 };
 
 use crate::{
-    keycracker::{KeyBytePrediction, KeyTester},
+    keycracker::{KeyBytePrediction, KeyCracker, KeyTester},
     ui::{draw_ui_widget_border, UIWidget},
-    wep::WepKey,
 };
 
-use super::KeyCracker;
-
 pub(super) struct CandidateKeyTestingWidget;
 
 impl CandidateKeyTestingWidget {
@@ -24,24 +21,38 @@ impl CandidateKeyTestingWidget {
     fn draw_info(&self, tester: &KeyTester, frame: &mut Frame, area: Rect) {
         let layout = Layout::new()
             .direction(Direction::Horizontal)
-            .constraints([Constraint::Length(15), Constraint::Min(0)])
+            .constraints([
+                Constraint::Length(15),
+                Constraint::Length(10),
+                Constraint::Length(10),
+                Constraint::Min(0),
+            ])
             .split(area);
 
-        frame.render_widget(Paragraph::new("maybe WEP40:".bold()), layout[0]);
+        //Show the length of the current candidate, which can be shorter than the cracked key length
+        let key_len = tester.current_key_len();
+        frame.render_widget(Paragraph::new("key length:".bold()), layout[0]);
         frame.render_widget(
-            Paragraph::new(match tester.is_maybe_wep40() {
-                true => "yes".green(),
-                false => "no".red(),
+            Paragraph::new(if key_len < tester.key_len() {
+                format!("WEP-{}", key_len * 8).green()
+            } else {
+                format!("WEP-{}", key_len * 8).red()
             }),
             layout[1],
         );
+
+        frame.render_widget(Paragraph::new("key cost:".bold()), layout[2]);
+        frame.render_widget(
+            Paragraph::new(format!("{:.2}", tester.current_cost())),
+            layout[3],
+        );
     }
 
     fn draw_candidate_key(&self, tester: &KeyTester, frame: &mut Frame, area: Rect) {
         //Construct the line
         let key = tester.current_key();
         let mut line = Vec::<Span<'_>>::new();
-        for (i, keybyte) in key.iter().enumerate() {
+        for (i, keybyte) in key.key().iter().enumerate() {
             if i > 0 {
                 line.push(" ".into());
             }
@@ -67,16 +78,14 @@ impl CandidateKeyTestingWidget {
     fn draw_l_indices(&self, tester: &KeyTester, frame: &mut Frame, area: Rect) {
         //Construct the line
         let mut line = Vec::<Span<'_>>::new();
-        for i in 0..WepKey::LEN_104 {
+        for (i, l_idx) in tester.current_l_indices().into_iter().enumerate() {
             if i > 0 {
                 line.push(" ".into());
             }
 
-            line.push(match tester.key_predictions()[i] {
-                KeyBytePrediction::Normal { sigma: _ } => "--".on_light_magenta(),
-                KeyBytePrediction::Strong => {
-                    format!("{:2}", tester.current_l_indices()[i]).on_light_cyan()
-                }
+            line.push(match l_idx {
+                usize::MAX => "--".on_light_magenta(),
+                l_idx => format!("{:2}", l_idx).on_light_cyan(),
             });
         }
 
@@ -89,13 +98,37 @@ impl CandidateKeyTestingWidget {
         frame.render_widget(Paragraph::new("current l-indices:".bold()), layout[0]);
         frame.render_widget(Paragraph::new(Line::from(line)), layout[1]);
     }
+
+    fn draw_sigma_ranks(&self, tester: &KeyTester, frame: &mut Frame, area: Rect) {
+        //Construct the line
+        let mut line = Vec::<Span<'_>>::new();
+        for (i, sigma_rank) in tester.current_sigma_ranks().into_iter().enumerate() {
+            if i > 0 {
+                line.push(" ".into());
+            }
+
+            line.push(match sigma_rank {
+                usize::MAX => "--".on_light_cyan(),
+                rank => format!("{:2}", rank).on_light_magenta(),
+            });
+        }
+
+        //Draw the line
+        let layout = Layout::new()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Length(20), Constraint::Min(0)])
+            .split(area);
+
+        frame.render_widget(Paragraph::new("current sigma ranks:".bold()), layout[0]);
+        frame.render_widget(Paragraph::new(Line::from(line)), layout[1]);
+    }
 }
 
 impl UIWidget<'_> for CandidateKeyTestingWidget {
     type SharedState = KeyCracker;
 
     fn size(&self, _cracker: &KeyCracker) -> u16 {
-        5
+        6
     }
 
     fn draw(&mut self, cracker: &KeyCracker, frame: &mut Frame, area: Rect) {
@@ -103,12 +136,13 @@ impl UIWidget<'_> for CandidateKeyTestingWidget {
         draw_ui_widget_border("Candidate Key Testing", frame, area);
 
         //Calculate the layout
-        let [info_layout, cand_key_layout, l_idxs_layout] = Layout::default()
+        let [info_layout, cand_key_layout, l_idxs_layout, sigma_ranks_layout] = Layout::default()
             .margin(1)
             .constraints([
                 Constraint::Length(1),
                 Constraint::Length(1),
                 Constraint::Length(1),
+                Constraint::Length(1),
             ])
             .split(area)[..]
         else {
@@ -123,5 +157,8 @@ impl UIWidget<'_> for CandidateKeyTestingWidget {
 
         //Draw the l indices
         self.draw_l_indices(tester, frame, l_idxs_layout);
+
+        //Draw the sigma ranks
+        self.draw_sigma_ranks(tester, frame, sigma_ranks_layout);
     }
 }
@@ -6,9 +6,12 @@ use std::{
     thread::JoinHandle,
 };
 
-use crate::util::RecessiveMutex;
-
-use super::{KeyCracker, KeyCrackerSampleProvider, KeyCrackerSettings};
+use crate::{
+    keycracker::{KeyCracker, KeyCrackerSampleProvider, KeyCrackerSettings},
+    session::SessionWriter,
+    util::RecessiveMutex,
+    wep::WepFrame,
+};
 
 pub(super) struct KeyCrackerThread {
     thread: Option<JoinHandle<()>>,
@@ -17,7 +20,11 @@ pub(super) struct KeyCrackerThread {
 }
 
 impl KeyCrackerThread {
-    fn cracker_thread_func(should_exit: &AtomicBool, state: &RecessiveMutex<KeyCracker>) {
+    fn cracker_thread_func(
+        should_exit: &AtomicBool,
+        state: &RecessiveMutex<KeyCracker>,
+        mut session: Option<SessionWriter>,
+    ) {
         while !should_exit.load(atomic::Ordering::SeqCst) {
             //Lock the cracker state
             let Ok(mut state) = state.lock_recessive() else {
@@ -31,22 +38,37 @@ impl KeyCrackerThread {
             } else {
                 //Indicate we're exiting cleanly
                 should_exit.store(true, atomic::Ordering::SeqCst);
-                return;
+                break;
+            }
+
+            //Occasionally save the session
+            if let Some(session) = &mut session {
+                session
+                    .save_periodically(&state)
+                    .expect("failed to save session");
             }
         }
+
+        //Save the final state of the session
+        if let (Some(session), Ok(state)) = (&mut session, state.lock_recessive()) {
+            session.save(&state).expect("failed to save session");
+        }
     }
 
     pub fn launch(
         settings: KeyCrackerSettings,
         sample_provider: Box<KeyCrackerSampleProvider>,
+        test_frames: &[WepFrame],
+        session: Option<SessionWriter>,
     ) -> KeyCrackerThread {
         //Create the thread state
         let should_exit = Arc::new(AtomicBool::new(false));
-        let state = Arc::new(RecessiveMutex::new(KeyCracker::new(
-            settings,
-            sample_provider,
-            should_exit.clone(),
-        )));
+
+        let mut cracker = KeyCracker::new(settings, sample_provider, should_exit.clone());
+        for frame in test_frames {
+            cracker.accept_test_frame(frame);
+        }
+        let state = Arc::new(RecessiveMutex::new(cracker));
 
         //Launch the key cracker thread
         let thread = {
@@ -54,7 +76,7 @@ impl KeyCrackerThread {
             let state = state.clone();
             std::thread::Builder::new()
                 .name("key cracker thread".into())
-                .spawn(move || Self::cracker_thread_func(&should_exit, &state))
+                .spawn(move || Self::cracker_thread_func(&should_exit, &state, session))
                 .expect("failed to spawn cracker thread")
         };
 
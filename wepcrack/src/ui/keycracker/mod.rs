@@ -1,12 +1,10 @@
 mod candidate_testing;
-mod cracker;
 mod cracker_thread;
 mod overview;
 mod scene;
 mod sigma_info;
 
 use candidate_testing::*;
-pub use cracker::*;
 use cracker_thread::*;
 use overview::*;
 pub use scene::*;
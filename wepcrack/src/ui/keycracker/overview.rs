@@ -1,4 +1,7 @@
-use std::time::Instant;
+use std::{
+    sync::{Arc, Mutex},
+    time::Instant,
+};
 
 use ratatui::{
     layout::{Constraint, Layout, Rect},
@@ -9,9 +12,11 @@ use ratatui::{
     Frame,
 };
 
-use crate::ui::UIWidget;
-
-use super::{KeyCracker, KeyCrackerPhase};
+use crate::{
+    keycracker::{KeyCracker, KeyCrackerPhase},
+    replay_rate::ReplayRateController,
+    ui::UIWidget,
+};
 
 pub(super) struct OverviewWidget {
     start_time: Instant,
@@ -20,10 +25,12 @@ pub(super) struct OverviewWidget {
     last_draw: Instant,
     last_draw_samples: usize,
     smoothed_sample_rate: f64,
+
+    replay_rate: Option<Arc<Mutex<ReplayRateController>>>,
 }
 
 impl OverviewWidget {
-    pub fn new() -> OverviewWidget {
+    pub fn new(replay_rate: Option<Arc<Mutex<ReplayRateController>>>) -> OverviewWidget {
         OverviewWidget {
             start_time: Instant::now(),
             end_time: None,
@@ -31,6 +38,8 @@ impl OverviewWidget {
             last_draw: Instant::now(),
             last_draw_samples: 0,
             smoothed_sample_rate: 0.,
+
+            replay_rate,
         }
     }
 
@@ -77,40 +86,113 @@ impl OverviewWidget {
                 layout[1],
             );
         }
+
+        // - number of dropped low-confidence samples
+        if cracker.num_dropped_samples() > 0 {
+            frame.render_widget(
+                Paragraph::new(Line::from(vec![
+                    "dropped: ".bold(),
+                    format!("{}", cracker.num_dropped_samples()).into(),
+                ])),
+                layout[2],
+            );
+        }
     }
 
-    fn draw_test_buf_stats(&self, cracker: &KeyCracker, frame: &mut Frame, area: Rect) {
-        let layout: std::rc::Rc<[Rect]> = Layout::default()
+    fn draw_replay_stats(replay_rate: &Mutex<ReplayRateController>, frame: &mut Frame, area: Rect) {
+        let stats = *replay_rate
+            .lock()
+            .expect("failed to lock replay rate controller")
+            .stats();
+
+        let layout = Layout::default()
             .direction(Direction::Horizontal)
             .constraints([
                 Constraint::Length(40),
                 Constraint::Length(25),
+                Constraint::Length(25),
                 Constraint::Min(0),
             ])
             .split(area);
 
-        // - utilization
+        // - current / target injection rate
         frame.render_widget(
             Paragraph::new(Line::from(vec![
-                "test sample buffer: ".bold(),
+                "replay rate: ".bold(),
                 format!(
-                    "{:5} / {:5}",
-                    cracker.test_sample_buf().num_samples(),
-                    cracker.settings().num_test_samples
+                    "{:7.1} / {:7.1} pkts/s",
+                    stats.injection_rate, stats.target_rate
                 )
                 .into(),
             ])),
             layout[0],
         );
 
+        // - response / unique IV rate
+        frame.render_widget(
+            Paragraph::new(Line::from(vec![
+                "responses/s: ".bold(),
+                format!("{:7.1}", stats.response_rate).into(),
+            ])),
+            layout[1],
+        );
+        frame.render_widget(
+            Paragraph::new(Line::from(vec![
+                "unique IVs/s: ".bold(),
+                format!("{:7.1}", stats.unique_iv_rate).into(),
+            ])),
+            layout[2],
+        );
+
+        // - stall warning
+        if stats.stalled {
+            frame.render_widget(
+                Paragraph::new("AP stopped responding!".red().bold()),
+                layout[3],
+            );
+        }
+    }
+
+    fn draw_test_buf_stats(&self, cracker: &KeyCracker, frame: &mut Frame, area: Rect) {
+        let layout: std::rc::Rc<[Rect]> = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([
+                Constraint::Length(40),
+                Constraint::Length(25),
+                Constraint::Min(0),
+            ])
+            .split(area);
+
+        //Show the frames used for ICV verification instead of the samples if enabled
+        let test_buf = cracker.test_sample_buf();
+        let (label, num_entries, buffer_size) = if test_buf.uses_frames() {
+            (
+                "test frame buffer: ",
+                test_buf.num_frames(),
+                cracker.settings().num_test_frames,
+            )
+        } else {
+            (
+                "test sample buffer: ",
+                test_buf.num_samples(),
+                cracker.settings().num_test_samples,
+            )
+        };
+
+        // - utilization
+        frame.render_widget(
+            Paragraph::new(Line::from(vec![
+                label.bold(),
+                format!("{num_entries:5} / {buffer_size:5}").into(),
+            ])),
+            layout[0],
+        );
+
         // - gauge
         frame.render_widget(
             LineGauge::default()
                 .gauge_style(Style::new().light_cyan())
-                .ratio(
-                    cracker.test_sample_buf().num_samples() as f64
-                        / cracker.settings().num_test_samples as f64,
-                ),
+                .ratio(num_entries as f64 / buffer_size as f64),
             layout[1],
         );
     }
@@ -118,15 +200,10 @@ impl OverviewWidget {
     fn draw_key_tester_stats(&self, cracker: &KeyCracker, frame: &mut Frame, area: Rect) {
         let tester = cracker.key_tester().unwrap();
 
-        let mut key_idx = tester.current_key_index();
-        if cracker.phase() == KeyCrackerPhase::FinishedSuccess {
-            key_idx += 1;
-        }
-
         frame.render_widget(
             Paragraph::new(Line::from(vec![
                 "tested candidate keys: ".bold(),
-                format!("{key_idx} / {}", tester.num_keys()).into(),
+                format!("{} / {}", cracker.num_tested_keys(), tester.num_keys()).into(),
             ])),
             area,
         );
@@ -137,17 +214,18 @@ impl UIWidget<'_> for OverviewWidget {
     type SharedState = KeyCracker;
 
     fn size(&self, _cracker: &KeyCracker) -> u16 {
-        2 + 1 + 1 + 1 + 1 + 2
+        2 + 1 + 1 + self.replay_rate.is_some() as u16 + 1 + 1 + 2
     }
 
     fn draw(&mut self, cracker: &KeyCracker, frame: &mut Frame, area: Rect) {
         //Calculate the layout
-        let [runtime_layout, sample_stats_layout, test_layout, _, progbar_layout] =
+        let [runtime_layout, sample_stats_layout, replay_layout, test_layout, _, progbar_layout] =
             Layout::default()
                 .margin(1)
                 .constraints([
                     Constraint::Length(1),
                     Constraint::Length(1),
+                    Constraint::Length(self.replay_rate.is_some() as u16),
                     Constraint::Length(1),
                     Constraint::Max(1),
                     Constraint::Length(2),
@@ -196,6 +274,13 @@ impl UIWidget<'_> for OverviewWidget {
         //Draw the sample stats text
         self.draw_sample_stats(cracker, frame, sample_stats_layout);
 
+        //Draw the ARP replay rate while still replaying requests
+        if let Some(replay_rate) = &self.replay_rate {
+            if let KeyCrackerPhase::SampleCollection = cracker.phase() {
+                Self::draw_replay_stats(replay_rate, frame, replay_layout);
+            }
+        }
+
         //Draw the test sample buffer / key tester statistics
         if cracker.phase() < KeyCrackerPhase::CandidateKeyTesting {
             self.draw_test_buf_stats(cracker, frame, test_layout);
@@ -228,14 +313,10 @@ impl UIWidget<'_> for OverviewWidget {
                 layout[0],
             );
             frame.render_widget(
-                Paragraph::new(Line::from(match cracked_key {
-                    crate::wep::WepKey::Wep40Key(key) => {
-                        vec!["WEP-40 key: ".bold(), hex::encode(key).into()]
-                    }
-                    crate::wep::WepKey::Wep104Key(key) => {
-                        vec!["WEP-104 key: ".bold(), hex::encode(key).into()]
-                    }
-                })),
+                Paragraph::new(Line::from(vec![
+                    format!("WEP-{} key: ", cracked_key.bits()).bold(),
+                    hex::encode(cracked_key.key()).into(),
+                ])),
                 layout[1],
             );
         } else {
@@ -1,13 +1,18 @@
+use std::sync::{Arc, Mutex};
+
 use crossterm::event::Event;
 use ratatui::{prelude::Rect, Frame};
 
-use crate::ui::{draw_ui_widgets, UIScene};
-
-use super::{
-    CandidateKeyTestingWidget, KeyCrackerPhase, KeyCrackerSampleProvider, KeyCrackerSettings,
-    KeyCrackerThread, OverviewWidget, SigmaInfoWidget,
+use crate::{
+    keycracker::{KeyCrackerPhase, KeyCrackerSampleProvider, KeyCrackerSettings},
+    replay_rate::ReplayRateController,
+    session::SessionWriter,
+    ui::{draw_ui_widgets, UIScene},
+    wep::WepFrame,
 };
 
+use super::{CandidateKeyTestingWidget, KeyCrackerThread, OverviewWidget, SigmaInfoWidget};
+
 pub struct UIKeyCracker {
     cracker_thread: KeyCrackerThread,
 
@@ -20,11 +25,19 @@ impl UIKeyCracker {
     pub fn new(
         cracker_settings: KeyCrackerSettings,
         sample_provider: Box<KeyCrackerSampleProvider>,
+        test_frames: &[WepFrame],
+        session: Option<SessionWriter>,
+        replay_rate: Option<Arc<Mutex<ReplayRateController>>>,
     ) -> UIKeyCracker {
         UIKeyCracker {
-            cracker_thread: KeyCrackerThread::launch(cracker_settings, sample_provider),
+            cracker_thread: KeyCrackerThread::launch(
+                cracker_settings,
+                sample_provider,
+                test_frames,
+                session,
+            ),
 
-            overview_widget: OverviewWidget::new(),
+            overview_widget: OverviewWidget::new(replay_rate),
             sigma_info_widget: SigmaInfoWidget::new(),
             candidate_testing_widget: CandidateKeyTestingWidget::new(),
         }
@@ -7,13 +7,10 @@ use ratatui::{
 };
 
 use crate::{
-    keycracker::KeyBytePrediction,
+    keycracker::{KeyBytePrediction, KeyCracker},
     ui::{draw_ui_widget_border, UIWidget},
-    wep::WepKey,
 };
 
-use super::KeyCracker;
-
 pub(super) struct SigmaInfoWidget;
 
 impl SigmaInfoWidget {
@@ -25,8 +22,8 @@ impl SigmaInfoWidget {
 impl UIWidget<'_> for SigmaInfoWidget {
     type SharedState = KeyCracker;
 
-    fn size(&self, _cracker: &KeyCracker) -> u16 {
-        2 + WepKey::LEN_104 as u16
+    fn size(&self, cracker: &KeyCracker) -> u16 {
+        2 + cracker.key_predictor().key_len() as u16
     }
 
     fn draw(&mut self, cracker: &KeyCracker, frame: &mut Frame, area: Rect) {
@@ -35,13 +32,13 @@ impl UIWidget<'_> for SigmaInfoWidget {
         //Calculate the layout
         let layout = Layout::default()
             .margin(1)
-            .constraints([Constraint::Length(WepKey::LEN_104 as u16)])
+            .constraints([Constraint::Length(cracker.key_predictor().key_len() as u16)])
             .split(area);
 
         //Draw the list
         let mut sigma_list = Vec::<ListItem>::new();
 
-        for i in 0..WepKey::LEN_104 {
+        for i in 0..cracker.key_predictor().key_len() {
             //Get key byte info
             let info = cracker.key_predictor().key_byte_info(i);
 
@@ -1,8 +1,10 @@
 pub mod app;
 pub mod attack_prep;
 pub mod confirmation;
+pub mod deauth_settings;
 pub use app::*;
 pub use confirmation::*;
+pub use deauth_settings::*;
 
 pub mod dev_select;
 pub mod keycracker;
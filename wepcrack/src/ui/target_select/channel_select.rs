@@ -13,11 +13,10 @@ use ratatui::{
 
 use crate::{
     nl80211::{NL80211Channel, NL80211ChannelBand},
+    target_monitor::TargetMonitor,
     ui::{draw_ui_widget_border, UIWidget},
 };
 
-use super::TargetMonitor;
-
 pub struct UIChannelSelect {
     selected_channel_idx: usize,
     list_scroll: usize,
@@ -2,10 +2,8 @@ mod ap_list;
 mod channel_select;
 mod scene;
 mod target_dev_list;
-mod target_monitor;
 
 use ap_list::*;
 use channel_select::*;
 pub use scene::*;
 use target_dev_list::*;
-use target_monitor::*;
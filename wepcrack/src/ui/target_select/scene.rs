@@ -6,10 +6,11 @@ use ratatui::{prelude::Rect, style::Stylize, text::Line, Frame};
 
 use crate::{
     ieee80211::IEEE80211Monitor,
+    target_monitor::TargetMonitor,
     ui::{draw_ui_widgets, ConfirmationWidget, UIScene},
 };
 
-use super::{TargetMonitor, UIAccessPointList, UIChannelSelect, UITargetDeviceList};
+use super::{UIAccessPointList, UIChannelSelect, UITargetDeviceList};
 
 pub enum TargetSelectState {
     ChannelSelect {
@@ -12,9 +12,10 @@ use ratatui::{
     Frame,
 };
 
-use crate::ui::{draw_ui_widget_border, UIWidget};
-
-use super::TargetMonitor;
+use crate::{
+    target_monitor::TargetMonitor,
+    ui::{draw_ui_widget_border, UIWidget},
+};
 
 pub struct UITargetDeviceList {
     selected_dev_mac: MacAddress,
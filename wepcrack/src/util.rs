@@ -1,6 +1,9 @@
-use std::sync::{
-    atomic::{self, AtomicBool},
-    LockResult, Mutex, MutexGuard,
+use std::{
+    io::Read,
+    sync::{
+        atomic::{self, AtomicBool},
+        LockResult, Mutex, MutexGuard,
+    },
 };
 
 pub struct DropGuard<T: FnOnce()>(Option<T>);
@@ -56,3 +59,26 @@ impl<T> RecessiveMutex<T> {
         self.mutex.lock()
     }
 }
+
+pub fn read_exact_or_eof(reader: &mut impl Read, buf: &mut [u8]) -> std::io::Result<bool> {
+    //Distinguish between a clean EOF and a truncated read
+    let mut off = 0;
+    while off < buf.len() {
+        match reader.read(&mut buf[off..]) {
+            Ok(0) if off == 0 => return Ok(false),
+            Ok(0) => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => off += n,
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(true)
+}
+
+pub fn read_exact_or_truncated(reader: &mut impl Read, buf: &mut [u8]) -> std::io::Result<bool> {
+    //Treat records cut off by the end of the file (e.g. by an interrupted capture) like a clean EOF
+    match read_exact_or_eof(reader, buf) {
+        Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+        res => res,
+    }
+}
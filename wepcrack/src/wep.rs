@@ -1,30 +1,385 @@
-use crate::rc4::RC4Cipher;
+use std::{path::Path, str::FromStr};
+
+use anyhow::Context;
+use hex::FromHex;
+use ieee80211::{DSStatus, DataFrame, DataSubtype, FrameSubtype, FrameTrait, MacAddress};
+use serde::{Deserialize, Serialize};
+
+use crate::{crc32::crc32, rc4::RC4Cipher};
 
 pub type WepIV = [u8; 3];
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum WepKey {
     Wep40Key([u8; 5]),
     Wep104Key([u8; 13]),
+    Wep128Key([u8; 16]),
+    Wep232Key([u8; 29]),
 }
 
 impl WepKey {
     pub const LEN_40: usize = 5;
     pub const LEN_104: usize = 13;
+    pub const LEN_128: usize = 16;
+    pub const LEN_232: usize = 29;
+    pub const LEN_MAX: usize = WepKey::LEN_232;
 
-    pub fn create_rc4(&self, iv: &WepIV) -> RC4Cipher {
+    //All supported key lengths, in ascending order
+    pub const KEY_LENGTHS: [usize; 4] = [
+        WepKey::LEN_40,
+        WepKey::LEN_104,
+        WepKey::LEN_128,
+        WepKey::LEN_232,
+    ];
+
+    pub fn from_slice(key: &[u8]) -> Option<WepKey> {
+        Some(match key.len() {
+            WepKey::LEN_40 => WepKey::Wep40Key(key.try_into().unwrap()),
+            WepKey::LEN_104 => WepKey::Wep104Key(key.try_into().unwrap()),
+            WepKey::LEN_128 => WepKey::Wep128Key(key.try_into().unwrap()),
+            WepKey::LEN_232 => WepKey::Wep232Key(key.try_into().unwrap()),
+            _ => return None,
+        })
+    }
+
+    pub fn key(&self) -> &[u8] {
         match self {
-            Self::Wep40Key(wep_key) => {
-                let mut rc4_key = [0u8; 8];
-                rc4_key[..3].copy_from_slice(iv);
-                rc4_key[3..].copy_from_slice(wep_key);
-                RC4Cipher::from_key(&rc4_key)
-            }
-            Self::Wep104Key(wep_key) => {
-                let mut rc4_key = [0u8; 16];
-                rc4_key[..3].copy_from_slice(iv);
-                rc4_key[3..].copy_from_slice(wep_key);
-                RC4Cipher::from_key(&rc4_key)
-            }
+            Self::Wep40Key(wep_key) => wep_key,
+            Self::Wep104Key(wep_key) => wep_key,
+            Self::Wep128Key(wep_key) => wep_key,
+            Self::Wep232Key(wep_key) => wep_key,
         }
     }
+
+    //The size of the secret part of the key in bits (e.g. 104 for "128-bit" WEP)
+    pub fn bits(&self) -> usize {
+        self.key().len() * 8
+    }
+
+    pub fn create_rc4(&self, iv: &WepIV) -> RC4Cipher {
+        let key = self.key();
+
+        let mut rc4_key = [0u8; 3 + WepKey::LEN_MAX];
+        rc4_key[..3].copy_from_slice(iv);
+        rc4_key[3..3 + key.len()].copy_from_slice(key);
+        RC4Cipher::from_key(&rc4_key[..3 + key.len()])
+    }
+}
+
+impl FromStr for WepKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.len() {
+            10 => Ok(WepKey::Wep40Key(<[u8; WepKey::LEN_40]>::from_hex(s)?)),
+            26 => Ok(WepKey::Wep104Key(<[u8; WepKey::LEN_104]>::from_hex(s)?)),
+            32 => Ok(WepKey::Wep128Key(<[u8; WepKey::LEN_128]>::from_hex(s)?)),
+            58 => Ok(WepKey::Wep232Key(<[u8; WepKey::LEN_232]>::from_hex(s)?)),
+            _ => anyhow::bail!("invalid WEP key length"),
+        }
+    }
+}
+
+pub fn random_iv() -> WepIV {
+    rand::random()
+}
+
+//A keystream recovered for a specific IV, which can be used to encrypt / decrypt frames without knowing the key
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WepKeystream {
+    pub iv: WepIV,
+
+    #[serde(with = "hex")]
+    pub keystream: Vec<u8>,
+}
+
+impl WepKeystream {
+    pub fn from_key(key: &WepKey, iv: &WepIV, len: usize) -> WepKeystream {
+        let mut keystream = vec![0u8; len];
+        key.create_rc4(iv).gen_keystream(&mut keystream);
+
+        WepKeystream { iv: *iv, keystream }
+    }
+
+    //Keystream files use the same format as aircrack-ng's PRGA (.xor) files: the IV header, followed by the keystream
+    pub fn load(path: &Path) -> anyhow::Result<WepKeystream> {
+        let data = std::fs::read(path)
+            .with_context(|| format!("failed to read keystream file {}", path.display()))?;
+        if data.len() <= 4 {
+            anyhow::bail!("keystream file {} is too short", path.display());
+        }
+
+        //The IV header ends with the key index in the top two bits, the rest is padding
+        if data[3] & 0x3f != 0 {
+            anyhow::bail!("keystream file {} has an invalid IV header", path.display());
+        }
+
+        Ok(WepKeystream {
+            iv: [data[0], data[1], data[2]],
+            keystream: data[4..].to_vec(),
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        //Keystreams don't keep track of their key index, so always write the default one
+        let mut data = Vec::with_capacity(4 + self.keystream.len());
+        data.extend_from_slice(&self.iv);
+        data.push(0);
+        data.extend_from_slice(&self.keystream);
+
+        std::fs::write(path, data)
+            .with_context(|| format!("failed to write keystream file {}", path.display()))
+    }
+
+    //The ICV has to be encrypted as well, so the keystream has to cover it too
+    pub fn max_plaintext_len(&self) -> usize {
+        self.keystream.len().saturating_sub(WepFrame::ICV_SIZE)
+    }
+}
+
+//The WEP encrypted body of a protected data frame
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WepFrame {
+    pub iv: WepIV,
+    pub key_idx: u8,
+
+    //The encrypted payload, followed by the encrypted ICV
+    #[serde(
+        serialize_with = "hex::serialize",
+        deserialize_with = "deserialize_body"
+    )]
+    pub body: Vec<u8>,
+}
+
+fn deserialize_body<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<u8>, D::Error> {
+    //The body has to at least contain the ICV, as it gets split off unchecked
+    let body: Vec<u8> = hex::deserialize(deserializer)?;
+    if body.len() < WepFrame::ICV_SIZE {
+        return Err(serde::de::Error::invalid_length(
+            body.len(),
+            &"a WEP frame body including the ICV",
+        ));
+    }
+    Ok(body)
+}
+
+impl WepFrame {
+    pub const ICV_SIZE: usize = 4;
+
+    pub fn from_data_frame(data: &DataFrame, has_fcs: bool) -> Option<WepFrame> {
+        //Frames between access points have a fourth address we don't handle
+        if matches!(data.ds_status(), DSStatus::WDSOrMesh) {
+            return None;
+        }
+
+        //Skip the 802.11 header
+        let mut index = DataFrame::FRAGMENT_SEQUENCE_START + 2;
+        if matches!(data.subtype(), FrameSubtype::Data(DataSubtype::QoSData)) {
+            index += 2;
+        }
+
+        let fcs_len = if has_fcs { 4 } else { 0 };
+        if data.bytes().len() < index + 4 + WepFrame::ICV_SIZE + fcs_len {
+            return None;
+        }
+
+        //Parse the IV header, and take the rest of the frame up to the FCS as the body
+        let header = &data.bytes()[index..index + 4];
+        Some(WepFrame {
+            iv: [header[0], header[1], header[2]],
+            key_idx: header[3] >> 6,
+            body: data.bytes()[index + 4..data.bytes().len() - fcs_len].to_vec(),
+        })
+    }
+
+    pub fn encrypt(key: &WepKey, iv: &WepIV, key_idx: u8, plaintext: &[u8]) -> WepFrame {
+        let keystream = WepKeystream::from_key(key, iv, plaintext.len() + WepFrame::ICV_SIZE);
+        WepFrame::encrypt_with_keystream(&keystream, key_idx, plaintext).unwrap()
+    }
+
+    pub fn encrypt_with_keystream(
+        keystream: &WepKeystream,
+        key_idx: u8,
+        plaintext: &[u8],
+    ) -> Option<WepFrame> {
+        if plaintext.len() > keystream.max_plaintext_len() {
+            return None;
+        }
+
+        //Append the ICV, and XOR everything with the keystream
+        let mut body = Vec::with_capacity(plaintext.len() + WepFrame::ICV_SIZE);
+        body.extend_from_slice(plaintext);
+        body.extend_from_slice(&crc32(plaintext).to_le_bytes());
+
+        for (b, keystream_byte) in body.iter_mut().zip(&keystream.keystream) {
+            *b ^= keystream_byte;
+        }
+
+        Some(WepFrame {
+            iv: keystream.iv,
+            key_idx,
+            body,
+        })
+    }
+
+    pub fn iv_header(&self) -> [u8; 4] {
+        [self.iv[0], self.iv[1], self.iv[2], self.key_idx << 6]
+    }
+
+    pub fn build_data_frame(
+        &self,
+        ds_status: DSStatus,
+        bssid: &MacAddress,
+        source: &MacAddress,
+        destination: &MacAddress,
+    ) -> Option<Vec<u8>> {
+        //Determine the DS flags and address order
+        let (ds_flags, addrs) = match ds_status {
+            DSStatus::NotLeavingDSOrADHOC => (0x00, [destination, source, bssid]),
+            DSStatus::FromSTAToDS => (0x01, [bssid, source, destination]),
+            DSStatus::FromDSToSTA => (0x02, [destination, bssid, source]),
+            DSStatus::WDSOrMesh => return None,
+        };
+
+        //Build the header of a plain data frame with the Protected bit set
+        let mut frame =
+            Vec::with_capacity(DataFrame::FRAGMENT_SEQUENCE_START + 2 + 4 + self.body.len());
+        frame.extend_from_slice(&[0x08, ds_flags | 0x40]); //Frame control
+        frame.extend_from_slice(&[0, 0]); //Duration
+        for addr in addrs {
+            frame.extend_from_slice(addr.as_bytes());
+        }
+        frame.extend_from_slice(&[0, 0]); //Sequence control
+
+        //Append the IV header and the encrypted body
+        frame.extend_from_slice(&self.iv_header());
+        frame.extend_from_slice(&self.body);
+
+        Some(frame)
+    }
+
+    pub fn encrypted_payload(&self) -> &[u8] {
+        &self.body[..self.body.len() - WepFrame::ICV_SIZE]
+    }
+
+    pub fn encrypted_icv(&self) -> &[u8] {
+        &self.body[self.body.len() - WepFrame::ICV_SIZE..]
+    }
+
+    pub fn decrypt(&self, key: &WepKey) -> Option<Vec<u8>> {
+        self.decrypt_with_keystream(&WepKeystream::from_key(key, &self.iv, self.body.len()))
+    }
+
+    pub fn decrypt_with_keystream(&self, keystream: &WepKeystream) -> Option<Vec<u8>> {
+        if keystream.iv != self.iv || keystream.keystream.len() < self.body.len() {
+            return None;
+        }
+
+        let mut plaintext = self.body.clone();
+        for (b, keystream_byte) in plaintext.iter_mut().zip(&keystream.keystream) {
+            *b ^= keystream_byte;
+        }
+
+        //Only accept the plaintext if the ICV matches
+        let icv = plaintext.split_off(plaintext.len() - WepFrame::ICV_SIZE);
+        if crc32(&plaintext).to_le_bytes()[..] != icv[..] {
+            return None;
+        }
+
+        Some(plaintext)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wep_roundtrip() {
+        let key: WepKey = "0123456789abcdef0123456789".parse().unwrap();
+        let iv = [0x12, 0x34, 0x56];
+        let plaintext = b"\xaa\xaa\x03\x00\x00\x00\x08\x06 forged ARP packet";
+
+        //Encrypting with the key and with its keystream has to produce the same frame
+        let frame = WepFrame::encrypt(&key, &iv, 1, plaintext);
+        assert_eq!(frame.body.len(), plaintext.len() + WepFrame::ICV_SIZE);
+        assert_eq!(frame.iv_header(), [0x12, 0x34, 0x56, 0x40]);
+
+        let keystream = WepKeystream::from_key(&key, &iv, 64);
+        let ks_frame = WepFrame::encrypt_with_keystream(&keystream, 1, plaintext).unwrap();
+        assert_eq!(ks_frame.body, frame.body);
+
+        assert_eq!(frame.decrypt(&key).unwrap(), plaintext);
+        assert_eq!(frame.decrypt_with_keystream(&keystream).unwrap(), plaintext);
+
+        //Keystreams which are too short can't be used
+        let short_keystream = WepKeystream::from_key(&key, &iv, plaintext.len());
+        assert!(WepFrame::encrypt_with_keystream(&short_keystream, 1, plaintext).is_none());
+
+        //Any modification has to be detected by the ICV
+        let mut tampered = frame.clone();
+        tampered.body[3] ^= 1;
+        assert!(tampered.decrypt(&key).is_none());
+
+        //Frames without an ICV are rejected when loading them
+        let json = serde_json::to_string(&frame).unwrap();
+        let restored: WepFrame = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.body, frame.body);
+        assert!(
+            serde_json::from_str::<WepFrame>(r#"{"iv":[1,2,3],"key_idx":0,"body":"aabbcc"}"#)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_wep_data_frame() {
+        let key: WepKey = "0102030405".parse().unwrap();
+        let frame = WepFrame::encrypt(&key, &[1, 2, 3], 0, b"payload");
+
+        let bssid = MacAddress::new([0x02, 0, 0, 0, 0, 1]);
+        let source = MacAddress::new([0x02, 0, 0, 0, 0, 2]);
+        let destination = MacAddress::new([0xff; 6]);
+
+        let data = frame
+            .build_data_frame(DSStatus::FromSTAToDS, &bssid, &source, &destination)
+            .unwrap();
+        assert_eq!(data[..2], [0x08, 0x41]);
+        assert_eq!(data[4..10], *bssid.as_bytes());
+        assert_eq!(data[10..16], *source.as_bytes());
+        assert_eq!(data[16..22], *destination.as_bytes());
+
+        //Parsing the frame has to give back the same WEP body
+        let parsed = WepFrame::from_data_frame(&DataFrame::new(&data[..]), false).unwrap();
+        assert_eq!(parsed.iv, frame.iv);
+        assert_eq!(parsed.key_idx, frame.key_idx);
+        assert_eq!(parsed.decrypt(&key).unwrap(), b"payload");
+    }
+
+    #[test]
+    fn test_keystream_file() {
+        let path = std::env::temp_dir().join(format!("wepcrack-test-{}.xor", std::process::id()));
+
+        //aircrack-ng's .xor files start with the IV header (IV + key index byte)
+        let keystream = WepKeystream {
+            iv: [0x12, 0x34, 0x56],
+            keystream: vec![0xaa, 0xbb, 0xcc, 0xdd],
+        };
+        keystream.save(&path).unwrap();
+        assert_eq!(
+            std::fs::read(&path).unwrap(),
+            [0x12, 0x34, 0x56, 0x00, 0xaa, 0xbb, 0xcc, 0xdd]
+        );
+        assert_eq!(WepKeystream::load(&path).unwrap(), keystream);
+
+        //Files for other key indices can be loaded as well
+        std::fs::write(&path, [0x12, 0x34, 0x56, 0x80, 0xaa, 0xbb, 0xcc, 0xdd]).unwrap();
+        assert_eq!(WepKeystream::load(&path).unwrap(), keystream);
+
+        std::fs::write(&path, [0x12, 0x34, 0x56, 0xaa, 0xbb, 0xcc, 0xdd]).unwrap();
+        assert!(WepKeystream::load(&path).is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
 }